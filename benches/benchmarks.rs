@@ -10,7 +10,7 @@ use snork::env::*;
 use snork::floodfill::FloodFill;
 use snork::game::{Game, Outcome, Snake};
 use snork::logging;
-use snork::search::{self, Heuristic};
+use snork::search::{self, Heuristic, MoveOrder, SearchLimit, SearchMode, TranspositionTable};
 
 #[derive(Debug, Clone, Default)]
 struct TestH;
@@ -116,7 +116,7 @@ fn normal_max_n(c: &mut Criterion) {
     let game = Game::new(0, 11, 11, snakes, &[], &[]);
 
     c.bench_function("normal_max_n", |b| {
-        b.iter(|| search::max_n(black_box(&game), 2, &TestH))
+        b.iter(|| search::max_n(black_box(&game), 2, &TestH, SearchMode::Paranoid, None))
     });
 }
 
@@ -132,10 +132,45 @@ fn async_max_n(c: &mut Criterion) {
 
     c.bench_function("async_max_n", |b| {
         b.to_async(tokio::runtime::Runtime::new().unwrap())
-            .iter(|| search::async_max_n(black_box(&game), 2, Arc::new(TestH)))
+            .iter(|| {
+                search::async_max_n(
+                    black_box(&game),
+                    Arc::new(SearchLimit::depth(2)),
+                    Arc::new(TestH),
+                )
+            })
     });
 }
 
+fn shallow_max_n(c: &mut Criterion) {
+    logging();
+    let snakes = vec![
+        Snake::new(vec![v2(0, 3), v2(1, 3), v2(2, 3), v2(3, 3)].into(), 100),
+        Snake::new(vec![v2(3, 7), v2(3, 6), v2(3, 5)].into(), 100),
+        Snake::new(vec![v2(10, 7), v2(10, 6), v2(10, 5)].into(), 100),
+        Snake::new(vec![v2(10, 0), v2(9, 0), v2(8, 0)].into(), 100),
+    ];
+    let game = Game::new(0, 11, 11, snakes, &[], &[]);
+
+    let mut group = c.benchmark_group("shallow_max_n");
+    for depth in 1..=maxn::SYNC_DEPTH {
+        group.bench_with_input(BenchmarkId::new("sync", depth), &depth, |b, &depth| {
+            b.iter(|| search::max_n(black_box(&game), depth, &TestH, SearchMode::Paranoid, None))
+        });
+        group.bench_with_input(BenchmarkId::new("async", depth), &depth, |b, &depth| {
+            b.to_async(tokio::runtime::Runtime::new().unwrap())
+                .iter(|| {
+                    search::async_max_n(
+                        black_box(&game),
+                        Arc::new(SearchLimit::depth(depth)),
+                        Arc::new(TestH),
+                    )
+                })
+        });
+    }
+    group.finish();
+}
+
 fn normal_alphabeta(c: &mut Criterion) {
     logging();
     let snakes = vec![
@@ -145,7 +180,27 @@ fn normal_alphabeta(c: &mut Criterion) {
     let game = Game::new(0, 11, 11, snakes, &[], &[]);
 
     c.bench_function("normal_alphabeta", |b| {
-        b.iter(|| search::alphabeta(black_box(&game), 5, &TestH))
+        b.iter(|| {
+            search::alphabeta(
+                black_box(&game),
+                &SearchLimit::depth(5),
+                &TestH,
+                MoveOrder::None,
+                None,
+            )
+        })
+    });
+    c.bench_function("normal_alphabeta_ordered", |b| {
+        b.iter(|| {
+            let limit = SearchLimit::depth(5);
+            search::alphabeta(black_box(&game), &limit, &TestH, MoveOrder::Space, None)
+        })
+    });
+    c.bench_function("normal_alphabeta_killer", |b| {
+        b.iter(|| {
+            let limit = SearchLimit::depth(5);
+            search::alphabeta(black_box(&game), &limit, &TestH, MoveOrder::Killer, None)
+        })
     });
 }
 
@@ -170,12 +225,42 @@ fn floodfill_normal(c: &mut Criterion) {
         ).unwrap();
 
     let game = Game::from_request(&request);
-    let heuristic = TestH::default();
+    let heuristic = TestH;
     c.bench_function("floodfill_normal", |b| {
         b.iter(|| heuristic.eval(black_box(&game)))
     });
 }
 
+fn floodfill_counts(c: &mut Criterion) {
+    logging();
+    let snakes = vec![
+        Snake::new(vec![v2(0, 3), v2(1, 3), v2(2, 3), v2(3, 3)].into(), 100),
+        Snake::new(vec![v2(3, 7), v2(3, 6), v2(3, 5)].into(), 100),
+        Snake::new(vec![v2(10, 7), v2(10, 6), v2(10, 5)].into(), 100),
+        Snake::new(vec![v2(10, 0), v2(9, 0), v2(8, 0)].into(), 100),
+    ];
+    let game = Game::new(0, 11, 11, snakes, &[v2(5, 5)], &[]);
+    let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+    flood_fill.flood_snakes(&game.grid, &game.snakes);
+
+    let mut group = c.benchmark_group("floodfill_counts");
+    group.bench_function("maintained", |b| {
+        b.iter(|| {
+            (0..4)
+                .map(|i| black_box(&flood_fill).count_space(i) + flood_fill.count_health(i))
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("scan", |b| {
+        b.iter(|| {
+            (0..4)
+                .map(|i| black_box(&flood_fill).scan_space(i) + flood_fill.scan_health(i))
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
 fn tree_heuristic(c: &mut Criterion) {
     logging();
     let request: GameRequest = serde_json::from_str(
@@ -201,7 +286,13 @@ fn tree_search(c: &mut Criterion) {
 
     c.bench_function("tree_search", |b| {
         b.to_async(tokio::runtime::Runtime::new().unwrap())
-            .iter(|| maxn::tree_search(heuristic.clone(), black_box(&game), 3))
+            .iter(|| {
+                maxn::tree_search(
+                    heuristic.clone(),
+                    black_box(&game),
+                    Arc::new(SearchLimit::depth(3)),
+                )
+            })
     });
 }
 
@@ -230,23 +321,62 @@ fn flood_search(c: &mut Criterion) {
 
     c.bench_function("flood_search", |b| {
         b.to_async(tokio::runtime::Runtime::new().unwrap())
-            .iter(|| maxn::tree_search(heuristic.clone(), black_box(&game), 3))
+            .iter(|| {
+                maxn::tree_search(
+                    heuristic.clone(),
+                    black_box(&game),
+                    Arc::new(SearchLimit::depth(3)),
+                )
+            })
     });
 }
 
-fn flood_2_search(c: &mut Criterion) {
-    logging();
+fn flood_2_game() -> Game {
     let request: GameRequest = serde_json::from_str(
             r#"{"game":{"id":"17d30fe5-a90f-45c0-bb81-1f8bd54781e1","ruleset":{"damagePerTurn":"14","foodSpawnChance":"15","minimumFood":"1","name":"royale","shrinkEveryNTurns":"25"},"timeout":500},"turn":64,"board":{"width":11,"height":11,"food":[{"x":10,"y":7}],"hazards":[{"x":0,"y":0},{"x":0,"y":1},{"x":0,"y":2},{"x":0,"y":3},{"x":0,"y":4},{"x":0,"y":5},{"x":0,"y":6},{"x":0,"y":7},{"x":0,"y":8},{"x":0,"y":9},{"x":0,"y":10},{"x":1,"y":0},{"x":2,"y":0},{"x":3,"y":0},{"x":4,"y":0},{"x":5,"y":0},{"x":6,"y":0},{"x":7,"y":0},{"x":8,"y":0},{"x":9,"y":0},{"x":10,"y":0}],"snakes":[{"id":"gs_c6BKHbpSr47cqd76mmWTj7dB","name":"unsigned long long","body":[{"x":5,"y":7},{"x":5,"y":6},{"x":5,"y":5},{"x":4,"y":5},{"x":3,"y":5},{"x":2,"y":5}],"health":93,"latency":471,"head":{"x":5,"y":7},"length":6,"shout":"","squad":""},{"id":"gs_BWkm6pVmC6kTmYShrGTrRHfW","name":"marrrvin","body":[{"x":4,"y":4},{"x":3,"y":4},{"x":3,"y":3},{"x":2,"y":3},{"x":1,"y":3}],"health":56,"latency":25,"head":{"x":4,"y":4},"length":5,"shout":"","squad":""}]},"you":{"id":"gs_c6BKHbpSr47cqd76mmWTj7dB","name":"unsigned long long","body":[{"x":5,"y":7},{"x":5,"y":6},{"x":5,"y":5},{"x":4,"y":5},{"x":3,"y":5},{"x":2,"y":5}],"health":93,"latency":471,"head":{"x":5,"y":7},"length":6,"shout":"","squad":""}}"#
         ).unwrap();
+    Game::from_request(&request)
+}
 
-    let game = Game::from_request(&request);
+fn flood_2_search(c: &mut Criterion) {
+    logging();
+    let game = flood_2_game();
     let heuristic = Arc::new(FloodHeuristic::default());
 
     c.bench_function("flood_2_search", |b| {
         b.to_async(tokio::runtime::Runtime::new().unwrap())
-            .iter(|| maxn::tree_search(heuristic.clone(), black_box(&game), 6))
+            .iter(|| {
+                maxn::tree_search(
+                    heuristic.clone(),
+                    black_box(&game),
+                    Arc::new(SearchLimit::depth(6)),
+                )
+            })
+    });
+}
+
+fn flood_2_transposition(c: &mut Criterion) {
+    logging();
+    let game = flood_2_game();
+    let heuristic = FloodHeuristic::default();
+
+    let mut group = c.benchmark_group("flood_2_transposition");
+    group.bench_function("uncached", |b| {
+        b.iter(|| search::max_n(black_box(&game), 3, &heuristic, SearchMode::Paranoid, None))
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            let mut tt = TranspositionTable::new();
+            search::max_n(
+                black_box(&game),
+                3,
+                &heuristic,
+                SearchMode::Paranoid,
+                Some(&mut tt),
+            )
+        })
     });
+    group.finish();
 }
 
 fn mobility_agent(c: &mut Criterion) {
@@ -298,14 +428,17 @@ criterion_group!(
     game_step_random,
     async_max_n,
     normal_max_n,
+    shallow_max_n,
     async_alphabeta,
     normal_alphabeta,
     floodfill_normal,
+    floodfill_counts,
     tree_heuristic,
     tree_search,
     flood_heuristic,
     flood_search,
     flood_2_search,
+    flood_2_transposition,
     mobility_agent,
     math,
 );
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use async_recursion::async_recursion;
+use log::info;
+use tokio::task::JoinSet;
+
+use super::{Agent, AgentInstance, RandomAgent};
+use crate::env::{Direction, MoveResponse};
+use crate::game::Game;
+
+/// Agent of an ensemble, whose vote counts `weight` times.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Member {
+    pub weight: f64,
+    pub agent: Agent,
+}
+
+impl Default for Member {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            agent: Agent::default(),
+        }
+    }
+}
+
+impl From<Agent> for Member {
+    fn from(agent: Agent) -> Self {
+        Self {
+            agent,
+            ..Default::default()
+        }
+    }
+}
+
+/// Runs all agents in parallel with the same timeout and picks the move
+/// with the highest sum of weights.
+///
+/// Ties are broken in favor of the agent listed first.
+/// If none of the agents returns a move, the `fallback` plays.
+#[async_recursion]
+pub async fn step(
+    agents: &[(f64, Arc<AgentInstance>)],
+    fallback: &RandomAgent,
+    timeout: u64,
+    max_board_size: usize,
+    game: &Game,
+) -> MoveResponse {
+    let mut set = JoinSet::new();
+    for (i, (_, agent)) in agents.iter().enumerate() {
+        let agent = agent.clone();
+        let game = game.clone();
+        set.spawn(async move {
+            let response = agent.step_internal(timeout, max_board_size, &game).await;
+            (i, response.r#move)
+        });
+    }
+
+    let mut moves = Vec::with_capacity(agents.len());
+    while let Some(r) = set.join_next().await {
+        if let Ok(r) = r {
+            moves.push(r);
+        }
+    }
+    moves.sort_unstable_by_key(|&(i, _)| i);
+    info!("ensemble {moves:?}");
+
+    let mut votes = [0.0; 4];
+    for &(i, d) in &moves {
+        votes[d as usize] += agents[i].0;
+    }
+
+    let mut best: Option<Direction> = None;
+    for &(_, d) in &moves {
+        if best.is_none_or(|best| votes[d as usize] > votes[best as usize]) {
+            best = Some(d);
+        }
+    }
+
+    match best {
+        Some(d) => MoveResponse::new(d),
+        None => fallback.step(game).await,
+    }
+}
@@ -1,4 +1,5 @@
-use crate::floodfill::FloodFill;
+use crate::env::Direction;
+use crate::floodfill::{owned_food_score, FloodFill};
 use crate::game::Game;
 use crate::search::{self, Heuristic};
 
@@ -10,30 +11,72 @@ pub struct FloodHeuristic {
     food_distance: f64,
     space: f64,
     space_adv: f64,
+    /// Exponent of the space advantage, higher values reward a lead more
+    /// sharply (1 is linear).
+    space_adv_power: i32,
     size_adv: f64,
     size_adv_decay: f64,
+    /// Bonus for threatening shorter enemy heads next to ours,
+    /// penalty for longer or equally long ones.
+    aggression: f64,
+    /// Penalty if the space we can reach is smaller than our body,
+    /// scaled by the missing space.
+    trap_penalty: f64,
+    /// Bonus for space close to our head, which is easier to defend than
+    /// far-flung territory.
+    compactness: f64,
+    /// Bonus if our tail is reachable from the head through free cells,
+    /// which keeps an escape route open.
+    tail_reachable: f64,
+    /// Number of turns at the start of the game, where the greedy food and
+    /// space policy is used instead of the search.
+    pub opening_turns: usize,
+    /// Shout the reached search depth and value.
+    pub shout: bool,
+    /// Margin of the aspiration windows in duels, disabled if zero.
+    pub aspiration: f64,
+    /// Number of turns the search is extended beyond its depth if heads
+    /// are about to collide, disabled if zero.
+    pub max_extension: usize,
+    /// Keep the transposition table of duels between turns.
+    pub persistent_tt: bool,
 }
 
 impl Default for FloodHeuristic {
     fn default() -> Self {
         Self {
             health: 0.00044,
-            food_distance: 0.173,
+            food_distance: 0.61,
             space: 0.0026,
             space_adv: 0.108,
+            space_adv_power: 3,
             size_adv: 7.049,
             size_adv_decay: 0.041,
+            aggression: 0.0,
+            trap_penalty: 0.0,
+            compactness: 0.0,
+            tail_reachable: 0.0,
+            opening_turns: 0,
+            shout: false,
+            aspiration: 0.0,
+            max_extension: 0,
+            persistent_tt: false,
         }
     }
 }
 
-impl Heuristic for FloodHeuristic {
-    fn eval(&self, game: &Game) -> f64 {
+impl FloodHeuristic {
+    /// Individual weighted terms of the evaluation, which sum up to `eval`:
+    /// health, space advantage, space, size advantage, aggression, trap penalty,
+    /// compactness and tail reachability.
+    ///
+    /// If we are dead, the first term is a loss and the others are zero.
+    pub fn eval_components(&self, game: &Game) -> [f64; 8] {
         if game.snake_is_alive(0) {
             let own_len = game.snakes[0].body.len() as f64;
             let area = (game.grid.width * game.grid.height) as f64;
 
-            let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+            let mut flood_fill = FloodFill::for_game(game);
             let food_distances = flood_fill.flood_snakes(&game.grid, &game.snakes);
 
             // Health is more important if we have not much
@@ -51,18 +94,15 @@ impl Heuristic for FloodHeuristic {
                 .max_by_key(|(_, s)| s.body.len())
             {
                 // Distance to the nearest four food cells
-                let food_distance: f64 = food_distances
-                    .iter()
-                    .map(|&d| (area - d as f64) / area)
-                    .sum();
+                let food_distance = owned_food_score(&food_distances);
                 let enemy_len = longest_enemy.body.len() as f64;
                 // Sqrt because if we are larger we do not have to as grow much anymore.
                 let size_adv = ((own_len + food_distance * self.food_distance) / enemy_len).sqrt();
 
                 let enemy_space = flood_fill.count_health(i as _) as f64;
                 let space_adv = if space > 0.0 {
-                    // x^3 so that the effect is stronger when the value is higher.
-                    (space / (enemy_space + space)).powi(3)
+                    // x^n so that the effect is stronger when the value is higher.
+                    (space / (enemy_space + space)).powi(self.space_adv_power)
                 } else {
                     0.0
                 };
@@ -73,12 +113,378 @@ impl Heuristic for FloodHeuristic {
 
             let space = (space / (area * 100.0)).sqrt();
 
-            self.health * health
-                + self.space_adv * space_adv
-                + self.space * space
-                + self.size_adv * size_adv * (-(game.turn as f64) * self.size_adv_decay).exp2()
+            // Potential head to head kills (or deaths)
+            let head = game.snakes[0].head();
+            let aggression: f64 = game.snakes[1..]
+                .iter()
+                .filter(|s| {
+                    s.alive()
+                        && Direction::all()
+                            .iter()
+                            .any(|&d| game.apply(head, d) == s.head())
+                })
+                .map(|s| {
+                    if s.body.len() < own_len as usize {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                })
+                .sum();
+
+            // Survival floor, if we are about to trap ourselves
+            let trapped = if self.trap_penalty != 0.0 {
+                let pocket = Direction::all()
+                    .map(|d| flood_fill.pocket_size(&game.grid, &game.snakes, game.apply(head, d)))
+                    .into_iter()
+                    .max()
+                    .unwrap_or(0) as f64;
+                ((own_len - pocket) / own_len).max(0.0)
+            } else {
+                0.0
+            };
+
+            // Space weighted by the distance to our head
+            let compactness = if self.compactness != 0.0 {
+                flood_fill.count_space_weighted(0) / area
+            } else {
+                0.0
+            };
+
+            // Escape route along our own tail
+            let tail_reachable = if self.tail_reachable != 0.0 {
+                let grid = &game.grid;
+                let distances = grid.bfs_distances(head);
+                let tail = game.snakes[0].body[0];
+                Direction::all().into_iter().any(|d| {
+                    let p = game.apply(tail, d);
+                    grid.has(p) && distances[p.x as usize + p.y as usize * grid.width].is_some()
+                }) as u8 as f64
+            } else {
+                0.0
+            };
+
+            [
+                self.health * health,
+                self.space_adv * space_adv,
+                self.space * space,
+                self.size_adv * size_adv * (-(game.turn as f64) * self.size_adv_decay).exp2(),
+                self.aggression * aggression,
+                -self.trap_penalty * trapped,
+                self.compactness * compactness,
+                self.tail_reachable * tail_reachable,
+            ]
         } else {
-            search::LOSS
+            [search::LOSS, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
         }
     }
 }
+
+impl Heuristic for FloodHeuristic {
+    fn eval(&self, game: &Game) -> f64 {
+        self.eval_components(game).iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn flood_aggression() {
+        use super::*;
+        logging();
+
+        let shorter = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . 1 < . .
+            . . . 0 < < <
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        let equal = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . 1 < < <
+            . . . 0 < < <
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let baseline = FloodHeuristic::default();
+        let aggressive = FloodHeuristic {
+            aggression: 1.0,
+            ..FloodHeuristic::default()
+        };
+
+        let (base, aggr) = (baseline.eval(&shorter), aggressive.eval(&shorter));
+        info!("shorter {base} {aggr}");
+        assert!(aggr > base);
+
+        let (base, aggr) = (baseline.eval(&equal), aggressive.eval(&equal));
+        info!("equal {base} {aggr}");
+        assert!(aggr < base);
+    }
+
+    #[test]
+    fn flood_compactness() {
+        use super::*;
+        logging();
+
+        let center = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 0 < < .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        let corner = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            0 < < . . . ."#,
+        )
+        .unwrap();
+
+        // Equal raw space, but closer to the head in the center
+        let space = |game: &Game| {
+            let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+            flood_fill.flood_snakes(&game.grid, &game.snakes);
+            (
+                flood_fill.count_space(0),
+                flood_fill.count_space_weighted(0),
+            )
+        };
+        let (center_space, center_weighted) = space(&center);
+        let (corner_space, corner_weighted) = space(&corner);
+        info!("center {center_space} {center_weighted}, corner {corner_space} {corner_weighted}");
+        assert_eq!(center_space, corner_space);
+        assert!(center_weighted > corner_weighted);
+
+        let baseline = FloodHeuristic::default();
+        let compact = FloodHeuristic {
+            compactness: 1.0,
+            ..FloodHeuristic::default()
+        };
+        let (base_center, base_corner) = (baseline.eval(&center), baseline.eval(&corner));
+        let (center, corner) = (compact.eval(&center), compact.eval(&corner));
+        info!("center {base_center} -> {center}, corner {base_corner} -> {corner}");
+        assert!(center - corner > base_center - base_corner);
+    }
+
+    #[test]
+    fn flood_tail_reachable() {
+        use super::*;
+        logging();
+
+        let reachable = Game::parse(
+            r#"
+            > > > > v . .
+            . . . . v . .
+            . . . . 0 . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        // The tail is boxed in by the coiled body
+        let coiled = Game::parse(
+            r#"
+            v > v . . . .
+            > ^ v . . . .
+            . . 0 . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(reachable.snakes[0].body.len(), coiled.snakes[0].body.len());
+
+        let baseline = FloodHeuristic::default();
+        let heuristic = FloodHeuristic {
+            tail_reachable: 1.0,
+            ..FloodHeuristic::default()
+        };
+        assert_eq!(baseline.eval_components(&reachable)[7], 0.0);
+        assert_eq!(heuristic.eval_components(&reachable)[7], 1.0);
+        assert_eq!(heuristic.eval_components(&coiled)[7], 0.0);
+
+        let base = baseline.eval(&reachable) - baseline.eval(&coiled);
+        let diff = heuristic.eval(&reachable) - heuristic.eval(&coiled);
+        info!("reachable - coiled: {base} -> {diff}");
+        assert!((diff - base - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flood_space_adv_power() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . 1 . .
+            . . . . ^ . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+        flood_fill.flood_snakes(&game.grid, &game.snakes);
+        let (space, enemy_space) = (flood_fill.count_health(0), flood_fill.count_health(1));
+        let ratio = space as f64 / (space + enemy_space) as f64;
+        info!("space {space} vs {enemy_space}: {ratio}");
+        assert!(ratio > 0.0 && ratio < 1.0);
+
+        let space_adv = |power| {
+            let heuristic = FloodHeuristic {
+                space_adv: 1.0,
+                space_adv_power: power,
+                ..FloodHeuristic::default()
+            };
+            heuristic.eval_components(&game)[1]
+        };
+        let (linear, cubed) = (space_adv(1), space_adv(3));
+        info!("linear {linear}, cubed {cubed}");
+        assert!((linear - ratio).abs() < 1e-9);
+        assert!((cubed - ratio.powi(3)).abs() < 1e-9);
+        assert!(cubed < linear);
+    }
+
+    #[test]
+    fn flood_trap_penalty() {
+        use super::*;
+        use crate::search::LOSS;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . > > v . . .
+            . ^ . v . . .
+            . ^ 0 < . . .
+            . ^ . . . . .
+            . ^ . . . . ."#,
+        )
+        .unwrap();
+
+        let baseline = FloodHeuristic::default();
+        let heuristic = FloodHeuristic {
+            trap_penalty: -LOSS,
+            ..FloodHeuristic::default()
+        };
+
+        // Up leads into a pocket that is too small
+        let mut trap = game.clone();
+        trap.step(&[Direction::Up]);
+        let mut open = game.clone();
+        open.step(&[Direction::Down]);
+
+        let (base_trap, base_open) = (baseline.eval(&trap), baseline.eval(&open));
+        let (trap, open) = (heuristic.eval(&trap), heuristic.eval(&open));
+        info!("trap {base_trap} -> {trap}, open {base_open} -> {open}");
+        assert!(trap < 0.9 * LOSS);
+        assert_eq!(open, base_open);
+    }
+
+    #[test]
+    fn flood_components() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . o . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let heuristic = FloodHeuristic {
+            aggression: 1.0,
+            trap_penalty: 1.0,
+            ..FloodHeuristic::default()
+        };
+        let components = heuristic.eval_components(&game);
+        let eval = heuristic.eval(&game);
+        info!("{components:?} -> {eval}");
+        assert_eq!(components.iter().sum::<f64>(), eval);
+    }
+
+    #[test]
+    fn flood_size_adv_decay() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . 1 . .
+            . . . . ^ . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.turn, 0);
+
+        // The turn advances with every step
+        let mut later = game.clone();
+        later.step(&[Direction::Up, Direction::Up]);
+        assert_eq!(later.turn, 1);
+        later.step(&[Direction::Up, Direction::Right]);
+        assert_eq!(later.turn, 2);
+        let request = later.to_request(later.turn, "standard");
+        assert_eq!(Game::from_request(&request).turn, 2);
+
+        // Same board at a later turn
+        let mut late = game.clone();
+        late.turn = 100;
+
+        let heuristic = FloodHeuristic::default();
+        let (early, late) = (
+            heuristic.eval_components(&game),
+            heuristic.eval_components(&late),
+        );
+        info!("{early:?} -> {late:?}");
+        assert!(early[3] > 0.0);
+        assert!(late[3] < early[3]);
+        assert_eq!(early[..3], late[..3]);
+
+        let constant = FloodHeuristic {
+            size_adv_decay: 0.0,
+            ..FloodHeuristic::default()
+        };
+        let mut late_game = game.clone();
+        late_game.turn = 100;
+        assert_eq!(constant.eval(&game), constant.eval(&late_game));
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use super::{safe_step, Agent, AgentInstance};
+use crate::env::{GameRequest, MoveResponse};
+use crate::game::Game;
+
+/// Instances older than this are evicted, in case the game did not end properly.
+pub const MAX_RUNTIME: Duration = Duration::from_secs(10 * 60);
+/// Maximum number of running games, the oldest instances are evicted first.
+pub const MAX_AGENT_COUNT: usize = 100;
+/// Time in ms the agent has to finish before the guard of a step falls back
+/// to a safe move, so that searches that run until their deadline are kept.
+pub const GUARD_SLACK: u64 = 20;
+/// Maximum time in ms spent on warming up a new game.
+pub const WARMUP_TIMEOUT: u64 = 200;
+
+/// Game and snake id.
+type GameKey = (String, String);
+
+/// State of a running game.
+struct Instance {
+    created: Instant,
+    agent: Arc<AgentInstance>,
+    /// Initialized with the configured latency on the first move.
+    latency: Arc<Mutex<Option<LatencyEstimate>>>,
+}
+
+/// Agents of the running games, keyed by the game and snake id.
+///
+/// The instances are warmed up on `start`, so that the first move is not
+/// cold, and they do not share their transposition tables between games.
+pub struct Instances {
+    games: Mutex<HashMap<GameKey, Instance>>,
+    max_runtime: Duration,
+    max_count: usize,
+}
+
+impl Default for Instances {
+    fn default() -> Self {
+        Self::new(MAX_RUNTIME, MAX_AGENT_COUNT)
+    }
+}
+
+impl Instances {
+    pub fn new(max_runtime: Duration, max_count: usize) -> Self {
+        Self {
+            games: Default::default(),
+            max_runtime,
+            max_count: max_count.max(1),
+        }
+    }
+
+    fn key(request: &GameRequest) -> GameKey {
+        (request.game.id.clone(), request.you.id.clone())
+    }
+
+    /// Number of running games.
+    pub fn len(&self) -> usize {
+        self.games.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the instance of the game, which is created from `config` if
+    /// the game has not been started.
+    pub fn get(&self, config: &Agent, request: &GameRequest) -> Arc<AgentInstance> {
+        self.instance(config, request).0
+    }
+
+    fn instance(
+        &self,
+        config: &Agent,
+        request: &GameRequest,
+    ) -> (Arc<AgentInstance>, Arc<Mutex<Option<LatencyEstimate>>>) {
+        let mut games = self.games.lock().unwrap();
+        let key = Self::key(request);
+        if let Some(instance) = games.get(&key) {
+            return (instance.agent.clone(), instance.latency.clone());
+        }
+
+        // Evict stale and, if there are still too many, the oldest instances
+        let now = Instant::now();
+        games.retain(|_, instance| now.duration_since(instance.created) < self.max_runtime);
+        while games.len() >= self.max_count {
+            let oldest = games
+                .iter()
+                .min_by_key(|(_, instance)| instance.created)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                games.remove(&oldest);
+            }
+        }
+
+        let instance = Instance {
+            created: now,
+            agent: Arc::new(config.instance_for_game(&request.game.id)),
+            latency: Default::default(),
+        };
+        let result = (instance.agent.clone(), instance.latency.clone());
+        games.insert(key, instance);
+        result
+    }
+
+    /// Creates the instance of a new game and warms it up by searching the
+    /// initial position for at most `WARMUP_TIMEOUT`.
+    pub async fn start(
+        &self,
+        config: &Agent,
+        request: &GameRequest,
+        latency: u64,
+        max_board_size: usize,
+    ) -> Arc<AgentInstance> {
+        info!(
+            "start {}x{} {}",
+            request.board.width, request.board.height, request.game.ruleset.name
+        );
+        let agent = self.get(config, request);
+        let game = Game::from_request(request);
+        let timeout = WARMUP_TIMEOUT.min(request.game.timeout.saturating_sub(latency));
+        let warmup = {
+            let (agent, game) = (agent.clone(), game.clone());
+            let budget = timeout.saturating_sub(GUARD_SLACK);
+            async move { agent.step_internal(budget, max_board_size, &game).await }
+        };
+        let response = safe_step(&game, timeout, warmup).await;
+        info!("warm up {response:?}");
+        agent
+    }
+
+    /// Computes the next move with the instance of the game.
+    /// A valid move is returned even if the agent fails or exceeds the timeout.
+    ///
+    /// The configured `latency` is the initial estimate, which is adapted
+    /// to the latencies reported by the game engine.
+    pub async fn step(
+        &self,
+        config: &Agent,
+        request: &GameRequest,
+        latency: u64,
+        max_board_size: usize,
+    ) -> MoveResponse {
+        self.guarded_step(
+            config,
+            request,
+            latency,
+            move |agent, request, latency| async move {
+                agent.step(&request, latency, max_board_size).await
+            },
+        )
+        .await
+    }
+
+    /// Runs `step` with the instance and the latency margin, falling back
+    /// to a safe move if it does not finish before the timeout minus the margin.
+    ///
+    /// The agent gets `GUARD_SLACK` less time than the guard,
+    /// which only catches real overruns.
+    async fn guarded_step<F, Fut>(
+        &self,
+        config: &Agent,
+        request: &GameRequest,
+        latency: u64,
+        step: F,
+    ) -> MoveResponse
+    where
+        F: FnOnce(Arc<AgentInstance>, GameRequest, u64) -> Fut,
+        Fut: Future<Output = MoveResponse> + Send + 'static,
+    {
+        let (agent, estimate) = self.instance(config, request);
+        let latency = {
+            let mut estimate = estimate.lock().unwrap();
+            let estimate = estimate.get_or_insert_with(|| LatencyEstimate::new(latency));
+            estimate.update(request.you.latency);
+            estimate.margin()
+        };
+
+        let timer = Instant::now();
+        let game = Game::from_request(request);
+        let timeout = request.game.timeout.saturating_sub(latency);
+        let response = safe_step(
+            &game,
+            timeout,
+            step(agent, request.clone(), latency + GUARD_SLACK),
+        )
+        .await;
+
+        if let Some(estimate) = estimate.lock().unwrap().as_mut() {
+            estimate.record_step(timer.elapsed().as_millis() as u64);
+        }
+        info!("latency {latency}ms");
+        response
+    }
+
+    /// Removes the instance of the finished game.
+    pub fn end(&self, request: &GameRequest) -> Option<Arc<AgentInstance>> {
+        let game = self.games.lock().unwrap().remove(&Self::key(request));
+        game.map(|instance| instance.agent)
+    }
+}
+
+/// Rolling estimate of the latency, the part of the round trip to the game
+/// engine that is not spent computing the move.
+///
+/// Similar to the retransmission timeout of TCP (RFC 6298),
+/// the safety margin is the smoothed latency plus four times its deviation,
+/// which quickly grows when the latency spikes.
+#[derive(Debug, Clone)]
+pub struct LatencyEstimate {
+    mean: f64,
+    deviation: f64,
+    /// Duration of the previous step in ms
+    last_step: Option<u64>,
+}
+
+impl LatencyEstimate {
+    pub fn new(initial: u64) -> Self {
+        Self {
+            mean: initial as f64,
+            deviation: 0.0,
+            last_step: None,
+        }
+    }
+
+    /// Records the duration of our step, which is part of the latency
+    /// reported for it.
+    pub fn record_step(&mut self, ms: u64) {
+        self.last_step = Some(ms);
+    }
+
+    /// Updates the estimate with the round trip time the game engine reported
+    /// for the previous step, zero if unknown.
+    pub fn update(&mut self, reported: u64) {
+        let Some(step) = self.last_step.take() else {
+            return;
+        };
+        if reported == 0 {
+            return;
+        }
+        let latency = reported.saturating_sub(step) as f64;
+        let error = latency - self.mean;
+        self.deviation += 0.25 * (error.abs() - self.deviation);
+        self.mean += 0.125 * error;
+    }
+
+    /// Safety margin in ms that is subtracted from the timeout.
+    pub fn margin(&self) -> u64 {
+        (self.mean + 4.0 * self.deviation).ceil() as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn instances() {
+        use super::*;
+        use crate::agents::MAX_BOARD_SIZE;
+        logging();
+
+        let request = |turn: usize| -> GameRequest {
+            serde_json::from_str(&format!(
+                r#"{{"game":{{"id":"g","ruleset":{{"name":"standard"}},"timeout":200}},"turn":{turn},"board":{{"height":7,"width":7,"food":[{{"x":1,"y":1}}],"hazards":[],"snakes":[{{"id":"a","name":"a","health":100,"body":[{{"x":1,"y":5}},{{"x":1,"y":5}},{{"x":1,"y":5}}]}},{{"id":"b","name":"b","health":100,"body":[{{"x":5,"y":1}},{{"x":5,"y":1}},{{"x":5,"y":1}}]}}]}},"you":{{"id":"a","name":"a","health":100,"body":[{{"x":1,"y":5}},{{"x":1,"y":5}},{{"x":1,"y":5}}]}}}}"#
+            ))
+            .unwrap()
+        };
+
+        let config: Agent = r#"{"Flood":{"persistent_tt":true}}"#.parse().unwrap();
+        let instances = Instances::default();
+
+        let start = request(0);
+        let warmed = instances.start(&config, &start, 0, MAX_BOARD_SIZE).await;
+        assert_eq!(instances.len(), 1);
+        let entries = warmed.tt().unwrap().lock().unwrap().len();
+        info!("warmed with {entries} entries");
+        assert!(entries > 0);
+
+        // Other games do not share the table
+        let other = config.instance();
+        assert!(other.tt().unwrap().lock().unwrap().is_empty());
+
+        // The move reuses the warmed instance
+        let response = instances
+            .step(&config, &request(1), 0, MAX_BOARD_SIZE)
+            .await;
+        info!("{response:?}");
+        assert!(Arc::ptr_eq(&warmed, &instances.get(&config, &start)));
+        assert_eq!(instances.len(), 1);
+
+        let ended = instances.end(&start).unwrap();
+        assert!(Arc::ptr_eq(&warmed, &ended));
+        assert!(instances.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn instances_overrun() {
+        use super::*;
+        use crate::env::Direction;
+        logging();
+
+        // Up is fatal
+        let request: GameRequest = serde_json::from_str(
+            r#"{"game":{"id":"g","ruleset":{"name":"standard"},"timeout":200},"turn":3,"board":{"height":7,"width":7,"food":[],"hazards":[],"snakes":[{"id":"a","name":"a","health":100,"body":[{"x":3,"y":6},{"x":4,"y":6},{"x":5,"y":6}]}]},"you":{"id":"a","name":"a","health":100,"body":[{"x":3,"y":6},{"x":4,"y":6},{"x":5,"y":6}]}}"#,
+        )
+        .unwrap();
+        let game = Game::from_request(&request);
+        let instances = Instances::default();
+
+        // The agent overruns, the fallback has to respect the latency margin
+        let timer = Instant::now();
+        let response = instances
+            .guarded_step(&Agent::default(), &request, 100, |_, _, _| async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                MoveResponse::new(Direction::Up)
+            })
+            .await;
+        let elapsed = timer.elapsed();
+        info!("{response:?} after {elapsed:?}");
+        assert!(game.move_is_valid(0, response.r#move));
+        assert!(elapsed >= Duration::from_millis(100));
+        // Generous bound for loaded machines, the agent would take 1s
+        assert!(elapsed < Duration::from_millis(200 + 500));
+
+        // Agents that use their whole budget are not cut off by the guard
+        let response = instances
+            .guarded_step(
+                &Agent::default(),
+                &request,
+                100,
+                |_, request, latency| async move {
+                    let budget = request.game.timeout.saturating_sub(latency);
+                    tokio::time::sleep(Duration::from_millis(budget)).await;
+                    MoveResponse::new(Direction::Left)
+                },
+            )
+            .await;
+        info!("{response:?}");
+        assert_eq!(response.r#move, Direction::Left);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn instances_warmup() {
+        use super::*;
+        use crate::agents::MAX_BOARD_SIZE;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{"game":{"id":"g","ruleset":{"name":"standard"},"timeout":10000},"turn":0,"board":{"height":7,"width":7,"food":[{"x":1,"y":1}],"hazards":[],"snakes":[{"id":"a","name":"a","health":100,"body":[{"x":1,"y":5},{"x":1,"y":5},{"x":1,"y":5}]},{"id":"b","name":"b","health":100,"body":[{"x":5,"y":1},{"x":5,"y":1},{"x":5,"y":1}]}]},"you":{"id":"a","name":"a","health":100,"body":[{"x":1,"y":5},{"x":1,"y":5},{"x":1,"y":5}]}}"#,
+        )
+        .unwrap();
+
+        // The warm up does not use the whole game timeout
+        let config: Agent = r#"{"Tree":{}}"#.parse().unwrap();
+        let instances = Instances::default();
+        let timer = Instant::now();
+        instances.start(&config, &request, 0, MAX_BOARD_SIZE).await;
+        let elapsed = timer.elapsed();
+        info!("warm up {elapsed:?}");
+        assert_eq!(instances.len(), 1);
+        assert!(elapsed < Duration::from_millis(WARMUP_TIMEOUT + 500));
+    }
+
+    #[test]
+    fn instances_eviction() {
+        use super::*;
+        logging();
+
+        let request = |id: &str| -> GameRequest {
+            serde_json::from_str(&format!(
+                r#"{{"game":{{"id":"{id}","ruleset":{{"name":"standard"}},"timeout":200}},"turn":0,"board":{{"height":7,"width":7,"food":[],"hazards":[],"snakes":[]}},"you":{{"id":"a","name":"a","health":100,"body":[{{"x":1,"y":5}}]}}}}"#
+            ))
+            .unwrap()
+        };
+        let config = Agent::default();
+
+        // Removed after the end
+        let instances = Instances::default();
+        let a = instances.get(&config, &request("a"));
+        assert!(Arc::ptr_eq(&a, &instances.get(&config, &request("a"))));
+        assert!(instances.end(&request("a")).is_some());
+        assert!(instances.is_empty());
+        assert!(instances.end(&request("a")).is_none());
+
+        // Stale instances are evicted
+        let instances = Instances::new(Duration::from_millis(50), MAX_AGENT_COUNT);
+        let a = instances.get(&config, &request("a"));
+        std::thread::sleep(Duration::from_millis(60));
+        instances.get(&config, &request("b"));
+        info!("running {}", instances.len());
+        assert_eq!(instances.len(), 1);
+        assert!(!Arc::ptr_eq(&a, &instances.get(&config, &request("a"))));
+
+        // The oldest instances are evicted
+        let instances = Instances::new(MAX_RUNTIME, 2);
+        for id in ["a", "b", "c"] {
+            instances.get(&config, &request(id));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(instances.len(), 2);
+        assert!(instances.end(&request("a")).is_none());
+        assert!(instances.end(&request("c")).is_some());
+    }
+
+    #[test]
+    fn latency_estimate() {
+        use super::*;
+        logging();
+
+        let mut estimate = LatencyEstimate::new(100);
+        assert_eq!(estimate.margin(), 100);
+
+        // Stable latency of 50ms, the steps take 200ms
+        for _ in 0..50 {
+            estimate.update(250);
+            estimate.record_step(200);
+        }
+        info!("stable {estimate:?} {}", estimate.margin());
+        assert!(estimate.margin() < 60);
+
+        // Latency spikes to 150ms, the margin reacts immediately
+        let stable = estimate.margin();
+        estimate.update(350);
+        estimate.record_step(200);
+        info!("spike {}", estimate.margin());
+        assert!(estimate.margin() > 150);
+
+        // And converges to the new latency
+        for _ in 0..50 {
+            estimate.update(350);
+            estimate.record_step(200);
+        }
+        let margin = estimate.margin();
+        info!("converged {estimate:?} {margin}");
+        assert!(margin > stable);
+        assert!((150..160).contains(&margin));
+
+        // Unknown latencies are ignored
+        estimate.update(0);
+        estimate.record_step(200);
+        assert_eq!(estimate.margin(), margin);
+
+        // Reported as strings by the game engine
+        let snake = |latency: &str| -> crate::env::Battlesnake {
+            serde_json::from_str(&format!(
+                r#"{{"id":"a","name":"a","health":100,"body":[],"latency":{latency}}}"#
+            ))
+            .unwrap()
+        };
+        assert_eq!(snake(r#""123""#).latency, 123);
+        assert_eq!(snake(r#""""#).latency, 0);
+        assert_eq!(snake("45").latency, 45);
+    }
+}
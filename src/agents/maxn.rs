@@ -1,95 +1,252 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::time::Instant;
 
 use crate::env::*;
 use crate::game::Game;
-use crate::search::{self, Heuristic};
+use crate::search::{self, Heuristic, MoveOrder, SearchLimit, SearchMode, TranspositionTable};
 
 use crate::util::argmax;
 
 use log::info;
-use tokio::sync::mpsc;
-use tokio::time;
 
 const FAST_TIMEOUT: u64 = 150;
 const MAX_DEPTH: usize = 16;
+/// Tree searches up to this depth are not parallelized.
+pub const SYNC_DEPTH: usize = 2;
 
-pub async fn step(heuristic: Arc<dyn Heuristic>, timeout: u64, game: &Game) -> MoveResponse {
-    if timeout <= FAST_TIMEOUT {
-        return step_fast(&*heuristic, game);
-    }
-
-    let (sender, mut receiver) = mpsc::channel(MAX_DEPTH);
-
-    let _ = time::timeout(
-        Duration::from_millis(timeout),
-        iterative_tree_search(heuristic, game, sender),
-    )
-    .await;
-
-    let mut result = None;
-    while let Some(dir) = receiver.recv().await {
-        result = Some(dir);
+/// Searches the next move until the timeout is reached.
+/// Deepening stops early if the next depth is unlikely to finish in time.
+///
+/// If `aspiration` is positive, duels are searched with alphabeta using
+/// aspiration windows of this margin.
+/// If a transposition table is given, duels are searched with alphabeta,
+/// which reuses the results of the previous turns.
+/// The search is extended by up to `max_extension` turns at leaves where
+/// heads are about to collide.
+///
+/// If `shout` is set, the reached depth and value are shouted (`d=6 v=1.2`).
+pub async fn step(
+    heuristic: Arc<dyn Heuristic>,
+    timeout: u64,
+    aspiration: f64,
+    max_extension: usize,
+    tt: Option<Arc<Mutex<TranspositionTable>>>,
+    shout: bool,
+    game: &Game,
+) -> MoveResponse {
+    // Nothing to search if there is only one move
+    let mut moves = game.valid_moves(0);
+    if let (Some(dir), None) = (moves.next(), moves.next()) {
+        info!(">>> single move");
+        return MoveResponse::new(dir);
     }
 
-    if let Some(dir) = result {
-        return MoveResponse::new(Direction::from(dir as u8));
+    let result = if timeout <= FAST_TIMEOUT {
+        step_fast(&*heuristic, game)
+    } else {
+        let deadline = Instant::now() + Duration::from_millis(timeout);
+        if (aspiration > 0.0 || tt.is_some()) && game.snakes.len() == 2 {
+            iterative_alphabeta(heuristic, game, deadline, aspiration, max_extension, tt).await
+        } else {
+            iterative_tree_search(heuristic, game, deadline, max_extension).await
+        }
+    };
+    match result {
+        Some((dir, depth, value)) => {
+            let mut response = if shout {
+                MoveResponse::shout(dir, format!("d={depth} v={value:.1}"))
+            } else {
+                MoveResponse::new(dir)
+            };
+            response.search = Some((depth, value));
+            response
+        }
+        None => {
+            info!(">>> none");
+            MoveResponse::new(super::fallback_move(game))
+        }
     }
-
-    info!(">>> none");
-    MoveResponse::new(game.valid_moves(0).next().unwrap_or(Direction::Up))
 }
 
-pub fn step_fast(heuristic: &dyn Heuristic, game: &Game) -> MoveResponse {
+/// Searches only the next turn, returning the move, depth and value.
+pub fn step_fast(heuristic: &dyn Heuristic, game: &Game) -> Option<(Direction, usize, f64)> {
     let start = Instant::now();
-    let result = search::max_n(game, 1, heuristic);
+    let result = search::max_n(game, 1, heuristic, SearchMode::Paranoid, None);
 
     info!(">>> max_n 1 {:?}ms {result:?}", start.elapsed().as_millis());
 
-    if let Some(dir) = argmax(result.iter().copied()) {
-        if result[dir] > search::LOSS {
-            return MoveResponse::new(Direction::from(dir as u8));
-        }
-    }
-
-    info!(">>> none");
-    MoveResponse::new(game.valid_moves(0).next().unwrap_or(Direction::Up))
+    argmax(result.iter().copied())
+        .filter(|&dir| !search::is_loss(result[dir]))
+        .map(|dir| (Direction::from(dir as u8), 1, result[dir]))
 }
 
 async fn iterative_tree_search(
     heuristic: Arc<dyn Heuristic>,
     game: &Game,
-    sender: mpsc::Sender<Direction>,
-) {
+    deadline: Instant,
+    max_extension: usize,
+) -> Option<(Direction, usize, f64)> {
+    let mut result = None;
+
     // Iterative deepening
     for depth in 1..MAX_DEPTH {
-        let heuristic = heuristic.clone();
-        let (dir, value) = tree_search(heuristic, game, depth).await;
+        let start = Instant::now();
+        let mut limit = SearchLimit::new(depth, usize::MAX, deadline);
+        limit.max_extension = max_extension;
+        let limit = Arc::new(limit);
+        let (dir, value) = tree_search(heuristic.clone(), game, limit.clone()).await;
+
+        if limit.aborted() {
+            // Partial results are only better than nothing
+            if result.is_none() && !search::is_loss(value) {
+                result = Some((dir, depth, value));
+            }
+            break;
+        }
 
         // Stop and fallback to random possible move
-        if value <= search::LOSS {
+        if search::is_loss(value) {
             break;
         }
 
-        if sender.send(dir).await.is_err()
-            // Terminate if we probably win/lose
-            || value >= search::WIN
-        {
+        result = Some((dir, depth, value));
+
+        // Terminate if we probably win/lose
+        if search::is_win(value) {
+            break;
+        }
+
+        if !next_depth_fits(start.elapsed(), deadline, game.snakes.len()) {
+            info!(">>> next depth would exceed the deadline");
             break;
         }
     }
+    result
+}
+
+/// Iterative deepening alphabeta, where every depth is searched with an
+/// aspiration window around the value of the previous depth,
+/// if the `margin` is positive.
+///
+/// Entries of the transposition table older than the previous turn are removed.
+async fn iterative_alphabeta(
+    heuristic: Arc<dyn Heuristic>,
+    game: &Game,
+    deadline: Instant,
+    margin: f64,
+    max_extension: usize,
+    tt: Option<Arc<Mutex<TranspositionTable>>>,
+) -> Option<(Direction, usize, f64)> {
+    let game = game.clone();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let _cancel = CancelOnDrop(cancel.clone());
+    let search = move || {
+        let mut result = None;
+        let mut expected = None;
+
+        let mut tt = tt.as_ref().and_then(|tt| tt.lock().ok());
+        if let Some(tt) = tt.as_deref_mut() {
+            tt.age(1);
+        }
+
+        for depth in 1..MAX_DEPTH {
+            let start = Instant::now();
+            let mut limit = SearchLimit::new(depth, usize::MAX, deadline);
+            limit.prune_suicides = true;
+            limit.max_extension = max_extension;
+            limit.cancel = Some(cancel.clone());
+            let (dir, value) = match expected {
+                Some(expected) if margin > 0.0 => search::aspiration(
+                    &game,
+                    &limit,
+                    &*heuristic,
+                    MoveOrder::Killer,
+                    expected,
+                    margin,
+                    tt.as_deref_mut(),
+                ),
+                _ => search::alphabeta(
+                    &game,
+                    &limit,
+                    &*heuristic,
+                    MoveOrder::Killer,
+                    tt.as_deref_mut(),
+                ),
+            };
+            info!(
+                ">>> alphabeta {depth} {:?}ms {dir:?} {value:.3}",
+                start.elapsed().as_millis()
+            );
+
+            if limit.aborted() {
+                // Partial results are only better than nothing
+                if result.is_none() && !search::is_loss(value) {
+                    result = Some((dir, depth, value));
+                }
+                break;
+            }
+
+            // Stop and fallback to random possible move
+            if search::is_loss(value) {
+                break;
+            }
+
+            result = Some((dir, depth, value));
+            expected = Some(value);
+
+            // Terminate if we probably win
+            if search::is_win(value) {
+                break;
+            }
+
+            if !next_depth_fits(start.elapsed(), deadline, game.snakes.len()) {
+                info!(">>> next depth would exceed the deadline");
+                break;
+            }
+        }
+        result
+    };
+    tokio::task::spawn_blocking(search).await.ok().flatten()
+}
+
+/// Cancels the blocking search if the awaiting task is dropped,
+/// e.g. because it exceeded its timeout.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Predicts if the next depth can be completed before the deadline, given the
+/// duration of the last one.
+///
+/// Every depth is assumed to take about three times the number of snakes
+/// longer than the previous one (roughly the branching factor).
+fn next_depth_fits(last: Duration, deadline: Instant, snakes: usize) -> bool {
+    Instant::now() + last * (3 * snakes) as u32 <= deadline
 }
 
 /// Performes a tree search and returns the maximized heuristic and move.
+///
+/// Searches up to `SYNC_DEPTH` without extensions are done synchronously,
+/// because spawning the tasks would take longer than the search itself.
 pub async fn tree_search(
     heuristic: Arc<dyn Heuristic>,
     game: &Game,
-    depth: usize,
+    limit: Arc<SearchLimit>,
 ) -> (Direction, f64) {
     let start = Instant::now();
+    let depth = limit.max_depth;
 
-    let result = search::async_max_n(game, depth, heuristic).await;
+    let result = if depth <= SYNC_DEPTH && limit.max_extension == 0 {
+        search::max_n(game, depth, &*heuristic, SearchMode::Paranoid, None)
+    } else {
+        search::async_max_n(game, limit, heuristic).await
+    };
 
     info!(
         ">>> max_n {depth} {:?}ms {result:.3?}",
@@ -8,7 +8,7 @@ use crate::env::*;
 use crate::floodfill::{FCell, FloodFill};
 use crate::game::{Game, Snake};
 use crate::grid::CellT;
-use crate::search::{self, Heuristic};
+use crate::search::{self, Heuristic, SearchMode};
 use crate::util::{argmax, OrdPair};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -39,7 +39,7 @@ struct MobilityHeuristic;
 impl Heuristic for MobilityHeuristic {
     fn eval(&self, game: &Game) -> f64 {
         if game.snake_is_alive(0) {
-            let mut flood_fill = FloodFill::new(game.grid.width, game.grid.width);
+            let mut flood_fill = FloodFill::for_game(game);
             flood_fill.flood_snakes(&game.grid, &game.snakes);
             flood_fill.count_space(0) as f64
         } else {
@@ -48,7 +48,28 @@ impl Heuristic for MobilityHeuristic {
     }
 }
 
+/// Fallback for agents that found no good move.
+///
+/// Picks the valid move with the most space after the next turn,
+/// or any valid move if all of them might lose.
+pub fn fallback_move(game: &Game) -> Direction {
+    let space = search::max_n(game, 1, &MobilityHeuristic, SearchMode::Paranoid, None);
+    argmax(space.iter().copied())
+        .filter(|&dir| !search::is_loss(space[dir]))
+        .map(|dir| Direction::from(dir as u8))
+        .unwrap_or_else(|| game.fallback_move(0))
+}
+
 impl MobilityAgent {
+    /// Agent that always moves towards the nearest food,
+    /// while keeping enough space after the move.
+    pub fn greedy() -> Self {
+        Self {
+            min_len: usize::MAX,
+            ..Self::default()
+        }
+    }
+
     fn find_food(
         &self,
         game: &Game,
@@ -67,7 +88,7 @@ impl MobilityAgent {
         for snake in &game.snakes[1..] {
             if snake.body.len() >= you.body.len() {
                 for d in Direction::all() {
-                    let p = snake.head().apply(d);
+                    let p = game.apply(snake.head(), d);
                     if grid.has(p) {
                         grid[p].t = CellT::Owned;
                     }
@@ -87,14 +108,22 @@ impl MobilityAgent {
 
         let mut food_dirs = BinaryHeap::new();
         for p in food {
-            if let Some(path) = grid.a_star(you.head(), p, &first_move_costs) {
+            let path = if game.wrapped {
+                grid.a_star_wrapped(you.head(), p, &first_move_costs)
+            } else {
+                grid.a_star(you.head(), p, &first_move_costs)
+            };
+            if let Some(path) = path {
                 if path.len() >= 2 {
                     let costs = path.len()
                         + match flood_fill[p] {
                             FCell::Owned { id: 0, .. } => 0,
                             _ => 5,
                         };
-                    food_dirs.push(OrdPair(Reverse(costs), Direction::from(path[1] - path[0])));
+                    food_dirs.push(OrdPair(
+                        Reverse(costs),
+                        Direction::of_step(path[1] - path[0]),
+                    ));
                 }
             }
         }
@@ -113,13 +142,14 @@ impl MobilityAgent {
 
         // Flood fill heuristics
         let start = Instant::now();
-        let space_after_move = search::max_n(game, 1, &MobilityHeuristic);
+        let space_after_move =
+            search::max_n(game, 1, &MobilityHeuristic, SearchMode::Paranoid, None);
         info!(
             "max_n {:?}ms {space_after_move:?}",
             start.elapsed().as_millis()
         );
 
-        let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+        let mut flood_fill = FloodFill::for_game(game);
         flood_fill.flood_snakes(&game.grid, &game.snakes);
 
         // Find Food
@@ -139,6 +169,6 @@ impl MobilityAgent {
         }
 
         warn!(">>> random");
-        MoveResponse::new(game.valid_moves(0).next().unwrap_or(Direction::Up))
+        MoveResponse::new(fallback_move(game))
     }
 }
@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::Path;
 use std::str::FromStr;
-use std::string::ToString;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 mod original;
 pub use original::*;
@@ -15,12 +20,24 @@ mod solo;
 pub use solo::*;
 mod mcts;
 pub use mcts::*;
+pub mod ensemble;
+mod survival;
+pub use survival::*;
+mod instances;
+pub use instances::*;
+mod remote;
+pub use remote::*;
 
+use log::{info, warn};
+
+use crate::env::Direction;
 use crate::game::Game;
+use crate::search::TranspositionTable;
 
 use super::env::{GameRequest, MoveResponse};
 
-const MAX_BOARD_SIZE: usize = 19;
+/// Default board size limit, larger boards fall back to the random agent.
+pub const MAX_BOARD_SIZE: usize = 19;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -31,6 +48,11 @@ pub enum Agent {
     MonteCarlo(FloodHeuristic),
     Solo(SoloHeuristic),
     Random(RandomAgent),
+    Survival(SurvivalAgent),
+    /// Weighted majority vote of multiple agents.
+    Ensemble(Vec<ensemble::Member>),
+    /// External Battlesnake server.
+    Remote(RemoteAgent),
 }
 
 impl Default for Agent {
@@ -40,26 +62,239 @@ impl Default for Agent {
 }
 
 impl Agent {
-    pub async fn step(&self, request: &GameRequest, latency: u64) -> MoveResponse {
+    /// Computes the next move without keeping any state between the turns,
+    /// use an `instance` to play a whole game.
+    pub async fn step(
+        &self,
+        request: &GameRequest,
+        latency: u64,
+        max_board_size: usize,
+    ) -> MoveResponse {
+        self.instance_for_game(&request.game.id)
+            .step(request, latency, max_board_size)
+            .await
+    }
+
+    /// Computes the next move, boards larger than `max_board_size` are
+    /// handled by the random agent.
+    pub async fn step_internal(
+        &self,
+        timeout: u64,
+        max_board_size: usize,
+        game: &Game,
+    ) -> MoveResponse {
+        self.instance()
+            .step_internal(timeout, max_board_size, game)
+            .await
+    }
+}
+
+/// Agent that plays a single game.
+///
+/// In contrast to the `Agent` configuration, it keeps state between the
+/// turns, like the transposition table of the search.
+pub struct AgentInstance {
+    pub config: Agent,
+    /// Only if the config keeps the table between turns (`persistent_tt`).
+    tt: Option<Arc<Mutex<TranspositionTable>>>,
+    /// Instances of the ensemble members with their weights.
+    members: Vec<(f64, Arc<AgentInstance>)>,
+    /// Plays oversized boards or if the ensemble has no move.
+    random: RandomAgent,
+}
+
+impl AgentInstance {
+    /// Transposition table that is kept between the turns.
+    pub fn tt(&self) -> Option<&Mutex<TranspositionTable>> {
+        self.tt.as_deref()
+    }
+
+    pub async fn step(
+        &self,
+        request: &GameRequest,
+        latency: u64,
+        max_board_size: usize,
+    ) -> MoveResponse {
         let game = Game::from_request(request);
         let timeout = request.game.timeout.saturating_sub(latency);
 
-        self.step_internal(timeout, &game).await
+        self.step_internal(timeout, max_board_size, &game).await
     }
 
-    pub async fn step_internal(&self, timeout: u64, game: &Game) -> MoveResponse {
-        if game.grid.width > MAX_BOARD_SIZE || game.grid.height > MAX_BOARD_SIZE {
-            return RandomAgent.step(game).await;
+    /// Computes the next move, boards larger than `max_board_size` are
+    /// handled by the random agent.
+    pub async fn step_internal(
+        &self,
+        timeout: u64,
+        max_board_size: usize,
+        game: &Game,
+    ) -> MoveResponse {
+        if !game.snake_is_alive(0) {
+            warn!(">>> already dead");
+            return MoveResponse::new(Direction::default());
+        }
+        if game.grid.width > max_board_size || game.grid.height > max_board_size {
+            return self.random.step(game).await;
         }
 
-        match self {
+        let tt = self.tt.clone();
+        match &self.config {
             Agent::Mobility(agent) => agent.step(game).await,
-            Agent::Tree(agent) => maxn::step(Arc::new(agent.clone()), timeout, game).await,
-            Agent::Flood(agent) => maxn::step(Arc::new(agent.clone()), timeout, game).await,
+            Agent::Tree(agent) if game.turn < agent.opening_turns => {
+                MobilityAgent::greedy().step(game).await
+            }
+            Agent::Flood(agent) if game.turn < agent.opening_turns => {
+                MobilityAgent::greedy().step(game).await
+            }
+            Agent::Tree(agent) => {
+                info!("components {:?}", agent.eval_components(game));
+                let (aspiration, extension) = (agent.aspiration, agent.max_extension);
+                let heuristic = Arc::new(agent.clone());
+                maxn::step(
+                    heuristic,
+                    timeout,
+                    aspiration,
+                    extension,
+                    tt,
+                    agent.shout,
+                    game,
+                )
+                .await
+            }
+            Agent::Flood(agent) => {
+                info!("components {:?}", agent.eval_components(game));
+                let (aspiration, extension) = (agent.aspiration, agent.max_extension);
+                let heuristic = Arc::new(agent.clone());
+                maxn::step(
+                    heuristic,
+                    timeout,
+                    aspiration,
+                    extension,
+                    tt,
+                    agent.shout,
+                    game,
+                )
+                .await
+            }
             Agent::MonteCarlo(agent) => mcts::step(Arc::new(agent.clone()), timeout, game).await,
-            Agent::Solo(agent) => maxn::step(Arc::new(agent.clone()), timeout, game).await,
+            Agent::Solo(agent) => {
+                maxn::step(Arc::new(agent.clone()), timeout, 0.0, 0, None, false, game).await
+            }
             Agent::Random(agent) => agent.step(game).await,
+            Agent::Survival(agent) => agent.step(game).await,
+            Agent::Ensemble(_) => {
+                ensemble::step(&self.members, &self.random, timeout, max_board_size, game).await
+            }
+            Agent::Remote(agent) => agent.step(timeout, game).await,
+        }
+    }
+}
+
+/// Runs the `step` of an agent and falls back to a safe or valid move if it
+/// panics, does not finish within `timeout` ms or returns a fatal move.
+///
+/// Steps that exceed the timeout are aborted, so that they do not compete
+/// with the following turns for the CPU.
+pub async fn safe_step<F>(game: &Game, timeout: u64, step: F) -> MoveResponse
+where
+    F: Future<Output = MoveResponse> + Send + 'static,
+{
+    let mut task = tokio::spawn(step);
+    let result = tokio::time::timeout(Duration::from_millis(timeout), &mut task).await;
+    if result.is_err() {
+        task.abort();
+    }
+    match result {
+        Ok(Ok(response))
+            if game.move_is_valid(0, response.r#move) || game.valid_moves(0).next().is_none() =>
+        {
+            return response;
+        }
+        Ok(Ok(response)) => warn!("invalid move {response:?}"),
+        Ok(Err(e)) => warn!("agent failed: {e}"),
+        Err(_) => warn!("agent timed out"),
+    }
+    MoveResponse::new(fallback_move(game))
+}
+
+impl Agent {
+    /// Creates an instance for a new game, which does not share any state,
+    /// like the transposition tables, with other games.
+    #[must_use]
+    pub fn instance(&self) -> AgentInstance {
+        self.instance_with(RandomAgent::default())
+    }
+
+    /// Like `instance`, but the random fallback is seeded with the game id,
+    /// so that replays of the game are reproducible.
+    #[must_use]
+    pub fn instance_for_game(&self, game_id: &str) -> AgentInstance {
+        self.instance_with(RandomAgent::from_game_id(game_id))
+    }
+
+    fn instance_with(&self, random: RandomAgent) -> AgentInstance {
+        let persistent_tt = match self {
+            Agent::Tree(agent) => agent.persistent_tt,
+            Agent::Flood(agent) => agent.persistent_tt,
+            _ => false,
+        };
+        let members = match self {
+            Agent::Ensemble(members) => members
+                .iter()
+                .map(|m| (m.weight, Arc::new(m.agent.instance_with(random.clone()))))
+                .collect(),
+            _ => Vec::new(),
+        };
+        AgentInstance {
+            config: self.clone(),
+            tt: persistent_tt.then(Default::default),
+            members,
+            random,
+        }
+    }
+
+    /// Loads the configuration, like retuned weights, from a json file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let config = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&config)?)
+    }
+}
+
+/// Agent configurations for the different rulesets,
+/// like standard, royale, wrapped or constrictor.
+#[derive(Debug, Clone, Default)]
+pub struct RulesetConfigs {
+    /// Used for rulesets without configuration.
+    pub default: Agent,
+    pub rulesets: HashMap<String, Agent>,
+}
+
+impl RulesetConfigs {
+    pub fn new(default: Agent) -> Self {
+        Self {
+            default,
+            rulesets: HashMap::new(),
+        }
+    }
+
+    /// Loads the configurations from the json files of a directory,
+    /// which are named after their ruleset (e.g. `royale.json`).
+    pub fn load_dir(dir: &Path, default: Agent) -> io::Result<Self> {
+        let mut configs = Self::new(default);
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(ruleset) = path.file_stem().and_then(|s| s.to_str()) {
+                    configs.rulesets.insert(ruleset.into(), Agent::load(&path)?);
+                }
+            }
         }
+        Ok(configs)
+    }
+
+    /// Returns the configuration of the ruleset or the default.
+    pub fn select(&self, ruleset: &str) -> &Agent {
+        self.rulesets.get(ruleset).unwrap_or(&self.default)
     }
 }
 
@@ -71,8 +306,635 @@ impl FromStr for Agent {
     }
 }
 
-impl ToString for Agent {
-    fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap_or_default()
+impl fmt::Display for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn large_board() {
+        use super::*;
+        use crate::env::v2;
+        use crate::floodfill::FloodFill;
+        use crate::game::Snake;
+        use crate::search::{Heuristic, LOSS};
+        logging();
+
+        let snakes = vec![
+            Snake::new(vec![v2(1, 1), v2(2, 1), v2(3, 1), v2(3, 2)].into(), 100),
+            Snake::new(vec![v2(22, 20), v2(22, 21), v2(22, 22)].into(), 100),
+        ];
+        let game = Game::new(0, 25, 25, snakes, &[v2(12, 12)], &[]);
+
+        let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+        flood_fill.flood_snakes(&game.grid, &game.snakes);
+        assert_eq!(
+            flood_fill.count_space(0) + flood_fill.count_space(1),
+            25 * 25
+        );
+        assert!(FloodHeuristic::default().eval(&game) > LOSS);
+
+        let agent = Agent::Flood(FloodHeuristic::default());
+        let response = agent.step_internal(100, 25, &game).await;
+        info!("{response:?}");
+        assert!(game.move_is_valid(0, response.r#move));
+    }
+
+    #[tokio::test]
+    async fn mobility_non_square() {
+        use super::*;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{"game":{"id":"","ruleset":{"name":"standard"},"timeout":500},"turn":10,"board":{"height":11,"width":7,"food":[{"x":5,"y":9}],"hazards":[],"snakes":[{"id":"a","name":"a","health":20,"body":[{"x":1,"y":8},{"x":1,"y":7},{"x":1,"y":6}]},{"id":"b","name":"b","health":90,"body":[{"x":5,"y":2},{"x":4,"y":2},{"x":3,"y":2},{"x":3,"y":1}]}]},"you":{"id":"a","name":"a","health":20,"body":[{"x":1,"y":8},{"x":1,"y":7},{"x":1,"y":6}]}}"#,
+        )
+        .unwrap();
+
+        let agent = Agent::Mobility(MobilityAgent::default());
+        let response = agent.step(&request, 0, MAX_BOARD_SIZE).await;
+        info!("{response:?}");
+        let game = Game::from_request(&request);
+        assert!(game.move_is_valid(0, response.r#move));
+    }
+
+    #[tokio::test]
+    async fn flood_aspiration() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let agent: Agent = r#"{"Flood":{"aspiration":0.5}}"#.parse().unwrap();
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        info!("{response:?}");
+        assert!(game.move_is_valid(0, response.r#move));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn search_extension() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . o . 0 < < .
+            . . . . . . .
+            . 1 < < < < .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        // Heads are about to collide, which is searched deeper
+        for config in [
+            r#"{"Tree":{"max_extension":2}}"#,
+            r#"{"Flood":{"max_extension":2}}"#,
+            r#"{"Flood":{"max_extension":2,"aspiration":0.5}}"#,
+        ] {
+            let agent: Agent = config.parse().unwrap();
+            let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+            info!("{config}: {response:?}");
+            assert!(game.move_is_valid(0, response.r#move));
+            assert!(response.search.is_some());
+        }
+    }
+
+    #[test]
+    fn load_weights() {
+        use super::*;
+        use crate::search::Heuristic;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . o . . . . ."#,
+        )
+        .unwrap();
+
+        let weights = r#"{"Flood":{"health":1.0,"space":2.0,"size_adv":0.5}}"#;
+        let dir = crate::unique_temp_dir("snork_load_weights");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("weights.json");
+        std::fs::write(&path, weights).unwrap();
+        let agent = Agent::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        info!("{agent}");
+
+        let expected: Agent = weights.parse().unwrap();
+        assert_eq!(agent.to_string(), expected.to_string());
+
+        let Agent::Flood(heuristic) = agent else {
+            panic!("Unexpected agent {agent}");
+        };
+        assert!(heuristic.eval(&game) != FloodHeuristic::default().eval(&game));
+
+        assert!(Agent::load(Path::new("/nonexistent/weights.json")).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ensemble() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . o . . . . ."#,
+        )
+        .unwrap();
+
+        let agent: Agent =
+            r#"{"Ensemble":[{"agent":{"Flood":{}}},{"weight":2,"agent":{"Tree":{}}}]}"#
+                .parse()
+                .unwrap();
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        info!("{response:?}");
+        assert!(game.move_is_valid(0, response.r#move));
+
+        // A single member decides alone
+        let member = Agent::Mobility(MobilityAgent::default());
+        let expected = member.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        let agent = Agent::Ensemble(vec![member.into()]);
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        info!("{expected:?} {response:?}");
+        assert_eq!(response.r#move, expected.r#move);
+
+        // A heavier member overrides two lighter ones
+        let mut seeds: HashMap<Direction, Vec<u64>> = HashMap::new();
+        for seed in 0..64 {
+            let response = RandomAgent { seed: Some(seed) }.step(&game).await;
+            seeds.entry(response.r#move).or_default().push(seed);
+        }
+        let (&light, pair) = seeds.iter().find(|(_, s)| s.len() >= 2).unwrap();
+        let (&heavy, single) = seeds.iter().find(|(&d, _)| d != light).unwrap();
+        let member = |weight, seed| ensemble::Member {
+            weight,
+            agent: Agent::Random(RandomAgent { seed: Some(seed) }),
+        };
+        let agent = Agent::Ensemble(vec![
+            member(1.0, pair[0]),
+            member(1.0, pair[1]),
+            member(1.0, single[0]),
+        ]);
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        assert_eq!(response.r#move, light);
+        let agent = Agent::Ensemble(vec![
+            member(1.0, pair[0]),
+            member(1.0, pair[1]),
+            member(2.5, single[0]),
+        ]);
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        info!("{light:?} {heavy:?} {response:?}");
+        assert_eq!(response.r#move, heavy);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn seeded_fallback() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . 0 < . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        // Oversized boards are played by the random agent of the game
+        let config = Agent::Ensemble(vec![Agent::default().into()]);
+        let a = config.instance_for_game("game");
+        let b = config.instance_for_game("game");
+        for turn in 0..16 {
+            game.turn = turn;
+            let ra = a.step_internal(200, 0, &game).await;
+            let rb = b.step_internal(200, 0, &game).await;
+            assert!(game.move_is_valid(0, ra.r#move));
+            assert_eq!(ra.r#move, rb.r#move);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn persistent_tt() {
+        use super::*;
+        use crate::env::Direction;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let config: Agent = r#"{"Flood":{"persistent_tt":true}}"#.parse().unwrap();
+        let agent = config.instance();
+
+        let response = agent.step_internal(300, MAX_BOARD_SIZE, &game).await;
+        assert!(game.move_is_valid(0, response.r#move));
+        let (entries, hits) = {
+            let tt = agent.tt().unwrap().lock().unwrap();
+            (tt.len(), tt.hits)
+        };
+        info!("{response:?} {entries} entries {hits} hits");
+        assert!(entries > 0);
+
+        let enemy = game.valid_moves(1).next().unwrap_or(Direction::Up);
+        game.step(&[response.r#move, enemy]);
+
+        // The second turn reuses the entries of the first
+        let response = agent.step_internal(300, MAX_BOARD_SIZE, &game).await;
+        assert!(game.move_is_valid(0, response.r#move));
+        let (new_entries, new_hits) = {
+            let tt = agent.tt().unwrap().lock().unwrap();
+            (tt.len(), tt.hits)
+        };
+        info!("{response:?} {new_entries} entries {new_hits} hits");
+        assert!(new_entries > entries);
+        assert!(new_hits > hits);
+    }
+
+    #[tokio::test]
+    async fn opening() {
+        use super::*;
+        use crate::env::Direction;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^
+            . . . . . . ^
+            . o . 0 < < .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        game.turn = 1;
+
+        let agent: Agent = r#"{"Flood":{"opening_turns":5}}"#.parse().unwrap();
+        let response = agent.step_internal(500, MAX_BOARD_SIZE, &game).await;
+        let expected = MobilityAgent::greedy().step(&game).await;
+        info!("{response:?} {expected:?}");
+        assert_eq!(response.r#move, expected.r#move);
+        // Towards the food
+        assert_eq!(response.r#move, Direction::Left);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shout() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let agent: Agent = r#"{"Tree":{"shout":true}}"#.parse().unwrap();
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        info!("{response:?}");
+        assert!(game.move_is_valid(0, response.r#move));
+
+        let (depth, value) = response.shout.split_once(' ').unwrap();
+        let depth: usize = depth.strip_prefix("d=").unwrap().parse().unwrap();
+        let value: f64 = value.strip_prefix("v=").unwrap().parse().unwrap();
+        assert!(depth >= 1);
+        assert!(value > crate::search::LOSS);
+
+        let agent: Agent = r#"{"Tree":{}}"#.parse().unwrap();
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        assert!(response.shout.is_empty());
+    }
+
+    #[tokio::test]
+    async fn single_move() {
+        use super::*;
+        use crate::env::Direction;
+        use std::time::Instant;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            v < < . . . .
+            0 . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.valid_moves(0).count(), 1);
+
+        for agent in [r#"{"Tree":{}}"#, r#"{"Flood":{}}"#] {
+            let agent: Agent = agent.parse().unwrap();
+            let start = Instant::now();
+            let response = agent.step_internal(1000, MAX_BOARD_SIZE, &game).await;
+            let elapsed = start.elapsed();
+            info!("{agent}: {response:?} in {elapsed:?}");
+            assert_eq!(response.r#move, Direction::Right);
+            // Returned without searching
+            assert_eq!(response.search, None);
+            assert!(elapsed < Duration::from_millis(500));
+        }
+    }
+
+    #[tokio::test]
+    async fn completed_depth() {
+        use super::*;
+        use crate::search::SearchLimit;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . 0 < < . . . . . . .
+            . . . . . . . . 1 . .
+            . . . . . . . . ^ . .
+            . . . . . . . . ^ . .
+            . . . . . o . . . . .
+            . . 2 . . . . . . . .
+            . . ^ . . . . . . . .
+            . . ^ . . . . > > 3 .
+            . . . . . . . . . . .
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+
+        let flood = FloodHeuristic::default();
+        let agent = Agent::Flood(flood.clone());
+        let response = agent.step_internal(200, MAX_BOARD_SIZE, &game).await;
+        info!("{response:?}");
+        let (depth, value) = response.search.unwrap();
+
+        // The same result as an unlimited search of this depth
+        let limit = Arc::new(SearchLimit::depth(depth));
+        let (dir, expected) = maxn::tree_search(Arc::new(flood), &game, limit.clone()).await;
+        info!("depth {depth}: {dir:?} {expected}");
+        assert!(!limit.aborted());
+        assert_eq!(response.r#move, dir);
+        assert_eq!(value, expected);
+    }
+
+    #[tokio::test]
+    async fn sync_tree_search() {
+        use super::*;
+        use crate::search::{self, SearchLimit, SearchMode};
+        use crate::util::argmax;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . 0 < < . . . . . . .
+            . . . . . . . . 1 . .
+            . . . . . . . . ^ . .
+            . . . . . . . . ^ . .
+            . . . . . o . . . . .
+            . . 2 . . . . . . . .
+            . . ^ . . . . . . . .
+            . . ^ . . . . > > 3 .
+            . . . . . . . . . . .
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+        let flood = Arc::new(FloodHeuristic::default());
+
+        // The synchronous and parallel search agree
+        for depth in 1..=maxn::SYNC_DEPTH {
+            let limit = Arc::new(SearchLimit::depth(depth));
+            let parallel = search::async_max_n(&game, limit.clone(), flood.clone()).await;
+            let sync = search::max_n(&game, depth, &*flood, SearchMode::Paranoid, None);
+            assert_eq!(sync, parallel);
+
+            let (dir, value) = maxn::tree_search(flood.clone(), &game, limit).await;
+            info!("depth {depth}: {dir:?} {value} {parallel:?}");
+            let best = argmax(parallel.iter().copied()).unwrap();
+            assert_eq!(dir as usize, best);
+            assert_eq!(value, parallel[best]);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn safe_step_panic() {
+        use super::*;
+        use crate::env::Direction;
+        use crate::search::Heuristic;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        logging();
+
+        #[derive(Debug)]
+        struct PanicH;
+        impl Heuristic for PanicH {
+            fn eval(&self, _game: &Game) -> f64 {
+                panic!("deliberate failure")
+            }
+        }
+
+        // Up is fatal
+        let game = Game::parse(
+            r#"
+            . . . 0 < < .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^
+            . . . . . . ^"#,
+        )
+        .unwrap();
+
+        let searched = game.clone();
+        let response = safe_step(&game, 200, async move {
+            maxn::step(Arc::new(PanicH), 100, 0.0, 0, None, false, &searched).await
+        })
+        .await;
+        info!("{response:?}");
+        assert!(game.move_is_valid(0, response.r#move));
+
+        let response = safe_step(&game, 200, async { panic!("deliberate failure") }).await;
+        assert!(game.move_is_valid(0, response.r#move));
+
+        // Timeout
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        let response = safe_step(&game, 10, async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            flag.store(true, Ordering::Relaxed);
+            MoveResponse::new(Direction::Up)
+        })
+        .await;
+        assert!(game.move_is_valid(0, response.r#move));
+        // The step has been aborted
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(!finished.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn safe_step_cancel_search() {
+        use super::*;
+        use std::time::Instant;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        // The blocking alphabeta search holds the table until it finishes
+        let tt: Arc<Mutex<TranspositionTable>> = Default::default();
+        let heuristic = Arc::new(FloodHeuristic::default());
+        let (searched, table) = (game.clone(), tt.clone());
+        let response = safe_step(&game, 200, async move {
+            maxn::step(heuristic, 60_000, 0.0, 0, Some(table), false, &searched).await
+        })
+        .await;
+        assert!(game.move_is_valid(0, response.r#move));
+
+        // The search stops soon after the timeout instead of at its deadline
+        let timer = Instant::now();
+        while tt.try_lock().is_err() {
+            assert!(timer.elapsed() < Duration::from_secs(5));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        info!("search stopped after {:?}", timer.elapsed());
+    }
+
+    #[test]
+    fn fallback_space() {
+        use super::*;
+        use crate::env::Direction;
+        logging();
+
+        // Up leads into the small pocket behind the other snake
+        let game = Game::parse(
+            r#"
+            . . . 1 . . .
+            . . . ^ . . .
+            . . . ^ . . .
+            . > > ^ . . .
+            0 . . . . . .
+            ^ . . . . . .
+            ^ . . . . . ."#,
+        )
+        .unwrap();
+        assert!(game.move_is_valid(0, Direction::Up));
+        assert_eq!(game.fallback_move(0), Direction::Up);
+
+        let dir = fallback_move(&game);
+        info!("{dir:?}");
+        assert_eq!(dir, Direction::Right);
+    }
+
+    #[tokio::test]
+    async fn dead_you() {
+        use super::*;
+        use crate::game::Outcome;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . . .
+            . ^ . 1 .
+            . ^ . ^ .
+            . . . ^ ."#,
+        )
+        .unwrap();
+        let mut request = game.to_request(game.turn, "standard");
+        request.you.health = 0;
+
+        let dead = Game::from_request(&request);
+        assert!(!dead.snake_is_alive(0));
+        assert!(dead.snakes[0].body.is_empty());
+        assert!(dead.snake_is_alive(1));
+        assert_eq!(dead.outcome(), Outcome::Winner(1));
+
+        for agent in [Agent::default(), Agent::Flood(Default::default())] {
+            let response = agent.step(&request, 0, MAX_BOARD_SIZE).await;
+            info!("{response:?}");
+            assert_eq!(response.r#move, Direction::default());
+        }
+
+        // Missing from the board
+        let mut request = game.to_request(game.turn, "standard");
+        request.board.snakes.retain(|s| s.id != request.you.id);
+        assert!(!Game::from_request(&request).snake_is_alive(0));
+    }
+
+    #[test]
+    fn ruleset_configs() {
+        use super::*;
+        logging();
+
+        let dir = crate::unique_temp_dir("snork_ruleset_configs");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("royale.json"), r#"{"Flood":{"health":1.0}}"#).unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+        let configs = RulesetConfigs::load_dir(&dir, Agent::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let configs = configs.unwrap();
+        info!("{configs:?}");
+
+        let royale = configs.select("royale");
+        assert!(matches!(royale, Agent::Flood(_)));
+        let standard = configs.select("standard");
+        assert!(matches!(standard, Agent::Mobility(_)));
+        assert_eq!(configs.rulesets.len(), 1);
     }
 }
@@ -1,5 +1,4 @@
-use crate::env::*;
-use crate::floodfill::FloodFill;
+use crate::floodfill::{owned_food_score, FloodFill};
 use crate::game::Game;
 use crate::search::{self, Heuristic};
 
@@ -17,6 +16,20 @@ pub struct TreeHeuristic {
     food_ownership_decay: f64,
     centrality: f64,
     centrality_decay: f64,
+    /// Penalty for every board edge the head is directly against.
+    wall_proximity: f64,
+    /// Number of turns at the start of the game, where the greedy food and
+    /// space policy is used instead of the search.
+    pub opening_turns: usize,
+    /// Shout the reached search depth and value.
+    pub shout: bool,
+    /// Margin of the aspiration windows in duels, disabled if zero.
+    pub aspiration: f64,
+    /// Number of turns the search is extended beyond its depth if heads
+    /// are about to collide, disabled if zero.
+    pub max_extension: usize,
+    /// Keep the transposition table of duels between turns.
+    pub persistent_tt: bool,
 }
 
 impl Default for TreeHeuristic {
@@ -32,18 +45,28 @@ impl Default for TreeHeuristic {
             food_ownership_decay: 0.0,
             centrality: 0.1,
             centrality_decay: 0.0,
+            wall_proximity: 0.0,
+            opening_turns: 0,
+            shout: false,
+            aspiration: 0.0,
+            max_extension: 0,
+            persistent_tt: false,
         }
     }
 }
 
-impl Heuristic for TreeHeuristic {
-    /// Heuristic function for the tree search.
-    fn eval(&self, game: &Game) -> f64 {
+impl TreeHeuristic {
+    /// Individual weighted terms of the evaluation, which sum up to `eval`:
+    /// mobility, health, length advantage, food ownership, centrality and
+    /// wall proximity.
+    ///
+    /// If we are dead, the first term is a loss and the others are zero.
+    pub fn eval_components(&self, game: &Game) -> [f64; 6] {
         if !game.snake_is_alive(0) {
-            return search::LOSS;
+            return [search::LOSS, 0.0, 0.0, 0.0, 0.0, 0.0];
         }
 
-        let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+        let mut flood_fill = FloodFill::for_game(game);
         let food_distances = flood_fill.flood_snakes(&game.grid, &game.snakes);
         let space = flood_fill.count_space(0);
         let mobility = space as f64 / (game.grid.width * game.grid.height) as f64;
@@ -59,25 +82,160 @@ impl Heuristic for TreeHeuristic {
             .unwrap_or(0);
         let len_advantage = own_len as f64 / max_enemy_len as f64;
 
-        // Owned food
-        let accessable_food = food_distances.iter().count() as f64;
-        let food_ownership = accessable_food / game.grid.width as f64;
+        // Owned food, weighted by distance
+        let food_ownership = owned_food_score(&food_distances) / game.grid.width as f64;
 
-        // Centrality
+        // Centrality, relative to the center of the cells without hazards
         let centrality = 1.0
-            - (game.snakes[0].head()
-                - Vec2D::new(game.grid.width as i16 / 2, game.grid.height as i16 / 2))
-            .manhattan() as f64
+            - (game.snakes[0].head() - game.grid.safe_center()).manhattan() as f64
                 / game.grid.width as f64;
 
-        mobility * self.mobility * (-(game.turn as f64) * self.mobility_decay).exp()
-            + health * self.health * (-(game.turn as f64) * self.health_decay).exp()
-            + len_advantage
+        // Number of edges we are against (0-2)
+        let head = game.snakes[0].head();
+        let walls = (head.x == 0 || head.x == game.grid.width as i16 - 1) as usize
+            + (head.y == 0 || head.y == game.grid.height as i16 - 1) as usize;
+
+        [
+            mobility * self.mobility * (-(game.turn as f64) * self.mobility_decay).exp(),
+            health * self.health * (-(game.turn as f64) * self.health_decay).exp(),
+            len_advantage
                 * self.len_advantage
-                * (-(game.turn as f64) * self.len_advantage_decay).exp()
-            + food_ownership
+                * (-(game.turn as f64) * self.len_advantage_decay).exp(),
+            food_ownership
                 * self.food_ownership
-                * (-(game.turn as f64) * self.food_ownership_decay).exp()
-            + centrality * self.centrality * (-(game.turn as f64) * self.centrality_decay).exp()
+                * (-(game.turn as f64) * self.food_ownership_decay).exp(),
+            centrality * self.centrality * (-(game.turn as f64) * self.centrality_decay).exp(),
+            -(walls as f64) * self.wall_proximity,
+        ]
+    }
+}
+
+impl Heuristic for TreeHeuristic {
+    /// Heuristic function for the tree search.
+    fn eval(&self, game: &Game) -> f64 {
+        self.eval_components(game).iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn tree_wall_proximity() {
+        use super::*;
+        logging();
+
+        let center = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 0 < < .
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^"#,
+        )
+        .unwrap();
+        let edge = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            0 < < . . . .
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^"#,
+        )
+        .unwrap();
+        let corner = Game::parse(
+            r#"
+            0 < < . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^"#,
+        )
+        .unwrap();
+
+        let baseline = TreeHeuristic::default();
+        let walls = TreeHeuristic {
+            wall_proximity: 1.0,
+            ..TreeHeuristic::default()
+        };
+
+        let penalty = |game: &Game| baseline.eval(game) - walls.eval(game);
+        let (center, edge, corner) = (penalty(&center), penalty(&edge), penalty(&corner));
+        info!("center {center} edge {edge} corner {corner}");
+        assert_eq!(center, 0.0);
+        assert_eq!(edge, 1.0);
+        assert_eq!(corner, 2.0);
+    }
+
+    #[test]
+    fn tree_centrality_hazards() {
+        use super::*;
+        use crate::env::Vec2D;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 0 < < .
+            . . . . . . .
+            . . . . . . 1
+            . . . . . . ^"#,
+        )
+        .unwrap();
+        let heuristic = TreeHeuristic::default();
+        let centrality = |game: &Game| heuristic.eval_components(game)[4];
+
+        let center = centrality(&game);
+        // The safe zone shrinks from the left
+        let hazards: Vec<_> = (0..7).map(|y| Vec2D::new(0, y)).collect();
+        game.grid.add_hazards(&hazards);
+        game.grid.add_hazards(
+            &hazards
+                .iter()
+                .map(|p| Vec2D::new(1, p.y))
+                .collect::<Vec<_>>(),
+        );
+        let shifted = centrality(&game);
+        info!("center {center} shifted {shifted}");
+        assert_eq!(game.grid.safe_center(), Vec2D::new(4, 3));
+        assert!(shifted < center);
+    }
+
+    #[test]
+    fn tree_components() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . o . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let heuristic = TreeHeuristic {
+            wall_proximity: 1.0,
+            ..TreeHeuristic::default()
+        };
+        let components = heuristic.eval_components(&game);
+        let eval = heuristic.eval(&game);
+        info!("{components:?} -> {eval}");
+        assert_eq!(components.iter().sum::<f64>(), eval);
     }
 }
@@ -5,15 +5,91 @@ use crate::game::Game;
 use rand::{rngs::SmallRng, seq::IteratorRandom, SeedableRng};
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
-pub struct RandomAgent;
+#[serde(default)]
+pub struct RandomAgent {
+    /// Makes the moves reproducible, they only depend on the seed and turn.
+    /// Without seed the moves are chosen randomly.
+    pub seed: Option<u64>,
+}
 
 thread_local! {
     static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy())
 }
 
 impl RandomAgent {
+    /// Creates an agent that is seeded with the hash of the game id.
+    pub fn from_game_id(id: &str) -> Self {
+        Self {
+            seed: Some(fnv1a(id.as_bytes())),
+        }
+    }
+
     pub async fn step(&self, game: &Game) -> MoveResponse {
-        let moves = game.valid_moves(0);
-        MoveResponse::new(RNG.with_borrow_mut(|rng| moves.choose(rng).unwrap_or(Direction::Up)))
+        // Avoid likely head to head losses if possible
+        let mut moves: Vec<Direction> = game.safe_moves(0).collect();
+        if moves.is_empty() {
+            moves = game.valid_moves(0).collect();
+        }
+        let dir = match self.seed {
+            Some(seed) => {
+                let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(game.turn as u64));
+                moves.into_iter().choose(&mut rng)
+            }
+            None => RNG.with_borrow_mut(|rng| moves.into_iter().choose(rng)),
+        };
+        MoveResponse::new(dir.unwrap_or(Direction::Up))
+    }
+}
+
+/// 64-bit FNV-1a hash, which in contrast to the `DefaultHasher` is
+/// stable across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn random_seeded() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 0 < . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        // Stable seeds for the same game
+        assert_eq!(
+            RandomAgent::from_game_id("a").seed,
+            Some(0xaf63dc4c8601ec8c)
+        );
+
+        for turn in 0..16 {
+            game.turn = turn;
+            let a = RandomAgent::from_game_id("game").step(&game).await;
+            let b = RandomAgent::from_game_id("game").step(&game).await;
+            info!("{turn}: {a:?} {b:?}");
+            assert_eq!(a.r#move, b.r#move);
+            assert!(game.move_is_valid(0, a.r#move));
+
+            let seed = Some(turn as u64 * 31);
+            let a = RandomAgent { seed }.step(&game).await;
+            let b = RandomAgent { seed }.step(&game).await;
+            assert_eq!(a.r#move, b.r#move);
+        }
     }
 }
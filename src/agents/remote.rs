@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use hyper::{Body, Client, Method, Request};
+use log::warn;
+
+use crate::env::*;
+use crate::game::Game;
+
+/// Agent that queries the `/move` endpoint of an external Battlesnake server,
+/// to play against other snakes in the simulator.
+///
+/// Only plain HTTP is supported.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RemoteAgent {
+    /// Base url of the server, like `http://127.0.0.1:8000`.
+    pub url: String,
+    /// Ruleset of the requests.
+    pub ruleset: String,
+}
+
+impl Default for RemoteAgent {
+    fn default() -> Self {
+        Self {
+            url: "http://127.0.0.1:8000".into(),
+            ruleset: "standard".into(),
+        }
+    }
+}
+
+impl RemoteAgent {
+    pub async fn step(&self, timeout: u64, game: &Game) -> MoveResponse {
+        let request = game.to_request(game.turn, &self.ruleset);
+        let result =
+            tokio::time::timeout(Duration::from_millis(timeout), self.query(&request)).await;
+        match result {
+            Ok(Ok(response)) => return response,
+            Ok(Err(e)) => warn!("remote {}: {e}", self.url),
+            Err(_) => warn!("remote {}: timed out", self.url),
+        }
+        MoveResponse::new(super::fallback_move(game))
+    }
+
+    async fn query(
+        &self,
+        request: &GameRequest,
+    ) -> Result<MoveResponse, Box<dyn std::error::Error>> {
+        let url = format!("{}/move", self.url.trim_end_matches('/'));
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(request)?))?;
+
+        let response = Client::new().request(request).await?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()).into());
+        }
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn remote_agent() {
+        use super::*;
+        use std::sync::{Arc, Mutex};
+        use warp::Filter;
+        logging();
+
+        // Mock server that always moves left
+        let received = Arc::new(Mutex::new(None));
+        let route = {
+            let received = received.clone();
+            warp::path("move")
+                .and(warp::post())
+                .and(warp::body::json())
+                .map(move |request: GameRequest| {
+                    *received.lock().unwrap() = Some(request);
+                    warp::reply::json(&MoveResponse::new(Direction::Left))
+                })
+        };
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . 0 . .
+            . . ^ . .
+            . . ^ 1 .
+            . . . . ."#,
+        )
+        .unwrap();
+
+        let agent = RemoteAgent {
+            url: format!("http://{addr}/"),
+            ..Default::default()
+        };
+        let response = agent.step(1000, &game).await;
+        info!("{response:?}");
+        assert_eq!(response.r#move, Direction::Left);
+
+        let request = received.lock().unwrap().take().unwrap();
+        assert_eq!(request.board.snakes.len(), 2);
+        assert_eq!(request.you.body[0], game.snakes[0].head());
+
+        // Falls back to a valid move if the server is unreachable
+        let agent = RemoteAgent {
+            url: "http://127.0.0.1:1".into(),
+            ..Default::default()
+        };
+        let response = agent.step(1000, &game).await;
+        assert!(game.move_is_valid(0, response.r#move));
+    }
+}
@@ -27,7 +27,7 @@ impl Heuristic for SoloHeuristic {
             let you = &game.snakes[0];
             let area = (game.grid.width * game.grid.height) as f64;
 
-            let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+            let mut flood_fill = FloodFill::for_game(game);
             let food_distances = flood_fill.flood_snakes(&game.grid, &game.snakes);
 
             let food_distance = food_distances[0] as f64;
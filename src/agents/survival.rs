@@ -0,0 +1,119 @@
+use std::cmp::Reverse;
+
+use log::info;
+
+use crate::env::*;
+use crate::floodfill::FloodFill;
+use crate::game::Game;
+use crate::grid::CellT;
+
+/// Agent for solo games that maximizes the number of survived turns.
+///
+/// It stays within the largest region, stalls by following its own tail
+/// and only searches food if hungry.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurvivalAgent {
+    /// Health below which the agent searches food.
+    hunger: u8,
+}
+
+impl Default for SurvivalAgent {
+    fn default() -> Self {
+        Self { hunger: 30 }
+    }
+}
+
+impl SurvivalAgent {
+    pub async fn step(&self, game: &Game) -> MoveResponse {
+        let you = &game.snakes[0];
+        let head = you.head();
+
+        // Size of the region entered by every move (following the tail)
+        let mut flood_fill = FloodFill::for_game(game);
+        let pockets = Direction::all().map(|d| {
+            if game.move_is_valid(0, d) {
+                flood_fill.pocket_size(&game.grid, &game.snakes, game.apply(head, d))
+            } else {
+                0
+            }
+        });
+        info!("pockets {pockets:?}");
+
+        // Moves that do not trap us, or the ones into the largest region
+        let max_pocket = pockets.iter().copied().max().unwrap_or(0);
+        if max_pocket == 0 {
+            return MoveResponse::new(super::fallback_move(game));
+        }
+        let min_pocket = you.body.len().min(max_pocket);
+        let safe = Direction::all()
+            .into_iter()
+            .filter(|&d| pockets[d as usize] >= min_pocket);
+
+        if you.health < self.hunger {
+            // Nearest reachable food
+            let food_distance = |d: Direction| {
+                let distances = game.grid.bfs_distances(game.apply(head, d));
+                distances
+                    .iter()
+                    .zip(&game.grid.cells)
+                    .filter(|(_, c)| c.t == CellT::Food)
+                    .filter_map(|(d, _)| *d)
+                    .min()
+            };
+            if let Some(dir) = safe
+                .clone()
+                .filter_map(|d| food_distance(d).map(|f| (f, d)))
+                .min_by_key(|&(f, _)| f)
+                .map(|(_, d)| d)
+            {
+                info!(">>> food");
+                return MoveResponse::new(dir);
+            }
+        }
+
+        // Stall by following the tail
+        let tail = you.body[0];
+        let dir = safe
+            .min_by_key(|&d| {
+                (
+                    (game.apply(head, d) - tail).manhattan(),
+                    Reverse(pockets[d as usize]),
+                )
+            })
+            .unwrap_or(Direction::Up);
+        info!(">>> tail");
+        MoveResponse::new(dir)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn survival_avoid_trap() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . > > v . . .
+            . ^ o v . . .
+            . ^ 0 < . . .
+            . ^ . . . . .
+            . ^ . . . . ."#,
+        )
+        .unwrap();
+        game.snakes[0].health = 10;
+
+        // The food is inside a pocket that is too small
+        let response = SurvivalAgent::default().step(&game).await;
+        info!("{response:?}");
+        assert_eq!(response.r#move, Direction::Down);
+    }
+}
@@ -20,6 +20,9 @@ struct Opts {
     /// Time in ms that is subtracted from the game timeouts.
     #[clap(long, default_value_t = 200)]
     latency: usize,
+    /// Larger boards are played by the random agent.
+    #[clap(long, default_value_t = MAX_BOARD_SIZE)]
+    max_board_size: usize,
 }
 
 fn parse_request(s: &str) -> Result<GameRequest, serde_json::Error> {
@@ -34,17 +37,18 @@ async fn main() {
         config,
         request,
         latency,
+        max_board_size,
     } = Opts::parse();
 
     let game = Game::from_request(&request);
     info!("{config:?}");
     info!("{game:?}");
 
-    let mut flood_fill = FloodFill::new(request.board.width, request.board.height);
+    let mut flood_fill = FloodFill::for_game(&game);
     flood_fill.flood_snakes(&game.grid, &game.snakes);
     info!("{flood_fill:?}");
 
-    let step = config.step(&request, latency as _).await;
+    let step = config.step(&request, latency as _, max_board_size).await;
 
     info!("Step: {step:?}");
 }
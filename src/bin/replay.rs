@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use log::{info, warn};
+
+use snork::agents::*;
+use snork::logging;
+use snork::savegame;
+
+use clap::Parser;
+
+#[derive(Parser)]
+#[clap(
+    version,
+    author,
+    about = "Replay a saved game and compare the moves of an agent."
+)]
+struct Opts {
+    /// Default configuration.
+    #[clap(long, default_value_t)]
+    config: Agent,
+    /// Saved game (`<game id>.jsonl`).
+    file: PathBuf,
+    /// Time in ms that is subtracted from the game timeouts.
+    #[clap(long, default_value_t = 200)]
+    latency: usize,
+    /// Larger boards are played by the random agent.
+    #[clap(long, default_value_t = MAX_BOARD_SIZE)]
+    max_board_size: usize,
+}
+
+#[tokio::main]
+async fn main() {
+    logging();
+
+    let Opts {
+        config,
+        file,
+        latency,
+        max_board_size,
+    } = Opts::parse();
+
+    let requests = savegame::load(&file)
+        .unwrap_or_else(|e| panic!("Failed to load the savegame {file:?}: {e}"));
+    info!("{config:?}");
+    info!("replaying {} turns", requests.len());
+
+    let turns = savegame::replay(&config, &requests, latency as _, max_board_size).await;
+
+    let mut mismatches = 0;
+    for turn in &turns {
+        match turn.played {
+            Some(played) if played != turn.chosen => {
+                mismatches += 1;
+                warn!("t={} chosen {:?} played {played:?}", turn.turn, turn.chosen);
+            }
+            played => info!("t={} chosen {:?} played {played:?}", turn.turn, turn.chosen),
+        }
+    }
+    println!("{mismatches} of {} moves differ", turns.len());
+}
@@ -1,26 +1,14 @@
-use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
 
-use log::{info, warn};
-use snork::env::{GameRequest, IndexResponse, API_VERSION};
-use snork::{agents::*, logging};
+use log::info;
+use snork::metrics::Metrics;
+use snork::savegame::SaveWriter;
+use snork::server::{routes, State};
+use snork::{agents::*, logging_with, LogFormat};
 
 use clap::Parser;
-use warp::Filter;
-
-pub const PACKAGE_VERSION: &str = env!("CARGO_PKG_VERSION");
-
-/// Runtime server configuration.
-struct State {
-    latency: u64,
-    color: String,
-    head: String,
-    tail: String,
-    author: String,
-    config: Agent,
-}
 
 /// High performant rust snake.
 #[derive(Debug, Parser)]
@@ -32,9 +20,13 @@ struct Opt {
     /// the outside. (`127.0.0.1` or `localhost` is private to your computer)
     #[clap(long, default_value = "127.0.0.1:5001")]
     host: SocketAddr,
-    /// Time in ms that is subtracted from the game timeouts.
+    /// Initial estimate of the latency in ms that is subtracted from the game
+    /// timeouts, it is adapted to the latencies reported during the game.
     #[clap(long, default_value_t = 100)]
     latency: u64,
+    /// Larger boards are played by the random agent.
+    #[clap(long, default_value_t = MAX_BOARD_SIZE)]
+    max_board_size: usize,
     /// Color in hex format.
     #[clap(long, default_value = "#FF7043")]
     color: String,
@@ -50,85 +42,75 @@ struct Opt {
     /// Default configuration.
     #[clap(long, default_value_t)]
     config: Agent,
+    /// Json file with the configuration, overwrites `config`.
+    /// This allows deploying retuned weights without recompiling.
+    #[clap(long)]
+    weights: Option<PathBuf>,
+    /// Directory with json configurations for specific rulesets
+    /// (e.g. `royale.json`), the others use the default configuration.
+    #[clap(long)]
+    configs: Option<PathBuf>,
+    /// Directory where the games are saved for a later replay.
+    #[clap(long)]
+    savegames: Option<PathBuf>,
+    /// Format of the logs, `text` or `json`.
+    /// Defaults to the `SNORK_LOG_FORMAT` environment variable.
+    #[clap(long)]
+    log_format: Option<LogFormat>,
 }
 
 #[tokio::main]
 async fn main() {
-    logging();
-
     let Opt {
         host,
         latency,
+        max_board_size,
         color,
         head,
         tail,
         author,
         config,
+        weights,
+        configs,
+        savegames,
+        log_format,
     } = Opt::parse();
+    logging_with(log_format.unwrap_or_else(LogFormat::from_env));
+
+    let config = match weights {
+        Some(path) => Agent::load(&path)
+            .unwrap_or_else(|e| panic!("Failed to load the weights {path:?}: {e}")),
+        None => config,
+    };
+    info!("config {config}");
+
+    let configs = match configs {
+        Some(dir) => RulesetConfigs::load_dir(&dir, config)
+            .unwrap_or_else(|e| panic!("Failed to load the configs {dir:?}: {e}")),
+        None => RulesetConfigs::new(config),
+    };
+    for (ruleset, config) in &configs.rulesets {
+        info!("config {ruleset} {config}");
+    }
+
+    if let Some(dir) = &savegames {
+        std::fs::create_dir_all(dir)
+            .unwrap_or_else(|e| panic!("Failed to create the savegames {dir:?}: {e}"));
+    }
 
     let state = Arc::new(State {
         latency,
+        max_board_size,
         color,
         head,
         tail,
         author,
-        config,
+        configs,
+        instances: Instances::default(),
+        metrics: Metrics::default(),
+        debug: Default::default(),
+        savegames: savegames.map(SaveWriter::spawn),
     });
 
-    let index = warp::get()
-        .and(warp::path::end())
-        .and(with_state(state.clone()))
-        .map(|state: Arc<State>| {
-            warn!("index");
-            warp::reply::json(&IndexResponse::new(
-                API_VERSION,
-                &state.author,
-                &state.color,
-                &state.head,
-                &state.tail,
-                PACKAGE_VERSION,
-            ))
-        });
-
-    let start = warp::path("start")
-        .and(warp::post())
-        .and(warp::body::json())
-        .map(|request: GameRequest| {
-            warn!("start {request}");
-            warp::reply()
-        });
-
-    let r#move = warp::path("move")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(with_state(state.clone()))
-        .and_then(step);
-
-    let end = warp::path("end")
-        .and(warp::post())
-        .and(warp::body::json())
-        .map(|request: GameRequest| {
-            warn!("end {request}");
-            warp::reply()
-        });
-
-    warp::serve(index.or(start).or(r#move).or(end))
-        .run(host)
-        .await;
-}
-
-fn with_state(
-    config: Arc<State>,
-) -> impl Filter<Extract = (Arc<State>,), Error = std::convert::Infallible> + Clone {
-    warp::any().map(move || config.clone())
-}
-
-async fn step(request: GameRequest, state: Arc<State>) -> Result<impl warp::Reply, Infallible> {
-    warn!("move {request}");
-
-    let timer = Instant::now();
-    let next_move = state.config.step(&request, state.latency).await;
-    info!("{next_move:?}, {:?}ms", timer.elapsed().as_millis());
-
-    Ok(warp::reply::json(&next_move))
+    warp::serve(routes(state)).run(host).await;
 }
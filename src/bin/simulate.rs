@@ -1,16 +1,17 @@
 use clap::Parser;
-use log::{debug, info, warn};
+use log::{info, warn};
 use owo_colors::OwoColorize;
 
-use snork::agents::Agent;
+use snork::agents::{Agent, MAX_BOARD_SIZE};
+use snork::dataset;
 use snork::env::*;
-use snork::game::{Game, Outcome, Snake};
-use snork::grid::CellT;
 use snork::logging;
+use snork::savegame;
+use snork::simulation::{write_stats, HazardSchedule, Simulation};
 
 use rand::prelude::*;
-use rand::seq::IteratorRandom;
-use std::iter::repeat;
+use std::iter::repeat_n;
+use std::path::PathBuf;
 use std::time::Instant;
 
 #[derive(clap::Parser)]
@@ -19,6 +20,9 @@ struct Opts {
     /// Time each snake has for a turn.
     #[clap(long, default_value_t = 200)]
     timeout: u64,
+    /// Larger boards are played by the random agent.
+    #[clap(long, default_value_t = MAX_BOARD_SIZE)]
+    max_board_size: usize,
     /// Board height.
     #[clap(long, default_value_t = 11)]
     width: usize,
@@ -28,28 +32,73 @@ struct Opts {
     /// Chance new food spawns.
     #[clap(long, default_value_t = 0.15)]
     food_rate: f64,
+    /// Minimum amount of food on the board.
+    #[clap(long, default_value_t = 1)]
+    min_food: usize,
     /// Number of turns after which the hazard expands.
     #[clap(short, long, default_value_t = 25)]
     shrink_turns: usize,
+    /// Expansion of the hazards: `royale`, `symmetric`, or `none`.
+    #[clap(long, default_value = "royale")]
+    hazards: HazardSchedule,
+    /// Games are a draw if a position is repeated this many times,
+    /// disabled if zero.
+    #[clap(long, default_value_t = 3)]
+    repetitions: usize,
     /// Number of games that are played.
     #[clap(short, long, default_value_t = 1)]
     game_count: usize,
+    /// Number of games that are played concurrently.
+    #[clap(short, long, default_value_t = 1)]
+    jobs: usize,
     /// Swap agent positions to get more accurate results.
     #[clap(long)]
     swap: bool,
+    /// Play all subsets of this many agents against each other,
+    /// instead of all agents in a single game.
+    #[clap(long)]
+    tournament: Option<usize>,
+    /// Compare the two agents in a head to head match, with swapped positions,
+    /// and report the win rate of the first with its confidence interval.
+    #[clap(long, conflicts_with = "tournament")]
+    r#match: bool,
+    /// Maximum change of the Elo ratings per tournament game.
+    #[clap(long, default_value_t = 32.0)]
+    k_factor: f64,
+    /// Directory for SVG frames of every turn, one subdirectory per game.
+    #[clap(long)]
+    render: Option<PathBuf>,
+    /// File for the records of the games, JSON if it ends with `.json`,
+    /// or CSV otherwise.
+    #[clap(long)]
+    stats: Option<PathBuf>,
+    /// File for the training samples of every turn: the floodfill ownership
+    /// and distance grids, chosen moves, and outcomes.
+    #[clap(long, conflicts_with_all = ["tournament", "match"])]
+    samples: Option<PathBuf>,
     /// Seed for the random number generator.
     #[clap(long, default_value_t = 0)]
     seed: u64,
     /// Start config.
     #[clap(long, value_parser = parse_request)]
     init: Option<GameRequest>,
+    /// Start from a saved game (`<game id>.jsonl`) instead, at the given `--turn`.
+    #[clap(long, conflicts_with = "init")]
+    savegame: Option<PathBuf>,
+    /// Turn of the saved game to start from.
+    #[clap(long, default_value_t = 0, requires = "savegame")]
+    turn: usize,
     /// Configurations.
     #[clap()]
     agents: Vec<Agent>,
 }
 
+/// Parses an API request or a board of the board editor.
 fn parse_request(s: &str) -> Result<GameRequest, serde_json::Error> {
-    serde_json::from_str(s)
+    serde_json::from_str(s).or_else(|e| match serde_json::from_str::<EditorBoard>(s) {
+        Ok(board) => Ok(board.into()),
+        Err(_) => Err(e),
+    })
 }
 
 #[tokio::main]
@@ -58,56 +107,109 @@ async fn main() {
 
     let Opts {
         timeout,
+        max_board_size,
         width,
         height,
         food_rate,
+        min_food,
         shrink_turns,
+        hazards,
+        repetitions,
         game_count,
+        jobs,
         swap,
+        tournament,
+        r#match,
+        k_factor,
+        render,
+        stats,
+        samples,
         seed,
         init,
+        savegame,
+        turn,
         mut agents,
     } = Opts::parse();
 
+    let init = match savegame {
+        Some(path) => Some(
+            savegame::load_turn(&path, turn)
+                .unwrap_or_else(|e| panic!("Failed to load the savegame {path:?}: {e}")),
+        ),
+        None => init,
+    };
+    if let Some(request) = &init {
+        assert!(
+            agents.len() >= request.board.snakes.len(),
+            "The start config requires an agent for each snake"
+        );
+    }
+
+    let simulation = Simulation {
+        timeout,
+        max_board_size,
+        width,
+        height,
+        food_rate,
+        min_food,
+        shrink_turns,
+        hazards,
+        init,
+        render,
+        samples: samples.is_some(),
+        repetitions,
+    };
+
+    if let Some(size) = tournament {
+        assert!((1..=4).contains(&size), "Only up to 4 snakes are supported");
+        let mut rng = rng(seed);
+        let result = simulation
+            .tournament(&agents, size, game_count, k_factor, &mut rng)
+            .await;
+        for (i, agent) in agents.iter().enumerate() {
+            println!("{i}: {agent}");
+        }
+        println!("{result}");
+        return;
+    }
+
+    if r#match {
+        let [a, b] = &agents[..] else {
+            panic!("A match requires exactly two agents");
+        };
+        let report = simulation.head_to_head(a, b, game_count, jobs, seed).await;
+        println!("A: {a}");
+        println!("B: {b}");
+        println!("{report}");
+        return;
+    }
+
     assert!(agents.len() <= 4, "Only up to 4 snakes are supported");
     info!("agents: {agents:?}");
 
     let start = Instant::now();
 
-    let mut wins = repeat(0).take(agents.len()).collect::<Vec<usize>>();
-
-    for _ in 0..agents.len() {
-        let mut rng = if seed == 0 {
-            SmallRng::from_entropy()
-        } else {
-            SmallRng::seed_from_u64(seed)
-        };
-
-        for i in 0..game_count {
-            let mut game = if let Some(request) = &init {
-                Game::from_request(request)
-            } else {
-                init_game(width, height, agents.len(), &mut rng)
-            };
+    let mut records = Vec::new();
+    let mut wins = repeat_n(0, agents.len()).collect::<Vec<usize>>();
 
-            let outcome = play_game(
-                &agents,
-                &mut game,
-                timeout,
-                food_rate,
-                shrink_turns,
-                &mut rng,
-            )
-            .await;
-            if let Outcome::Winner(winner) = outcome {
-                wins[winner as usize] += 1;
+    for rotation in 0..agents.len() {
+        let first = rotation * game_count;
+        for mut record in simulation.run(&agents, first, game_count, jobs, seed).await {
+            if let Some(winner) = record.winner {
+                wins[winner] += 1;
             }
-            warn!(
-                "{}: {i} {}ms",
-                "Finish Game".bright_green(),
-                start.elapsed().as_millis()
-            );
+            // Indices of the initial agent order
+            for p in &mut record.players {
+                *p = (*p + rotation) % agents.len();
+            }
+            records.push(record);
         }
+        warn!(
+            "{}: {} {}ms",
+            "Finish Games".bright_green(),
+            records.len(),
+            start.elapsed().as_millis()
+        );
 
         if !swap {
             break;
@@ -117,160 +219,24 @@ async fn main() {
         agents.rotate_left(1);
     }
 
-    println!("Agents: {agents:?}");
-    println!("Result: {wins:?}");
-}
-
-async fn play_game(
-    agents: &[Agent],
-    game: &mut Game,
-    timeout: u64,
-    food_rate: f64,
-    shrink_turns: usize,
-    rng: &mut SmallRng,
-) -> Outcome {
-    let mut food_count = 4;
-
-    debug!("init: {game:?}");
-
-    let mut hazard_insets = [0; 4];
-
-    for turn in game.turn.. {
-        let mut moves = [Direction::Up; 4];
-        for i in 0..game.snakes.len() {
-            if game.snakes[i].alive() {
-                // Agents assume player 0 is you.
-                game.snakes.swap(0, i);
-
-                let response = agents[i].step_internal(timeout, game).await;
-                moves[i] = response.r#move;
-
-                game.snakes.swap(0, i);
-            }
-        }
-        debug!("Moves: {moves:?}");
-
-        game.step(&moves);
-
-        debug!("{}: {:?}", turn, game);
-
-        let outcome = game.outcome();
-        if outcome != Outcome::None {
-            warn!("game: {outcome:?} after {turn} turns");
-            return outcome;
-        }
-
-        // Check if snakes have consumed food
-        for snake in &game.snakes {
-            if snake.alive() && snake.health == 100 {
-                food_count -= 1;
-            }
-        }
-
-        // Spawn food
-        if food_count == 0 || rng.gen::<f64>() < food_rate {
-            if let Some(cell) = game
-                .grid
-                .cells
-                .iter_mut()
-                .filter(|c| c.t == CellT::Free)
-                .choose(rng)
-            {
-                cell.t = CellT::Food;
-                food_count += 1;
-            }
-        }
-
-        // Hazards
-        if turn > 0
-            && turn % shrink_turns == 0
-            && hazard_insets[0] + hazard_insets[2] < game.grid.height
-            && hazard_insets[1] + hazard_insets[3] < game.grid.width
-        {
-            let dir = rng.gen_range(0..4);
-            hazard_insets[dir] += 1;
-            if dir % 2 == 0 {
-                let y = if dir == 0 {
-                    hazard_insets[dir] - 1
-                } else {
-                    game.grid.height - hazard_insets[dir]
-                };
-                for x in 0..game.grid.width {
-                    game.grid[v2(x as _, y as _)].hazard = true;
-                }
-            } else {
-                let x = if dir == 1 {
-                    hazard_insets[dir] - 1
-                } else {
-                    game.grid.width - hazard_insets[dir]
-                };
-                for y in 0..game.grid.height {
-                    game.grid[v2(x as _, y as _)].hazard = true;
-                }
-            }
-        }
-    }
-    Outcome::Match
-}
-
-fn init_game(width: usize, height: usize, num_agents: usize, rng: &mut SmallRng) -> Game {
-    if width % 2 == 0 || height % 2 == 0 {
-        warn!("If the dimension are even, the initial board configuration is unfair!");
-    }
-    if width != height {
-        warn!("If width != height, the initial board configuration is unfair!");
+    if let Some(path) = stats {
+        write_stats(&path, &records)
+            .unwrap_or_else(|e| panic!("Failed to write the stats {path:?}: {e}"));
     }
 
-    // Either start in the corners or in the middle of the edges
-    let mut start_positions = if rng.gen() {
-        // Corners
-        [
-            v2(1, 1),
-            v2((width - 2) as _, 1),
-            v2((width - 2) as _, (height - 2) as _),
-            v2(1, (height - 2) as _),
-        ]
-    } else {
-        // Edges
-        [
-            v2((width / 2) as _, 1),
-            v2((width - 2) as _, (height / 2) as _),
-            v2((width / 2) as _, (height - 2) as _),
-            v2(1, (height / 2) as _),
-        ]
+    if let Some(path) = samples {
+        dataset::write(&path, records.iter().flat_map(|r| &r.samples))
+            .unwrap_or_else(|e| panic!("Failed to write the samples {path:?}: {e}"));
     }
-    .into_iter()
-    .choose_multiple(rng, num_agents);
-
-    start_positions.shuffle(rng);
 
-    let snakes = start_positions
-        .into_iter()
-        .map(|p| Snake::new(vec![p; 3].into(), 100))
-        .collect();
-
-    let mut game = Game::new(0, width, height, snakes, &[], &[]);
-
-    // Food at center
-    game.grid[(width / 2, height / 2).into()].t = CellT::Food;
+    println!("Agents: {agents:?}");
+    println!("Result: {wins:?}");
+}
 
-    // Spawn 1 food 2 steps away from each snake
-    for snake in game.snakes.clone() {
-        let p = [v2(-1, -1), v2(-1, 1), v2(1, 1), v2(1, -1)]
-            .into_iter()
-            .map(|p| snake.head() + p)
-            // Only free cells on the board
-            .filter(|&p| game.grid.has(p) && game.grid[p].t != CellT::Owned)
-            // Limit to a border cells (excluding the corners)
-            .filter(|&p| {
-                (p.x == 0 || p.x == game.grid.width as i16 - 1)
-                    ^ (p.y == 0 || p.y == game.grid.height as i16 - 1)
-            })
-            .choose(rng);
-        if let Some(p) = p {
-            game.grid[p].t = CellT::Food;
-        }
+fn rng(seed: u64) -> SmallRng {
+    if seed == 0 {
+        SmallRng::from_entropy()
+    } else {
+        SmallRng::seed_from_u64(seed)
     }
-
-    game
 }
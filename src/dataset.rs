@@ -0,0 +1,233 @@
+//! Training data for supervised learning, recorded by the simulator.
+//!
+//! Every turn of every living snake is stored as a [Sample], which contains
+//! the floodfill ownership and distance grids from the perspective of the
+//! snake, its chosen move, and the outcome of the game.
+//!
+//! The samples are written into a compact binary file (little endian):
+//!
+//! ```text
+//! header: b"SNKD", version: u8
+//! sample: turn: u16, player: u8, move: u8, outcome: i8, width: u8, height: u8,
+//!         ownership: [u8; width * height], distance: [u16; width * height]
+//! ```
+//!
+//! The grids are row-major from the bottom left.
+//! The ownership is `0` for cells that are not owned, `1` for the snake
+//! itself and `2..` for the enemies.
+//! The distance is `u16::MAX` for cells that are not owned.
+//! The move is encoded as `Up = 0`, `Right = 1`, `Down = 2`, `Left = 3`,
+//! and the outcome is `1` for a win, `-1` for a loss, and `0` for a draw.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::env::Direction;
+use crate::floodfill::FloodFill;
+use crate::game::Game;
+
+/// Magic number at the start of the file.
+pub const MAGIC: &[u8; 4] = b"SNKD";
+/// Version of the binary format.
+pub const VERSION: u8 = 1;
+
+/// A labeled game state of a single snake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub turn: u16,
+    /// Index of the snake in the game.
+    pub player: u8,
+    pub chosen: Direction,
+    /// `1` for a win, `-1` for a loss, and `0` for a draw.
+    pub outcome: i8,
+    pub width: u8,
+    pub height: u8,
+    pub ownership: Vec<u8>,
+    pub distance: Vec<u16>,
+}
+
+impl Sample {
+    /// Encodes the game from the perspective of the snake at index 0,
+    /// which is the `player` of the original game.
+    /// The outcome is filled in with [label] after the game has ended.
+    pub fn new(game: &Game, player: usize, chosen: Direction) -> Self {
+        let mut flood_fill = FloodFill::for_game(game);
+        flood_fill.flood_snakes(&game.grid, &game.snakes);
+        Self {
+            turn: game.turn as _,
+            player: player as _,
+            chosen,
+            outcome: 0,
+            width: game.grid.width as _,
+            height: game.grid.height as _,
+            ownership: flood_fill
+                .ownership_grid()
+                .into_iter()
+                .map(|o| o.map_or(0, |id| id + 1))
+                .collect(),
+            distance: flood_fill
+                .distance_grid()
+                .into_iter()
+                .map(|d| d.unwrap_or(u16::MAX))
+                .collect(),
+        }
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.turn.to_le_bytes())?;
+        out.write_all(&[
+            self.player,
+            self.chosen as u8,
+            self.outcome as u8,
+            self.width,
+            self.height,
+        ])?;
+        out.write_all(&self.ownership)?;
+        for d in &self.distance {
+            out.write_all(&d.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Returns `None` at the end of the file.
+    fn read(input: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut turn = [0; 2];
+        match input.read_exact(&mut turn) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let mut fields = [0; 5];
+        input.read_exact(&mut fields)?;
+        let [player, chosen, outcome, width, height] = fields;
+        if chosen > 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid move"));
+        }
+        let cells = width as usize * height as usize;
+        let mut ownership = vec![0; cells];
+        input.read_exact(&mut ownership)?;
+        let mut distance = vec![0; 2 * cells];
+        input.read_exact(&mut distance)?;
+        Ok(Some(Self {
+            turn: u16::from_le_bytes(turn),
+            player,
+            chosen: Direction::from(chosen),
+            outcome: outcome as i8,
+            width,
+            height,
+            ownership,
+            distance: distance
+                .chunks_exact(2)
+                .map(|d| u16::from_le_bytes([d[0], d[1]]))
+                .collect(),
+        }))
+    }
+}
+
+/// Sets the outcome of the samples of a game that has been won by `winner`,
+/// or ended in a draw if `None`.
+pub fn label(samples: &mut [Sample], winner: Option<usize>) {
+    for sample in samples {
+        sample.outcome = match winner {
+            Some(w) if w == sample.player as usize => 1,
+            Some(_) => -1,
+            None => 0,
+        };
+    }
+}
+
+/// Writes the samples into a new file.
+pub fn write<'a>(path: &Path, samples: impl IntoIterator<Item = &'a Sample>) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    for sample in samples {
+        sample.write(&mut out)?;
+    }
+    out.flush()
+}
+
+/// Reads all samples of the file.
+pub fn read(path: &Path) -> io::Result<Vec<Sample>> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut header = [0; 5];
+    input.read_exact(&mut header)?;
+    if &header[..4] != MAGIC || header[4] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unsupported file format",
+        ));
+    }
+    let mut samples = Vec::new();
+    while let Some(sample) = Sample::read(&mut input)? {
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn dataset_samples() {
+        use super::*;
+        use crate::agents::{Agent, RandomAgent};
+        use crate::simulation::Simulation;
+        use rand::prelude::*;
+        logging();
+
+        let agents = [
+            Agent::Random(RandomAgent { seed: Some(1) }),
+            Agent::Random(RandomAgent { seed: Some(2) }),
+        ]
+        .map(|a| a.instance());
+        let simulation = Simulation {
+            width: 7,
+            height: 7,
+            samples: true,
+            ..Default::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(42);
+        let mut game = simulation.new_game(agents.len(), &mut rng);
+        let stats = simulation.play(&agents, &mut game, &mut rng).await;
+        info!("{} turns, winner {:?}", stats.turns, stats.winner);
+
+        // One sample for every turn a snake was alive
+        let alive = |player: u8| stats.samples.iter().filter(|s| s.player == player).count();
+        assert!(stats.turns > 0);
+        assert_eq!(alive(0) + alive(1), stats.samples.len());
+        match stats.winner {
+            Some(w) => assert_eq!(alive(w as u8), stats.turns),
+            None => assert_eq!(alive(0).max(alive(1)), stats.turns),
+        }
+        for sample in &stats.samples {
+            let expected = match stats.winner {
+                Some(w) if w == sample.player as usize => 1,
+                Some(_) => -1,
+                None => 0,
+            };
+            assert_eq!(sample.outcome, expected);
+            assert_eq!(sample.ownership.len(), 7 * 7);
+            assert_eq!(sample.distance.len(), 7 * 7);
+            // Both snakes own some space at the start
+            if sample.turn == 0 {
+                assert!(sample.ownership.contains(&1));
+                assert!(sample.ownership.contains(&2));
+            }
+        }
+
+        let dir = crate::unique_temp_dir("snork_dataset");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("samples.bin");
+        write(&file, &stats.samples).unwrap();
+        let size = std::fs::metadata(&file).unwrap().len() as usize;
+        let loaded = read(&file);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(size, 5 + stats.samples.len() * (7 + 3 * 7 * 7));
+        assert_eq!(loaded.unwrap(), stats.samples);
+    }
+}
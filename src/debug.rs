@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::Filter;
+
+use crate::env::{Board, Direction, GameRequest, MoveResponse};
+
+/// Number of buffered events, slower clients skip the older ones.
+const CAPACITY: usize = 64;
+
+/// Served move that is streamed to the debugging clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugEvent<'a> {
+    pub game: &'a str,
+    pub turn: usize,
+    pub board: &'a Board,
+    pub r#move: Direction,
+    /// Reached search depth, if the agent searches.
+    pub depth: Option<usize>,
+    pub value: Option<f64>,
+}
+
+/// Broadcasts the served moves to the clients of the `/debug/ws` WebSocket,
+/// which allows watching the agent think in real time.
+pub struct DebugStream {
+    sender: broadcast::Sender<Arc<str>>,
+}
+
+impl Default for DebugStream {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl DebugStream {
+    /// Sends the move to all connected clients.
+    pub fn publish(&self, request: &GameRequest, response: &MoveResponse) {
+        // Skip the serialization if nobody listens
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+
+        let event = DebugEvent {
+            game: &request.game.id,
+            turn: request.turn,
+            board: &request.board,
+            r#move: response.r#move,
+            depth: response.search.map(|(depth, _)| depth),
+            value: response.search.map(|(_, value)| value),
+        };
+        match serde_json::to_string(&event) {
+            Ok(event) => {
+                let _ = self.sender.send(event.into());
+            }
+            Err(e) => warn!("debug event: {e}"),
+        }
+    }
+
+    /// Filter of the `/debug/ws` WebSocket route.
+    pub fn filter(
+        self: Arc<Self>,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        warp::path!("debug" / "ws")
+            .and(warp::ws())
+            .map(move |ws: Ws| {
+                let receiver = self.sender.subscribe();
+                ws.on_upgrade(move |socket| forward(socket, receiver))
+            })
+    }
+}
+
+/// Forwards the events to the client until it disconnects.
+async fn forward(socket: WebSocket, mut receiver: broadcast::Receiver<Arc<str>>) {
+    info!("debug client connected");
+    let (mut sink, mut stream) = socket.split();
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    if sink.send(Message::text(&*event)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => warn!("debug client lagged {n}"),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            // Stop if the client disconnects
+            message = stream.next() => if !matches!(message, Some(Ok(_))) {
+                break;
+            },
+        }
+    }
+    info!("debug client disconnected");
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn debug_stream() {
+        use super::*;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{"game":{"id":"g","ruleset":{"name":"standard"},"timeout":200},"turn":3,"board":{"height":7,"width":7,"food":[],"hazards":[],"snakes":[]},"you":{"id":"a","name":"a","health":100,"body":[]}}"#,
+        )
+        .unwrap();
+
+        let stream = Arc::new(DebugStream::default());
+        // Nobody listens
+        stream.publish(&request, &MoveResponse::new(Direction::Up));
+
+        let mut client = warp::test::ws()
+            .path("/debug/ws")
+            .handshake(stream.clone().filter())
+            .await
+            .unwrap();
+
+        let mut response = MoveResponse::new(Direction::Left);
+        response.search = Some((4, 1.5));
+        for _ in 0..3 {
+            stream.publish(&request, &response);
+        }
+
+        for _ in 0..3 {
+            let message = client.recv().await.unwrap();
+            let message = message.to_str().unwrap();
+            info!("{message}");
+            let event: serde_json::Value = serde_json::from_str(message).unwrap();
+            assert_eq!(event["turn"], 3);
+            assert_eq!(event["move"], "left");
+            assert_eq!(event["depth"], 4);
+        }
+        // No more messages than served moves
+        let next = tokio::time::timeout(std::time::Duration::from_millis(50), client.recv()).await;
+        assert!(next.is_err());
+    }
+}
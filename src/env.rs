@@ -4,14 +4,14 @@
 /// requests.
 ///
 /// See: https://docs.battlesnake.com/api
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt::{self, Debug};
 use std::mem::size_of;
 use std::ops::{Add, Neg, Sub};
 
 pub const API_VERSION: &str = "1";
 
-pub const HAZARD_DAMAGE: u8 = 15;
+pub const HAZARD_DAMAGE: u8 = 14;
 
 /// Position in the a 2D grid.
 #[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -41,6 +41,15 @@ impl Vec2D {
         self.x.unsigned_abs() as u64 + self.y.unsigned_abs() as u64
     }
 
+    /// Returns the manhattan distance to `other` on a board of the given size
+    /// where the edges are connected (wrapped ruleset).
+    /// Both positions have to be within the board.
+    pub fn manhattan_wrapped(self, other: Vec2D, width: usize, height: usize) -> u64 {
+        let diff = self - other;
+        let (dx, dy) = (diff.x.unsigned_abs() as u64, diff.y.unsigned_abs() as u64);
+        dx.min(width as u64 - dx) + dy.min(height as u64 - dy)
+    }
+
     /// Returns whether the vector is inside a rectangle from (0,0) to (width-1,height-1)
     pub fn within(self, width: usize, height: usize) -> bool {
         self.x >= 0 && self.x < width as _ && self.y >= 0 && self.y < height as _
@@ -106,7 +115,7 @@ impl Neg for Vec2D {
 /// The Direction is returned as part of a `MoveResponse`.
 ///
 /// The Y-Axis is positive in the up direction, and X-Axis is positive to the right.
-#[derive(Serialize, Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Direction {
     /// Positive Y
@@ -127,10 +136,16 @@ impl fmt::Display for Direction {
 }
 
 impl Direction {
+    /// Returns all directions in the order of their `u8` representation.
     pub fn all() -> [Self; 4] {
         [Self::Up, Self::Right, Self::Down, Self::Left]
     }
 
+    /// Iterates over all directions in the order of `all`.
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::all().into_iter()
+    }
+
     /// Returns the invert direction (eg. Left for Right)
     pub fn invert(&self) -> Self {
         match self {
@@ -140,6 +155,17 @@ impl Direction {
             Self::Left => Self::Right,
         }
     }
+
+    /// Returns the direction of a step between two neighboring cells.
+    /// Steps across the edge of a wrapped board span the whole board and
+    /// are inverted accordingly.
+    pub fn of_step(delta: Vec2D) -> Self {
+        if delta.manhattan() > 1 {
+            Self::from(delta).invert()
+        } else {
+            Self::from(delta)
+        }
+    }
 }
 
 impl From<Vec2D> for Direction {
@@ -194,7 +220,58 @@ pub struct Ruleset {
     #[serde(default)]
     pub version: String,
     #[serde(default)]
-    pub settings: Settings,
+    pub settings: Option<Settings>,
+    /// Settings of older API versions, that are sent as strings directly
+    /// within the ruleset.
+    #[serde(flatten)]
+    pub legacy: RulesetSettings,
+}
+
+impl Ruleset {
+    /// Returns the health damage of hazards, falling back to the default
+    /// if the request did not specify it.
+    pub fn hazard_damage(&self) -> u8 {
+        self.legacy
+            .damage_per_turn
+            .or(self.settings.as_ref().map(|s| s.hazard_damage))
+            .map_or(HAZARD_DAMAGE, |d| d.min(u8::MAX as _) as u8)
+    }
+}
+
+/// Ruleset settings of older API versions.
+///
+/// Example: `{"damagePerTurn": "14", "foodSpawnChance": "15", ...}`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RulesetSettings {
+    /// Health damage a snake will take when ending its turn in a hazard.
+    #[serde(default, deserialize_with = "from_str_or_num")]
+    pub damage_per_turn: Option<usize>,
+    /// Percentage chance of spawning a new food every round.
+    #[serde(default, deserialize_with = "from_str_or_num")]
+    pub food_spawn_chance: Option<usize>,
+    /// Minimum food to keep on the board every turn.
+    #[serde(default, deserialize_with = "from_str_or_num")]
+    pub minimum_food: Option<usize>,
+    /// Number of turns between generating new hazards.
+    #[serde(default, deserialize_with = "from_str_or_num")]
+    pub shrink_every_n_turns: Option<usize>,
+}
+
+/// Parses numbers that might be encoded as strings.
+fn from_str_or_num<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StrOrNum {
+        Str(String),
+        Num(usize),
+    }
+
+    match Option::<StrOrNum>::deserialize(deserializer)? {
+        Some(StrOrNum::Str(s)) => s.parse().map(Some).map_err(serde::de::Error::custom),
+        Some(StrOrNum::Num(n)) => Ok(Some(n)),
+        None => Ok(None),
+    }
 }
 
 /// A collection of specific settings being used by the current game
@@ -230,6 +307,26 @@ pub struct Battlesnake {
     pub body: Vec<Vec2D>,
     #[serde(default)]
     pub shout: String,
+    /// Teammates share the same squad, empty if there are no teams.
+    #[serde(default)]
+    pub squad: String,
+    /// Round trip time of the previous move in ms, zero if unknown.
+    #[serde(default, deserialize_with = "deserialize_latency")]
+    pub latency: u64,
+}
+
+/// The latency is reported as string (or number), which is empty if unknown.
+fn deserialize_latency<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Latency {
+        Number(u64),
+        Text(String),
+    }
+    Ok(match Latency::deserialize(deserializer)? {
+        Latency::Number(latency) => latency,
+        Latency::Text(latency) => latency.parse().unwrap_or_default(),
+    })
 }
 
 impl PartialEq for Battlesnake {
@@ -287,6 +384,123 @@ impl fmt::Display for GameRequest {
     }
 }
 
+/// Board exported by the community board editor.
+///
+/// Unlike the API, the snakes might have a separate `head`, and most fields
+/// are optional. The board might also be nested in a `board` object.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum EditorBoard {
+    Nested { board: EditorBoardData },
+    Flat(EditorBoardData),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EditorBoardData {
+    pub width: usize,
+    pub height: usize,
+    #[serde(default)]
+    pub turn: usize,
+    #[serde(default)]
+    pub ruleset: Option<String>,
+    #[serde(default)]
+    pub food: Vec<Vec2D>,
+    #[serde(default)]
+    pub hazards: Vec<Vec2D>,
+    #[serde(default)]
+    pub snakes: Vec<EditorSnake>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EditorSnake {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "EditorSnake::default_health")]
+    pub health: u8,
+    #[serde(default)]
+    pub head: Option<Vec2D>,
+    /// head to tail, the head might be missing
+    #[serde(default)]
+    pub body: Vec<Vec2D>,
+}
+
+impl EditorSnake {
+    fn default_health() -> u8 {
+        100
+    }
+}
+
+impl From<EditorBoard> for GameRequest {
+    /// Converts the board into a request, where the first snake is `you`.
+    fn from(board: EditorBoard) -> Self {
+        let board = match board {
+            EditorBoard::Nested { board } | EditorBoard::Flat(board) => board,
+        };
+        let snakes: Vec<Battlesnake> = board
+            .snakes
+            .into_iter()
+            .enumerate()
+            .map(|(i, snake)| {
+                let mut body = snake.body;
+                if let Some(head) = snake.head {
+                    if body.first() != Some(&head) {
+                        body.insert(0, head);
+                    }
+                }
+                let id = if snake.id.is_empty() {
+                    format!("snake-{i}")
+                } else {
+                    snake.id
+                };
+                Battlesnake {
+                    name: if snake.name.is_empty() {
+                        id.clone()
+                    } else {
+                        snake.name
+                    },
+                    id,
+                    health: snake.health,
+                    body,
+                    shout: String::new(),
+                    squad: String::new(),
+                    latency: 0,
+                }
+            })
+            .collect();
+        let you = snakes.first().cloned().unwrap_or_else(|| Battlesnake {
+            id: String::new(),
+            name: String::new(),
+            health: 0,
+            body: Vec::new(),
+            shout: String::new(),
+            squad: String::new(),
+            latency: 0,
+        });
+        GameRequest {
+            game: GameData {
+                id: "editor".into(),
+                ruleset: Ruleset {
+                    name: board.ruleset.unwrap_or_else(|| "standard".into()),
+                    ..Default::default()
+                },
+                timeout: 500,
+                source: "editor".into(),
+            },
+            turn: board.turn,
+            board: Board {
+                height: board.height,
+                width: board.width,
+                food: board.food,
+                hazards: board.hazards,
+                snakes,
+            },
+            you,
+        }
+    }
+}
+
 /// This response configures the battlesnake and its appearance.
 #[derive(Serialize, Debug)]
 pub struct IndexResponse<'a> {
@@ -319,11 +533,15 @@ impl<'a> IndexResponse<'a> {
 }
 
 /// Game response with the direction in which a snake has decided to move.
-#[derive(Serialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[must_use]
 pub struct MoveResponse {
     pub r#move: Direction,
+    #[serde(default)]
     pub shout: String,
+    /// Reached depth and value of the search, only used for debugging.
+    #[serde(skip)]
+    pub search: Option<(usize, f64)>,
 }
 
 impl MoveResponse {
@@ -331,9 +549,61 @@ impl MoveResponse {
         Self {
             r#move,
             shout: String::new(),
+            search: None,
         }
     }
     pub fn shout(r#move: Direction, shout: String) -> Self {
-        Self { r#move, shout }
+        Self {
+            r#move,
+            shout,
+            search: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn direction_all() {
+        use super::*;
+        logging();
+
+        let expected = [
+            Direction::Up,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+        ];
+        info!("{:?}", Direction::all());
+        assert_eq!(Direction::all(), expected);
+        assert!(Direction::iter().eq(expected));
+        for (i, d) in Direction::iter().enumerate() {
+            assert_eq!(Direction::from(i as u8), d);
+        }
+    }
+
+    #[test]
+    fn manhattan_wrapped() {
+        use super::*;
+        logging();
+
+        let (a, b) = (v2(0, 5), v2(10, 6));
+        info!(
+            "{} vs {}",
+            (a - b).manhattan(),
+            a.manhattan_wrapped(b, 11, 11)
+        );
+        assert_eq!((a - b).manhattan(), 11);
+        assert_eq!(a.manhattan_wrapped(b, 11, 11), 2);
+        assert_eq!(b.manhattan_wrapped(a, 11, 11), 2);
+        // Corners are neighbors over both edges
+        assert_eq!(v2(0, 0).manhattan_wrapped(v2(10, 10), 11, 11), 2);
+        // Closer directly
+        assert_eq!(v2(3, 3).manhattan_wrapped(v2(5, 4), 11, 11), 3);
+        assert_eq!(v2(3, 3).manhattan_wrapped(v2(3, 3), 11, 11), 0);
     }
 }
@@ -2,8 +2,8 @@ use std::collections::VecDeque;
 use std::mem::size_of;
 use std::ops::{Index, IndexMut};
 
-use crate::env::{Direction, Vec2D, HAZARD_DAMAGE};
-use crate::game::Snake;
+use crate::env::{Direction, Vec2D};
+use crate::game::{Game, Snake};
 use crate::grid::{CellT, Grid};
 use crate::util::FixedVec;
 
@@ -17,6 +17,9 @@ pub enum FCell {
         id: u8,
         /// Distance from the tail
         tail_dist: u16,
+        /// Additional turns the segment remains for other snakes,
+        /// if its snake is expected to eat.
+        growth: u16,
     },
     Owned {
         id: u8,
@@ -41,7 +44,7 @@ impl std::fmt::Debug for FCell {
         }
 
         match self {
-            FCell::Occupied { id, tail_dist } => {
+            FCell::Occupied { id, tail_dist, .. } => {
                 write!(f, "{:0>3}", tail_dist.color(id_color(*id)))
             }
             FCell::Owned { id, distance, .. } => {
@@ -82,27 +85,75 @@ impl SnakePos {
 /// This struct also contains all necessary buffers for the floodfill algorithm.
 pub struct FloodFill {
     cells: Vec<FCell>,
+    queue: VecDeque<SnakePos>,
     pub width: usize,
     pub height: usize,
+    /// Cells that are farther away from the heads are not flooded and
+    /// remain free, which bounds the cost on large boards.
+    pub max_distance: u16,
+    /// Flood across the board edges, as in the `wrapped` ruleset.
+    pub wrapped: bool,
+    /// Number of owned cells and their summed health per snake,
+    /// maintained while flooding.
+    space: Vec<usize>,
+    health: Vec<usize>,
 }
 
 impl FloodFill {
     #[must_use]
     pub fn new(width: usize, height: usize) -> Self {
+        // Distances and lengths are stored as u16
+        debug_assert!(width * height <= u16::MAX as usize);
         Self {
             cells: vec![FCell::Free; width * height],
+            // Assuming there are at most n^2 elements in the queue
+            queue: VecDeque::with_capacity(width * height),
             width,
             height,
+            max_distance: u16::MAX,
+            wrapped: false,
+            space: Vec::with_capacity(4),
+            health: Vec::with_capacity(4),
         }
     }
 
+    /// Creates the floodfill for the board of the game,
+    /// which wraps around the edges if the game does.
+    #[must_use]
+    pub fn for_game(game: &Game) -> Self {
+        let mut flood_fill = Self::new(game.grid.width, game.grid.height);
+        flood_fill.wrapped = game.wrapped;
+        flood_fill
+    }
+
     /// Returns if `p` is within the boundaries of the board.
     pub fn has(&self, p: Vec2D) -> bool {
         p.within(self.width, self.height)
     }
 
+    /// Applies the direction to `p`, wrapping around the edges if enabled.
+    #[inline]
+    fn apply(&self, grid: &Grid, p: Vec2D, d: Direction) -> Vec2D {
+        if self.wrapped {
+            grid.wrapping_apply(p, d)
+        } else {
+            p.apply(d)
+        }
+    }
+
     /// Counts the total health of you or the enemies.
     pub fn count_health(&self, i: u8) -> usize {
+        self.health.get(i as usize).copied().unwrap_or_default()
+    }
+
+    /// Counts the space of you or the enemies.
+    pub fn count_space(&self, i: u8) -> usize {
+        self.space.get(i as usize).copied().unwrap_or_default()
+    }
+
+    /// Like `count_health`, but scans all cells instead of using the
+    /// counts maintained during the flood.
+    pub fn scan_health(&self, i: u8) -> usize {
         self.cells
             .iter()
             .map(|&c| match c {
@@ -112,17 +163,100 @@ impl FloodFill {
             .sum()
     }
 
-    /// Counts the space of you or the enemies.
-    pub fn count_space(&self, i: u8) -> usize {
+    /// Like `count_space`, but scans all cells instead of using the
+    /// counts maintained during the flood.
+    pub fn scan_space(&self, i: u8) -> usize {
         self.cells
             .iter()
             .filter(|&c| matches!(c, FCell::Owned { id, .. } if *id == i))
             .count()
     }
 
+    /// Counts the space of you or the enemies, where every cell is weighted
+    /// by the inverse of its distance to the head (`1 / (distance + 1)`).
+    pub fn count_space_weighted(&self, i: u8) -> f64 {
+        self.cells
+            .iter()
+            .map(|&c| match c {
+                FCell::Owned { id, distance, .. } if id == i => 1.0 / (distance as f64 + 1.0),
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    /// Returns the owner of every cell (row-major from the bottom left),
+    /// or `None` if the cell is free or occupied.
+    pub fn ownership_grid(&self) -> Vec<Option<u8>> {
+        self.cells
+            .iter()
+            .map(|&c| match c {
+                FCell::Owned { id, .. } => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the distance of every cell (row-major from the bottom left)
+    /// to the head of its owner, or `None` if the cell is not owned.
+    pub fn distance_grid(&self) -> Vec<Option<u16>> {
+        self.cells
+            .iter()
+            .map(|&c| match c {
+                FCell::Owned { distance, .. } => Some(distance),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the snake that owns the cell, or `None` if the cell is free,
+    /// occupied, or outside of the board.
+    pub fn owner(&self, p: Vec2D) -> Option<u8> {
+        match self.has(p).then(|| self[p]) {
+            Some(FCell::Owned { id, .. }) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Returns the distance of the cell to the head of its owner,
+    /// or `None` if the cell is not owned.
+    pub fn distance(&self, p: Vec2D) -> Option<u16> {
+        match self.has(p).then(|| self[p]) {
+            Some(FCell::Owned { distance, .. }) => Some(distance),
+            _ => None,
+        }
+    }
+
     /// Clears the board so that it can be reused for another floodfill computation.
     pub fn clear(&mut self) {
         self.cells.fill(FCell::Free);
+        self.queue.clear();
+        self.space.fill(0);
+        self.health.fill(0);
+    }
+
+    /// Assigns the cell to the snake, updating the counts of the snakes.
+    fn own(&mut self, p: Vec2D, id: u8, health: u8, len: u16, distance: u16) {
+        if let FCell::Owned {
+            id: o_id,
+            health: o_health,
+            ..
+        } = self[p]
+        {
+            self.space[o_id as usize] -= 1;
+            self.health[o_id as usize] -= o_health as usize;
+        }
+        if id as usize >= self.space.len() {
+            self.space.resize(id as usize + 1, 0);
+            self.health.resize(id as usize + 1, 0);
+        }
+        self.space[id as usize] += 1;
+        self.health[id as usize] += health as usize;
+        self[p] = FCell::Owned {
+            id,
+            health,
+            len,
+            distance,
+        };
     }
 
     /// Flood fill combined with ignoring tails depending on distance to head.
@@ -146,9 +280,13 @@ impl FloodFill {
             match cell {
                 FCell::Free => true,
                 // Follow own tail
-                FCell::Occupied { id, tail_dist } if id == s_id => tail_dist + food <= s_distance,
+                FCell::Occupied { id, tail_dist, .. } if id == s_id => {
+                    tail_dist + food <= s_distance
+                }
                 // Follow enemy tail
-                FCell::Occupied { tail_dist, .. } => tail_dist <= s_distance, // <= enemy eats!
+                FCell::Occupied {
+                    tail_dist, growth, ..
+                } => tail_dist + growth <= s_distance, // <= enemy eats!
                 // Reached in same step?
                 FCell::Owned {
                     id,
@@ -168,9 +306,7 @@ impl FloodFill {
             }
         }
 
-        // Assuming there are at most n^2 elements in the queue
-        let mut queue = VecDeque::with_capacity(self.width * self.height);
-        queue.extend(heads);
+        self.queue.extend(heads);
 
         // Collect food on the way
         let mut food_distances = FixedVec::new();
@@ -182,10 +318,15 @@ impl FloodFill {
             food,
             len,
             health,
-        }) = queue.pop_front()
+        }) = self.queue.pop_front()
         {
+            // The queue is ordered by distance
+            if distance > self.max_distance {
+                self.queue.clear();
+                break;
+            }
             for d in Direction::all() {
-                let p = p.apply(d);
+                let p = self.apply(grid, p, d);
                 if !self.has(p) {
                     continue;
                 }
@@ -198,8 +339,7 @@ impl FloodFill {
                 let health = if is_food {
                     100
                 } else {
-                    let cost = if g_cell.hazard { HAZARD_DAMAGE } else { 1 };
-                    health.saturating_sub(cost)
+                    health.saturating_sub(grid.damage(p))
                 };
 
                 // Collect food
@@ -211,47 +351,245 @@ impl FloodFill {
                 let len = len + is_food as u16;
 
                 if health > 0 && owns(cell, id, distance, food, len, health) {
-                    self[p] = FCell::Owned {
-                        id,
-                        health,
-                        len,
-                        distance,
-                    };
-                    queue.push_back(SnakePos::new(p, id, distance + 1, food, len, health));
+                    self.own(p, id, health, len, distance);
+                    self.queue
+                        .push_back(SnakePos::new(p, id, distance + 1, food, len, health));
                 }
             }
         }
         food_distances
     }
 
-    /// Prepare the board and compute flood fill.
-    /// It is assumed that the snake at position and id 0 is the evaluated
-    /// agent and the other snakes are the enemies.
-    pub fn flood_snakes(&mut self, grid: &Grid, snakes: &[Snake]) -> FixedVec<u16, 4> {
+    /// Clears the board and adds the snake bodies (tail = 0, ..., head = n - 1).
+    ///
+    /// Stacked segments (at the start or after eating) are a single cell,
+    /// which vacates together with its last segment.
+    fn prepare(&mut self, snakes: &[Snake]) {
         self.clear();
-
-        // Prepare board with snakes (tail = 1, ..., head = n)
         for (id, snake) in snakes.iter().enumerate() {
             for (i, p) in snake.body.iter().enumerate() {
+                // Later segments overwrite the stacked ones before them
                 self[*p] = FCell::Occupied {
                     id: id as _,
-                    tail_dist: i as u16,
+                    tail_dist: i as _,
+                    growth: 0,
                 }
             }
         }
+    }
 
-        // Longer or equally long snakes first
+    /// Returns the size of the region our snake (id 0) enters by moving to `from`.
+    /// Zero is returned if `from` cannot be entered.
+    ///
+    /// Like `flood_snakes` this follows the vanishing tails and stops where
+    /// our snake would starve, but ignores the enemy heads.
+    /// This can be used to reject moves into pockets smaller than the snake.
+    pub fn pocket_size(&mut self, grid: &Grid, snakes: &[Snake], from: Vec2D) -> usize {
+        self.prepare(snakes);
+
+        let Some(snake) = snakes.first().filter(|s| s.alive()) else {
+            return 0;
+        };
+        if !self.has(from)
+            || !matches!(
+                self[from],
+                FCell::Free | FCell::Occupied { tail_dist: 0, .. }
+            )
+        {
+            return 0;
+        }
+
+        let is_food = grid[from].t == CellT::Food;
+        let food = is_food as u16;
+        let len = snake.body.len() as u16 + food;
+        let health = if is_food {
+            100
+        } else {
+            snake.health.saturating_sub(grid.damage(from))
+        };
+        // Starving on the first step
+        if health == 0 {
+            return 0;
+        }
+        self.own(from, 0, health, len, 0);
         self.flood(
             grid,
-            snakes
-                .iter()
-                .enumerate()
-                .filter(|&(_, s)| s.alive())
-                .map(|(id, s)| SnakePos::new(s.head(), id as _, 0, 0, s.body.len() as _, s.health)),
-        )
+            [SnakePos::new(from, 0, 1, food, len, health)].into_iter(),
+        );
+        self.count_space(0)
+    }
+
+    /// Prepare the board and compute flood fill.
+    /// It is assumed that the snake at position and id 0 is the evaluated
+    /// agent and the other snakes are the enemies.
+    pub fn flood_snakes(&mut self, grid: &Grid, snakes: &[Snake]) -> FixedVec<u16, 4> {
+        self.prepare(snakes);
+        self.flood_alive(grid, snakes)
+    }
+
+    /// Like `flood_snakes`, but enemies are expected to eat the nearest food,
+    /// which delays their vanishing tails by one turn after eating.
+    ///
+    /// This is more conservative in the space near contested food.
+    pub fn flood_snakes_growing(&mut self, grid: &Grid, snakes: &[Snake]) -> FixedVec<u16, 4> {
+        self.prepare(snakes);
+        self.grow_enemies(grid, snakes);
+        self.flood_alive(grid, snakes)
+    }
+
+    fn flood_alive(&mut self, grid: &Grid, snakes: &[Snake]) -> FixedVec<u16, 4> {
+        // Longer or equally long snakes first
+        self.flood(grid, heads(snakes))
+    }
+
+    /// Delays the body segments of the enemies that vanish after they could
+    /// have eaten, assuming they head straight to the nearest food.
+    fn grow_enemies(&mut self, grid: &Grid, snakes: &[Snake]) {
+        let food: Vec<Vec2D> = (0..grid.height)
+            .flat_map(|y| (0..grid.width).map(move |x| Vec2D::new(x as _, y as _)))
+            .filter(|&p| grid[p].t == CellT::Food)
+            .collect();
+        // Move with which the enemies eat at the earliest
+        let distance = |a: Vec2D, b: Vec2D| {
+            if self.wrapped {
+                a.manhattan_wrapped(b, self.width, self.height)
+            } else {
+                (a - b).manhattan()
+            }
+        };
+        let eats: Vec<Option<u16>> = snakes
+            .iter()
+            .enumerate()
+            .map(|(id, s)| {
+                let head = s.try_head().filter(|_| id > 0 && s.alive())?;
+                food.iter().map(|&f| distance(f, head) as u16).min()
+            })
+            .collect();
+        // The segment that vanishes with move i + 1 stays one move longer
+        for cell in &mut self.cells {
+            if let FCell::Occupied {
+                id,
+                tail_dist,
+                growth,
+            } = cell
+            {
+                if eats[*id as usize].is_some_and(|m| *tail_dist >= m) {
+                    *growth = 1;
+                }
+            }
+        }
+    }
+
+    /// Computes the voronoi partition of the board by head distance.
+    ///
+    /// In contrast to `flood_snakes`, the snake bodies are static obstacles
+    /// and neither tails nor food are considered.
+    /// Every free cell is owned by the snake that reaches it first.
+    /// Ties go to the longer snake (on draw we loose).
+    pub fn voronoi(&mut self, grid: &Grid, snakes: &[Snake]) {
+        self.prepare(snakes);
+        self.flood_heads(grid, heads(snakes));
+    }
+
+    /// Computes the distances of all cells to the nearest enemy head,
+    /// which can be queried with `enemy_distance_grid`.
+    ///
+    /// Like `voronoi`, the snake bodies are static obstacles.
+    pub fn flood_enemies(&mut self, grid: &Grid, snakes: &[Snake]) {
+        self.prepare(snakes);
+        self.flood_heads(grid, heads(snakes).filter(|s| s.id > 0));
+    }
+
+    /// Returns the number of steps the nearest enemy needs to reach every
+    /// cell (row-major from the bottom left), or `u16::MAX` if unreachable.
+    ///
+    /// Only valid after `flood_enemies`.
+    pub fn enemy_distance_grid(&self) -> Vec<u16> {
+        self.cells
+            .iter()
+            .map(|&c| match c {
+                FCell::Owned { distance, .. } => distance + 1,
+                _ => u16::MAX,
+            })
+            .collect()
+    }
+
+    /// Multi-source BFS from the given heads, where the bodies are static.
+    fn flood_heads(&mut self, grid: &Grid, heads: impl Iterator<Item = SnakePos>) {
+        self.queue.extend(heads);
+
+        while let Some(SnakePos {
+            p,
+            id,
+            distance,
+            len,
+            health,
+            ..
+        }) = self.queue.pop_front()
+        {
+            // The queue is ordered by distance
+            if distance > self.max_distance {
+                self.queue.clear();
+                break;
+            }
+            for d in Direction::all() {
+                let p = self.apply(grid, p, d);
+                if !grid.has(p) {
+                    continue;
+                }
+
+                let owns = match self[p] {
+                    FCell::Free => true,
+                    FCell::Occupied { .. } => false,
+                    FCell::Owned {
+                        id: o_id,
+                        len: o_len,
+                        distance: o_distance,
+                        ..
+                    } => {
+                        o_distance == distance
+                            && o_id != id
+                            && (o_len < len || o_len == len && o_id < id)
+                    }
+                };
+
+                if owns {
+                    self.own(p, id, health, len, distance);
+                    self.queue
+                        .push_back(SnakePos::new(p, id, distance + 1, 0, len, health));
+                }
+            }
+        }
     }
 }
 
+/// Starting positions of the living snakes, skipping those without a body.
+fn heads(snakes: &[Snake]) -> impl Iterator<Item = SnakePos> + '_ {
+    snakes
+        .iter()
+        .enumerate()
+        .filter(|&(_, s)| s.alive())
+        .filter_map(|(id, s)| {
+            let head = s.try_head()?;
+            Some(SnakePos::new(
+                head,
+                id as _,
+                0,
+                0,
+                s.body.len() as _,
+                s.health,
+            ))
+        })
+}
+
+/// Scores the food we own, given the distances returned by `flood_snakes`.
+///
+/// Every food is weighted by the inverse of its distance (`1 / (d + 1)`),
+/// which is 1 next to our head.
+pub fn owned_food_score(food_distances: &[u16]) -> f64 {
+    food_distances.iter().map(|&d| 1.0 / (d as f64 + 1.0)).sum()
+}
+
 impl Index<Vec2D> for FloodFill {
     type Output = FCell;
 
@@ -490,6 +828,85 @@ mod test {
         assert_eq!(floodfill.count_space(0), 24);
     }
 
+    #[test]
+    fn flood_snakes_growing() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            0 . . . . . .
+            ^ v < . . . .
+            ^ v . . . . .
+            ^ 1 o . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        let space = floodfill.count_space(0);
+        info!("Filled {space} {floodfill:?}");
+        floodfill.flood_snakes_growing(&game.grid, &game.snakes);
+        let growing = floodfill.count_space(0);
+        info!("Filled {growing} {floodfill:?}");
+        // The enemy may eat with its first move, we follow its tail later
+        assert!(growing < space);
+
+        // No difference without food
+        let mut game = game;
+        game.grid[Vec2D::new(2, 0)].t = CellT::Free;
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        let space = floodfill.count_space(0);
+        floodfill.flood_snakes_growing(&game.grid, &game.snakes);
+        assert_eq!(floodfill.count_space(0), space);
+    }
+
+    #[test]
+    fn flood_max_distance() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . 0 < < . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        let unbounded = floodfill.count_space(0);
+
+        floodfill.max_distance = 2;
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        let bounded = floodfill.count_space(0);
+        info!("Filled {bounded} of {unbounded} {floodfill:?}");
+        assert_eq!(unbounded, 11 * 11);
+        assert!(bounded < unbounded);
+        // The cells up to three steps away, except the head, the tail,
+        // and the cell behind it
+        assert_eq!(bounded, 1 + 4 + 8 + 12 - 3);
+        assert!(floodfill
+            .distance_grid()
+            .into_iter()
+            .flatten()
+            .all(|d| d <= 2));
+    }
+
     #[test]
     fn flood_snakes_low_health() {
         use super::*;
@@ -519,6 +936,46 @@ mod test {
         assert_eq!(floodfill.count_space(0), 59);
     }
 
+    #[test]
+    fn flood_snakes_starve() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 0 < < .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("Filled {} {floodfill:?}", floodfill.count_space(0));
+        assert_eq!(floodfill.count_space(0), 7 * 7);
+
+        // Starves after 3 moves
+        game.snakes[0].health = 4;
+        let head = game.snakes[0].head();
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("Filled {} {floodfill:?}", floodfill.count_space(0));
+        assert_eq!(floodfill.count_space(0), 22);
+
+        let pocket = floodfill.pocket_size(&game.grid, &game.snakes, head.apply(Direction::Left));
+        info!("Pocket {pocket} {floodfill:?}");
+        assert_eq!(pocket, 11);
+
+        // Starves on the first move
+        game.snakes[0].health = 1;
+        let pocket = floodfill.pocket_size(&game.grid, &game.snakes, head.apply(Direction::Left));
+        assert_eq!(pocket, 0);
+    }
+
     #[test]
     fn flood_snakes_hazard() {
         use super::*;
@@ -543,7 +1000,7 @@ mod test {
         game.snakes[0].health = 50;
         for y in 0..game.grid.height {
             for x in game.grid.width / 2 + 1..game.grid.width {
-                game.grid[Vec2D::new(x as _, y as _)].hazard = true;
+                game.grid[Vec2D::new(x as _, y as _)].hazard = 1;
             }
         }
 
@@ -603,4 +1060,554 @@ mod test {
         info!("Filled {} {floodfill:?}", floodfill.count_space(0));
         assert_eq!(35, floodfill.count_space(0));
     }
+
+    #[test]
+    fn voronoi_escape() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . 1 < < < v . . . .
+            o 0 < < < ^ v . . . .
+            > > > > ^ ^ < . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        let flood = (floodfill.count_space(0), floodfill.count_space(1));
+        floodfill.voronoi(&game.grid, &game.snakes);
+        let voronoi = (floodfill.count_space(0), floodfill.count_space(1));
+        info!("flood {flood:?} voronoi {voronoi:?} {floodfill:?}");
+        assert_eq!(flood, (29, 92));
+        // Without tail following we cannot escape
+        assert_eq!(voronoi, (1, 102));
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . 1 < < < < . . . . .
+            0 < < < < ^ v . . . .
+            > > > > ^ ^ < . . . ."#,
+        )
+        .unwrap();
+
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        let flood = (floodfill.count_space(0), floodfill.count_space(1));
+        floodfill.voronoi(&game.grid, &game.snakes);
+        let voronoi = (floodfill.count_space(0), floodfill.count_space(1));
+        info!("flood {flood:?} voronoi {voronoi:?} {floodfill:?}");
+        assert_eq!(flood, (35, 86));
+        assert_eq!(voronoi, (9, 93));
+    }
+
+    #[test]
+    fn pocket_escape() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . 1 < < < v . . . .
+            o 0 < < < ^ v . . . .
+            > > > > ^ ^ < . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        let head = game.snakes[0].head();
+        let pockets = Direction::all()
+            .map(|d| floodfill.pocket_size(&game.grid, &game.snakes, head.apply(d)));
+        info!("pockets {pockets:?}");
+        assert_eq!(pockets, [121, 0, 0, 121]);
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . 1 < < < < . . . . .
+            0 < < < < ^ v . . . .
+            > > > > ^ ^ < . . . ."#,
+        )
+        .unwrap();
+
+        let head = game.snakes[0].head();
+        let pockets = Direction::all()
+            .map(|d| floodfill.pocket_size(&game.grid, &game.snakes, head.apply(d)));
+        info!("pockets {pockets:?}");
+        assert_eq!(pockets, [121, 0, 121, 0]);
+
+        // Trapped within our own body
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            v . . . . . . . . . .
+            v . . . . . . . . . .
+            v . . . . . . . . . .
+            > > > v . . . . . . .
+            . . . v . . . . . . .
+            . 0 < < . . . . . . ."#,
+        )
+        .unwrap();
+
+        let head = game.snakes[0].head();
+        let pockets = Direction::all()
+            .map(|d| floodfill.pocket_size(&game.grid, &game.snakes, head.apply(d)));
+        info!("pockets {pockets:?}");
+        assert_eq!(pockets, [4, 0, 0, 4]);
+        assert!(pockets.iter().all(|&p| p < game.snakes[0].body.len()));
+    }
+
+    #[test]
+    fn flood_repeated() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            1 . . . . . . . . . .
+            ^ v . . . . . . . . .
+            ^ > v . . . . . . . .
+            . 0 < . . . . . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        let expected = (floodfill.count_space(0), floodfill.count_space(1));
+        info!("Filled {expected:?}");
+
+        // The reused buffers do not change the results
+        for _ in 0..4 {
+            floodfill.flood_snakes(&game.grid, &game.snakes);
+            assert_eq!(
+                (floodfill.count_space(0), floodfill.count_space(1)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn flood_food_score() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let score = |board: &str| {
+            let game = Game::parse(board).unwrap();
+            let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+            let food_distances = floodfill.flood_snakes(&game.grid, &game.snakes);
+            owned_food_score(&food_distances)
+        };
+        let far = score(
+            r#"
+            . . . . . . o
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . 0 < < . . .
+            . . . . . . .
+            . . . . . . ."#,
+        );
+        let near = score(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . o . . .
+            . . . . . . .
+            . 0 < < . . .
+            . . . . . . .
+            . . . . . . ."#,
+        );
+        let next = score(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . o . . . . .
+            . 0 < < . . .
+            . . . . . . .
+            . . . . . . ."#,
+        );
+        info!("far {far} near {near} next {next}");
+        assert!(0.0 < far && far < near && near < next);
+        assert_eq!(next, 1.0);
+
+        // More food is better
+        assert!(owned_food_score(&[3, 5]) > owned_food_score(&[3]));
+        assert_eq!(owned_food_score(&[3]), 0.25);
+        assert_eq!(owned_food_score(&[]), 0.0);
+    }
+
+    #[test]
+    fn flood_owner() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . 1 < < <
+            . . . . . . .
+            . 0 < < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("{floodfill:?}");
+
+        // Next to the heads
+        assert_eq!(floodfill.owner(Vec2D::new(0, 2)), Some(0));
+        assert_eq!(floodfill.distance(Vec2D::new(0, 2)), Some(0));
+        assert_eq!(floodfill.owner(Vec2D::new(3, 5)), Some(1));
+        assert_eq!(floodfill.distance(Vec2D::new(3, 5)), Some(0));
+        // Reached at the same time by equally long snakes
+        assert_eq!(floodfill.owner(Vec2D::new(2, 3)), Some(1));
+        assert_eq!(floodfill.distance(Vec2D::new(2, 3)), Some(1));
+        // Far away from the enemy
+        assert_eq!(floodfill.owner(Vec2D::new(0, 0)), Some(0));
+        assert_eq!(floodfill.distance(Vec2D::new(0, 0)), Some(2));
+        // Our head, after the enemy followed our tail
+        assert_eq!(floodfill.owner(Vec2D::new(1, 2)), Some(1));
+        assert_eq!(floodfill.distance(Vec2D::new(1, 2)), Some(3));
+        // Outside
+        assert_eq!(floodfill.owner(Vec2D::new(-1, 0)), None);
+        assert_eq!(floodfill.distance(Vec2D::new(7, 0)), None);
+
+        // Consistent with the grids
+        let owners = floodfill.ownership_grid();
+        let distances = floodfill.distance_grid();
+        for y in 0..game.grid.height {
+            for x in 0..game.grid.width {
+                let p = Vec2D::new(x as _, y as _);
+                assert_eq!(floodfill.owner(p), owners[x + y * game.grid.width]);
+                assert_eq!(floodfill.distance(p), distances[x + y * game.grid.width]);
+            }
+        }
+
+        // Beyond the horizon
+        floodfill.max_distance = 1;
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        assert_eq!(floodfill.owner(Vec2D::new(0, 0)), None);
+        assert_eq!(floodfill.distance(Vec2D::new(0, 0)), None);
+    }
+
+    #[test]
+    fn flood_wrapped() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . v . .
+            . . v . .
+            0 < < . ."#,
+        )
+        .unwrap();
+
+        // The right side is only reachable after the tail vanished
+        let mut floodfill = FloodFill::for_game(&game);
+        assert!(!floodfill.wrapped);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("{floodfill:?}");
+        assert_eq!(floodfill.distance(Vec2D::new(4, 0)), Some(5));
+
+        // Or across the left edge
+        game.wrapped = true;
+        let mut floodfill = FloodFill::for_game(&game);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("{floodfill:?}");
+        assert_eq!(floodfill.distance(Vec2D::new(4, 0)), Some(0));
+        assert_eq!(floodfill.distance(Vec2D::new(0, 2)), Some(0));
+        let head = game.snakes[0].head();
+        let pocket =
+            floodfill.pocket_size(&game.grid, &game.snakes, game.apply(head, Direction::Left));
+        assert_eq!(pocket, 15);
+    }
+
+    #[test]
+    fn flood_ownership() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . 0 . . . 1 .
+            . ^ . . . ^ .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("{floodfill:?}");
+
+        let owners = floodfill.ownership_grid();
+        let distances = floodfill.distance_grid();
+        let owner = |x: usize, y: usize| owners[x + y * game.grid.width];
+        let distance = |x: usize, y: usize| distances[x + y * game.grid.width];
+
+        // The boundary between the territories
+        assert_eq!(owner(2, 3), Some(0));
+        assert_eq!(owner(4, 3), Some(1));
+        assert_eq!(distance(2, 3), Some(0));
+        assert_eq!(distance(4, 3), Some(0));
+        // Reached at the same time by equally long snakes
+        assert_eq!(owner(3, 3), Some(1));
+        assert_eq!(distance(3, 3), Some(1));
+        assert_eq!(
+            owners.iter().filter(|&&o| o == Some(0)).count(),
+            floodfill.count_space(0)
+        );
+    }
+
+    #[test]
+    fn flood_enemy_distance() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . 1 . . .
+            . . . ^ . . .
+            . . . . . . 0
+            . . . . . . ^"#,
+        )
+        .unwrap();
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_enemies(&game.grid, &game.snakes);
+        info!("{floodfill:?}");
+
+        let distances = floodfill.enemy_distance_grid();
+        let head = game.snakes[1].head();
+        for corner in [Vec2D::new(0, 0), Vec2D::new(0, 6), Vec2D::new(6, 6)] {
+            let distance = distances[corner.x as usize + corner.y as usize * game.grid.width];
+            assert_eq!(distance as u64, (corner - head).manhattan());
+        }
+        // Our body is not reachable
+        assert_eq!(distances[6], u16::MAX);
+        // Our head is not a source
+        assert_eq!(floodfill.count_space(0), 0);
+    }
+
+    #[test]
+    fn flood_snakes_stacked() {
+        use super::*;
+        use crate::env::v2;
+        use crate::game::Game;
+        logging();
+
+        // Freshly started snake, all segments on the same cell
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . 0 . .
+            . . . . .
+            . . . . ."#,
+        )
+        .unwrap();
+        let start = v2(2, 2);
+        assert_eq!(game.snakes[0].body, [start; 3]);
+        assert!(!game.snakes[0].tail_moves());
+        assert_eq!(game.valid_moves(0).count(), 4);
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("Filled {} {floodfill:?}", floodfill.count_space(0));
+        // Only the occupied start cell is missing
+        assert_eq!(floodfill.count_space(0), 5 * 5 - 1);
+
+        // The stacked tail stays for another turn
+        game.step(&[Direction::Up]);
+        assert_eq!(game.snakes[0].body, [start, start, v2(2, 3)]);
+        assert!(!game.snakes[0].tail_moves());
+        assert!(!game.move_is_valid(0, Direction::Down));
+        assert_eq!(game.valid_moves(0).count(), 3);
+        floodfill.prepare(&game.snakes);
+        assert_eq!(
+            floodfill[start],
+            FCell::Occupied {
+                id: 0,
+                tail_dist: 1,
+                growth: 0,
+            }
+        );
+        assert_eq!(floodfill.pocket_size(&game.grid, &game.snakes, start), 0);
+        let pocket = floodfill.pocket_size(&game.grid, &game.snakes, v2(2, 4));
+        info!("Pocket {pocket} {floodfill:?}");
+        assert_eq!(pocket, 5 * 5);
+
+        let mut back = game.clone();
+        back.step(&[Direction::Down]);
+        assert!(!back.snake_is_alive(0));
+
+        // Now the body is spread out
+        game.step(&[Direction::Right]);
+        assert_eq!(game.snakes[0].body, [start, v2(2, 3), v2(3, 3)]);
+        assert!(game.snakes[0].tail_moves());
+        assert_eq!(game.valid_moves(0).count(), 3);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("Filled {} {floodfill:?}", floodfill.count_space(0));
+        assert_eq!(floodfill.count_space(0), 5 * 5);
+    }
+
+    #[test]
+    fn flood_maintained_counts() {
+        use super::*;
+        use crate::env::v2;
+        use crate::game::{Game, Outcome};
+        use crate::simulation::init_game;
+        use rand::rngs::SmallRng;
+        use rand::seq::IteratorRandom;
+        use rand::SeedableRng;
+        logging();
+
+        let check = |floodfill: &FloodFill, game: &Game| {
+            for i in 0..game.snakes.len() as u8 {
+                assert_eq!(floodfill.count_space(i), floodfill.scan_space(i));
+                assert_eq!(floodfill.count_health(i), floodfill.scan_health(i));
+            }
+        };
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut floodfill = FloodFill::new(11, 11);
+        let mut positions = 0;
+        for _ in 0..8 {
+            let mut game = init_game(11, 11, 4, &mut rng);
+            game.grid.add_hazards(&[v2(0, 0), v2(5, 5), v2(10, 3)]);
+            game.grid.hazard_damage = 14;
+            while game.outcome() == Outcome::None {
+                floodfill.max_distance = u16::MAX;
+                floodfill.flood_snakes(&game.grid, &game.snakes);
+                check(&floodfill, &game);
+                floodfill.flood_snakes_growing(&game.grid, &game.snakes);
+                check(&floodfill, &game);
+                floodfill.voronoi(&game.grid, &game.snakes);
+                check(&floodfill, &game);
+                floodfill.flood_enemies(&game.grid, &game.snakes);
+                check(&floodfill, &game);
+                if game.snake_is_alive(0) {
+                    let head = game.snakes[0].head();
+                    for d in Direction::all() {
+                        floodfill.pocket_size(&game.grid, &game.snakes, head.apply(d));
+                        check(&floodfill, &game);
+                    }
+                }
+                floodfill.max_distance = 3;
+                floodfill.flood_snakes(&game.grid, &game.snakes);
+                check(&floodfill, &game);
+
+                let moves: Vec<Direction> = (0..game.snakes.len() as u8)
+                    .map(|i| game.valid_moves(i).choose(&mut rng).unwrap_or_default())
+                    .collect();
+                game.step(&moves);
+                positions += 1;
+            }
+        }
+        info!("{positions} positions");
+        assert!(positions > 100);
+    }
+
+    #[test]
+    fn flood_empty_bodies() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . 1 < < .
+            . . . 0 < < <
+            . . . . . . .
+            . . 2 . . . .
+            . . ^ . . . ."#,
+        )
+        .unwrap();
+        // Dead snakes have no body
+        game.snakes[1].health = 0;
+        game.snakes[1].body.clear();
+        // Not expected, but possible with a broken request
+        game.snakes[2].body.clear();
+        assert!(game.snakes[1].try_head().is_none());
+
+        let mut floodfill = FloodFill::new(game.grid.width, game.grid.height);
+        floodfill.flood_snakes(&game.grid, &game.snakes);
+        info!("{floodfill:?}");
+        assert!(floodfill.count_space(0) > 0);
+        assert_eq!(floodfill.count_space(1), 0);
+        assert_eq!(floodfill.count_space(2), 0);
+
+        floodfill.flood_snakes_growing(&game.grid, &game.snakes);
+        assert_eq!(floodfill.count_space(2), 0);
+        floodfill.voronoi(&game.grid, &game.snakes);
+        assert_eq!(floodfill.count_space(0), 7 * 7 - 4);
+        floodfill.flood_enemies(&game.grid, &game.snakes);
+        assert!(floodfill
+            .enemy_distance_grid()
+            .iter()
+            .all(|&d| d == u16::MAX));
+    }
 }
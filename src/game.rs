@@ -3,10 +3,19 @@ use std::collections::{BinaryHeap, VecDeque};
 use std::fmt::{self, Debug};
 
 use owo_colors::{AnsiColors, OwoColorize};
+use serde::{Deserialize, Serialize};
 
-use crate::env::{Battlesnake, Direction, GameRequest, Vec2D, HAZARD_DAMAGE};
+use crate::env::{
+    Battlesnake, Board, Direction, EditorBoard, GameData, GameRequest, Ruleset, Settings, Vec2D,
+};
 use crate::grid::{Cell, CellT, Grid};
+use crate::search::MAX_SNAKES;
 use crate::util::OrdPair;
+use crate::zobrist;
+
+/// Number of enemies kept by `Game::from_request`, the nearest ones if
+/// there are more.
+pub const MAX_ENEMIES: usize = 3;
 
 /// The outcome of a simulated game.
 /// If the game did not end the outcome is `None`.
@@ -18,15 +27,22 @@ pub enum Outcome {
 }
 
 /// Reduced representation of a snake.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Snake {
     /// tail to head
     pub body: VecDeque<Vec2D>,
     pub health: u8,
+    /// Teammates do not collide with each other (squad mode).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub squad: Option<u8>,
 }
 impl Snake {
     pub fn new(body: VecDeque<Vec2D>, health: u8) -> Self {
-        Self { body, health }
+        Self {
+            body,
+            health,
+            squad: None,
+        }
     }
 
     #[must_use]
@@ -38,20 +54,42 @@ impl Snake {
         self.health > 0
     }
 
+    /// Panics if the body is empty, like for dead snakes.
     pub fn head(&self) -> Vec2D {
         *self.body.back().unwrap()
     }
+
+    /// Returns the head or `None` if the body is empty.
+    pub fn try_head(&self) -> Option<Vec2D> {
+        self.body.back().copied()
+    }
+
+    /// Returns if the tail vacates its cell with the next move.
+    ///
+    /// This is not the case if the tail is stacked, like at the start of the
+    /// game or after eating, where multiple segments occupy the same cell.
+    pub fn tail_moves(&self) -> bool {
+        self.body.get(1) != self.body.front()
+    }
+
+    /// Returns if both snakes are in the same squad.
+    pub fn is_teammate(&self, other: &Snake) -> bool {
+        self.squad.is_some() && self.squad == other.squad
+    }
 }
 
 /// Game represents holds the complete game state.
 /// This also provides methods to execute moves and evaluate their outcome.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Game {
     pub turn: usize,
     pub grid: Grid,
     /// All snakes. Dead ones have health = 0 and no body.
     /// The ids have to be the same as the indices!
     pub snakes: Vec<Snake>,
+    /// Moving over the edge of the board re-enters on the opposite side.
+    #[serde(default)]
+    pub wrapped: bool,
 }
 
 impl Game {
@@ -73,35 +111,90 @@ impl Game {
             grid.add_snake(snake.body.iter().copied());
         }
 
-        Self { turn, snakes, grid }
+        Self {
+            turn,
+            grid,
+            snakes,
+            wrapped: false,
+        }
+    }
+
+    /// Loads the game state from a board of the community board editor.
+    /// The first snake is the player.
+    pub fn from_board_json(json: &str) -> Result<Self, serde_json::Error> {
+        let board: EditorBoard = serde_json::from_str(json)?;
+        Ok(Self::from_request(&board.into()))
     }
 
     /// Loads the game state from the provided request.
+    ///
+    /// If `you` is eliminated or missing from the board, snake 0 is dead.
     #[must_use]
     pub fn from_request(request: &GameRequest) -> Self {
-        let mut snakes = Vec::with_capacity(4);
-        snakes.push(Snake::from(&request.you));
+        Self::from_request_with(request, MAX_ENEMIES)
+    }
+
+    /// Loads the game state from the provided request, keeping only the
+    /// `max_enemies` nearest enemies.
+    #[must_use]
+    pub fn from_request_with(request: &GameRequest, max_enemies: usize) -> Self {
+        // Squads are numbered in the order of their first snake
+        let mut squads: Vec<&str> = Vec::new();
+        for snake in request.board.snakes.iter().chain([&request.you]) {
+            if !snake.squad.is_empty() && !squads.contains(&snake.squad.as_str()) {
+                squads.push(&snake.squad);
+            }
+        }
+        let snake = |s: &Battlesnake| {
+            let mut snake = Snake::from(s);
+            snake.squad = squads.iter().position(|&q| q == s.squad).map(|i| i as u8);
+            snake
+        };
+
+        let mut snakes = Vec::with_capacity(
+            max_enemies
+                .saturating_add(1)
+                .min(request.board.snakes.len() + 1),
+        );
+        // `you` might already be eliminated, which some proxies still send
+        let you_alive = request.you.health > 0
+            && !request.you.body.is_empty()
+            && request.board.snakes.iter().any(|s| s.id == request.you.id);
+        if you_alive {
+            snakes.push(snake(&request.you));
+        } else {
+            let mut you = snake(&request.you);
+            you.health = 0;
+            you.body.clear();
+            snakes.push(you);
+        }
 
-        // Only look at the nearest four snakes
-        if request.board.snakes.len() > 4 {
+        // Only look at the nearest enemies
+        let enemies = request
+            .board
+            .snakes
+            .iter()
+            .filter(|s| s.id != request.you.id)
+            .count();
+        if enemies > max_enemies {
             let mut queue = BinaryHeap::new();
             for snake in request
                 .board
                 .snakes
                 .iter()
                 .filter(|s| s.id != request.you.id)
-                .map(Snake::from)
+                .map(snake)
             {
                 let body_dist = snake
                     .body
                     .iter()
-                    .map(|&p| (p - snakes[0].head()).manhattan())
+                    .map(|&p| snakes[0].try_head().map_or(0, |h| (p - h).manhattan()))
                     .min()
                     .unwrap_or_default();
                 queue.push(OrdPair(Reverse(body_dist), snake));
             }
 
-            for _ in 1..3 {
+            for _ in 0..max_enemies {
                 if let Some(OrdPair(_, snake)) = queue.pop() {
                     snakes.push(snake);
                 }
@@ -113,11 +206,11 @@ impl Game {
                     .snakes
                     .iter()
                     .filter(|s| s.id != request.you.id)
-                    .map(Snake::from),
+                    .map(snake),
             );
         }
 
-        if request.game.ruleset.name == "constrictor" {
+        let mut game = if request.game.ruleset.name == "constrictor" {
             // Constrictor games just have food everywhere
             let food = (0..request.board.height)
                 .flat_map(|y| (0..request.board.width).map(move |x| Vec2D::new(x as _, y as _)))
@@ -140,6 +233,73 @@ impl Game {
                 &request.board.food,
                 &request.board.hazards,
             )
+        };
+        game.wrapped = request.game.ruleset.name == "wrapped";
+        game.grid.hazard_damage = request.game.ruleset.hazard_damage();
+        game
+    }
+
+    /// Converts the game into an API request, for which snake 0 is `you`.
+    ///
+    /// The snakes are named by their index (`snake-<i>`), dead snakes are
+    /// omitted.
+    pub fn to_request(&self, turn: usize, ruleset: &str) -> GameRequest {
+        let (width, height) = (self.grid.width, self.grid.height);
+        let positions =
+            (0..height).flat_map(|y| (0..width).map(move |x| Vec2D::new(x as _, y as _)));
+        let food = positions
+            .clone()
+            .filter(|&p| self.grid[p].t == CellT::Food)
+            .collect();
+        // Stacked hazards are repeated
+        let hazards = positions
+            .flat_map(|p| std::iter::repeat_n(p, self.grid[p].hazard as usize))
+            .collect();
+
+        let snake = |(i, snake): (usize, &Snake)| Battlesnake {
+            id: format!("snake-{i}"),
+            name: format!("snake-{i}"),
+            health: snake.health,
+            body: snake.body.iter().rev().copied().collect(),
+            shout: String::new(),
+            squad: snake
+                .squad
+                .map(|q| format!("squad-{q}"))
+                .unwrap_or_default(),
+            latency: 0,
+        };
+        let snakes: Vec<Battlesnake> = self
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.alive())
+            .map(snake)
+            .collect();
+        let you = snake((0, &self.snakes[0]));
+
+        GameRequest {
+            game: GameData {
+                id: "snork".into(),
+                ruleset: Ruleset {
+                    name: ruleset.into(),
+                    settings: Some(Settings {
+                        hazard_damage: self.grid.hazard_damage as _,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                timeout: 500,
+                source: "custom".into(),
+            },
+            turn,
+            board: Board {
+                height,
+                width,
+                food,
+                hazards,
+                snakes,
+            },
+            you,
         }
     }
 
@@ -157,10 +317,69 @@ impl Game {
         match living_snakes {
             0 => Outcome::Match,
             1 => Outcome::Winner(survivor),
+            // The last squad wins
+            _ if self
+                .snakes
+                .iter()
+                .filter(|s| s.alive())
+                .all(|s| s.is_teammate(&self.snakes[survivor as usize])) =>
+            {
+                Outcome::Winner(survivor)
+            }
             _ => Outcome::None,
         }
     }
 
+    /// Returns the zobrist hash of the snakes, food and hazards.
+    ///
+    /// The turn is not part of the hash, so that repeated positions can be
+    /// detected.
+    pub fn zobrist_hash(&self) -> u64 {
+        let width = self.grid.width;
+        let index = |p: Vec2D| p.x as usize + p.y as usize * width;
+
+        let mut hash = 0;
+        for (i, cell) in self.grid.cells.iter().enumerate() {
+            if cell.t == CellT::Food {
+                hash ^= zobrist::food(i);
+            }
+            if cell.hazard > 0 {
+                // Distinct keys for the number of layers
+                hash ^= zobrist::hazard(i).rotate_left(cell.hazard as u32 - 1);
+            }
+        }
+
+        for (id, snake) in self.snakes.iter().enumerate() {
+            if !snake.alive() {
+                continue;
+            }
+            hash ^= zobrist::health(id, snake.health);
+            for (&p, &next) in snake.body.iter().zip(snake.body.iter().skip(1)) {
+                let delta = next - p;
+                let part = if delta == Vec2D::new(0, 0) {
+                    zobrist::PART_STACKED
+                } else {
+                    Direction::of_step(delta) as usize
+                };
+                hash ^= zobrist::body(id, part, index(p));
+            }
+            hash ^= zobrist::body(id, zobrist::PART_HEAD, index(snake.head()));
+        }
+        hash
+    }
+
+    /// Returns the zobrist hash without the health of the snakes,
+    /// which only depends on the positions of the snakes, food and hazards.
+    pub fn position_hash(&self) -> u64 {
+        self.snakes
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.alive())
+            .fold(self.zobrist_hash(), |hash, (id, s)| {
+                hash ^ zobrist::health(id, s.health)
+            })
+    }
+
     /// Returns if a snake is alive.
     pub fn snake_is_alive(&self, snake: u8) -> bool {
         snake < self.snakes.len() as u8 && self.snakes[snake as usize].alive()
@@ -169,7 +388,7 @@ impl Game {
     /// Returns all valid moves that do not immediately kill the snake.
     /// Head to head collisions are not considered.
     #[inline]
-    pub fn valid_moves(&self, snake: u8) -> ValidMoves {
+    pub fn valid_moves(&self, snake: u8) -> ValidMoves<'_> {
         if self.snake_is_alive(snake) {
             ValidMoves::new(self, &self.snakes[snake as usize])
         } else {
@@ -177,15 +396,160 @@ impl Game {
         }
     }
 
+    /// Returns the valid moves that do not end next to the head of a longer
+    /// or equally long enemy, which might lead to a lost head to head.
+    pub fn safe_moves(&self, snake: u8) -> impl Iterator<Item = Direction> + '_ {
+        self.valid_moves(snake).filter(move |&d| {
+            let own = &self.snakes[snake as usize];
+            let p = self.apply(own.head(), d);
+            !self.snakes.iter().enumerate().any(|(i, s)| {
+                i != snake as usize
+                    && s.alive()
+                    && s.body.len() >= own.body.len()
+                    && Direction::iter().any(|d| self.apply(s.head(), d) == p)
+            })
+        })
+    }
+
+    /// Returns a move for the case that an agent has nothing better,
+    /// preferring safe over valid moves.
+    pub fn fallback_move(&self, snake: u8) -> Direction {
+        self.safe_moves(snake)
+            .next()
+            .or_else(|| self.valid_moves(snake).next())
+            .unwrap_or(Direction::Up)
+    }
+
+    /// Returns how dangerous each cell is for snake 0 in the next turn,
+    /// indexed like the grid cells.
+    ///
+    /// Every enemy adds to the cells it can move to, with full weight if it is
+    /// at least as long as we are (lost head to head) and half otherwise.
+    pub fn threat_map(&self) -> Vec<f64> {
+        let (width, height) = (self.grid.width, self.grid.height);
+        let mut threats = vec![0.0; width * height];
+        let own_len = self.snakes.first().map_or(0, |s| s.body.len());
+        for (i, snake) in self.snakes.iter().enumerate().skip(1) {
+            if !snake.alive() || self.snakes[0].is_teammate(snake) {
+                continue;
+            }
+            let weight = if snake.body.len() >= own_len {
+                1.0
+            } else {
+                0.5
+            };
+            for d in self.valid_moves(i as u8) {
+                let p = self.apply(snake.head(), d);
+                threats[p.x as usize + p.y as usize * width] += weight;
+            }
+        }
+        threats
+    }
+
+    /// Returns the moves and resulting states for all combinations of the
+    /// valid moves of the snakes.
+    ///
+    /// Snakes without valid moves, including dead ones, only make their
+    /// fallback move.
+    /// Up to `MAX_SNAKES` snakes are supported.
+    pub fn successors(&self) -> Vec<([Direction; MAX_SNAKES], Game)> {
+        assert!(self.snakes.len() <= MAX_SNAKES);
+        let moves: Vec<Vec<Direction>> = (0..self.snakes.len() as u8)
+            .map(|i| {
+                let moves: Vec<_> = self.valid_moves(i).collect();
+                if moves.is_empty() {
+                    vec![self.fallback_move(i)]
+                } else {
+                    moves
+                }
+            })
+            .collect();
+
+        let mut successors = Vec::new();
+        let mut indices = [0; MAX_SNAKES];
+        loop {
+            let mut actions = [Direction::Up; MAX_SNAKES];
+            for (i, moves) in moves.iter().enumerate() {
+                actions[i] = moves[indices[i]];
+            }
+            let mut game = self.clone();
+            game.step(&actions[..self.snakes.len()]);
+            successors.push((actions, game));
+
+            // Next combination, like counting with mixed radices
+            let mut i = 0;
+            loop {
+                if i == moves.len() {
+                    return successors;
+                }
+                indices[i] += 1;
+                if indices[i] < moves[i].len() {
+                    break;
+                }
+                indices[i] = 0;
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns the move onto our own tail, if the snake can chase it in a
+    /// loop for at least one full cycle with its current health.
+    ///
+    /// The tail has to be vacated in every turn of the cycle, so the body
+    /// must not be stacked, and no other head may reach the tail in the
+    /// next turn. Enemies might still cut into the cycle later on.
+    pub fn can_tail_chase(&self, snake: u8) -> Option<Direction> {
+        if !self.snake_is_alive(snake) {
+            return None;
+        }
+        let own = &self.snakes[snake as usize];
+        let tail = own.body[0];
+        if own.body.len() < 4
+            || own
+                .body
+                .iter()
+                .zip(own.body.iter().skip(1))
+                .any(|(a, b)| a == b)
+        {
+            return None;
+        }
+        let dir = Direction::iter().find(|&d| self.apply(own.head(), d) == tail)?;
+
+        // Every body cell is entered once per cycle
+        let damage: usize = own.body.iter().map(|&p| self.grid.damage(p) as usize).sum();
+        if own.health as usize <= damage {
+            return None;
+        }
+
+        let contested = self.snakes.iter().enumerate().any(|(i, s)| {
+            i != snake as usize
+                && s.alive()
+                && !s.is_teammate(own)
+                && Direction::iter().any(|d| self.apply(s.head(), d) == tail)
+        });
+        (!contested).then_some(dir)
+    }
+
     /// Returns if a move will not immediately kill the snake.
     /// Head to head collisions are not considered.
     pub fn move_is_valid(&self, snake: u8, dir: Direction) -> bool {
         self.snake_is_alive(snake) && self.snake_move_is_valid(&self.snakes[snake as usize], dir)
     }
 
+    /// Applies the move to the position, wrapping around the board edges
+    /// if this is a wrapped game.
+    #[inline]
+    pub fn apply(&self, p: Vec2D, dir: Direction) -> Vec2D {
+        if self.wrapped {
+            self.grid.wrapping_apply(p, dir)
+        } else {
+            p.apply(dir)
+        }
+    }
+
     #[inline]
     fn snake_move_is_valid(&self, snake: &Snake, dir: Direction) -> bool {
-        let p = snake.head().apply(dir);
+        let p = self.apply(snake.head(), dir);
         // Free or occupied by tail (free in the next turn)
         self.grid.has(p)
             && (self.grid[p].t != CellT::Owned
@@ -193,86 +557,200 @@ impl Game {
                     .snakes
                     .iter()
                     .filter(|s| s.alive())
-                    .any(|s| p == s.body[0] && p != s.body[1]))
+                    .any(|s| p == s.body[0] && s.tail_moves())
+                || Self::squad_passable(&self.snakes, snake, p))
+    }
+
+    /// Returns if the cell `p` is only occupied by the bodies of teammates,
+    /// which the `snake` can pass through.
+    fn squad_passable(snakes: &[Snake], snake: &Snake, p: Vec2D) -> bool {
+        snake.squad.is_some()
+            && !snake.body.contains(&p)
+            && snakes
+                .iter()
+                .filter(|s| s.alive() && s.body.contains(&p))
+                .all(|s| s.is_teammate(snake))
     }
 
     /// Executed the provided moves for each living agent.
     /// This method also checks for eating and collision with walls or other snakes.
     pub fn step(&mut self, moves: &[Direction]) {
+        self.step_impl(moves, None);
+    }
+
+    /// Like `step`, but records the changes into `delta`,
+    /// so that the step can be reverted with `undo_step`.
+    ///
+    /// The buffers of `delta` are reused, which avoids cloning the game.
+    pub fn step_undoable(&mut self, moves: &[Direction], delta: &mut StepDelta) {
+        delta.cells.clear();
+        delta.snakes.clear();
+        delta
+            .snakes
+            .extend(self.snakes.iter().map(|s| SnakeDelta::new(s.health)));
+        self.step_impl(moves, Some(delta));
+    }
+
+    /// Executes the moves like `step` and returns the token to revert them
+    /// with `unmake`.
+    ///
+    /// This allows searching in place instead of cloning the game at every node.
+    pub fn make(&mut self, moves: &[Direction]) -> MoveUndo {
+        let mut undo = MoveUndo::default();
+        self.step_undoable(moves, &mut undo);
+        undo
+    }
+
+    /// Reverts the moves of `make`.
+    pub fn unmake(&mut self, mut undo: MoveUndo) {
+        self.undo_step(&mut undo);
+    }
+
+    /// Reverts the step recorded with `step_undoable`.
+    pub fn undo_step(&mut self, delta: &mut StepDelta) {
+        debug_assert_eq!(delta.snakes.len(), self.snakes.len());
+
+        for (snake, delta) in self.snakes.iter_mut().zip(&mut delta.snakes) {
+            if !delta.body.is_empty() {
+                std::mem::swap(&mut snake.body, &mut delta.body);
+            }
+            if delta.grown {
+                snake.body.pop_front();
+            }
+            if delta.head {
+                snake.body.pop_back();
+            }
+            if let Some(tail) = delta.tail {
+                snake.body.push_front(tail);
+            }
+            snake.health = delta.health;
+        }
+        for &(p, cell) in delta.cells.iter().rev() {
+            self.grid[p] = cell;
+        }
+        self.turn -= 1;
+    }
+
+    fn step_impl(&mut self, moves: &[Direction], mut delta: Option<&mut StepDelta>) {
         assert!(moves.len() >= self.snakes.len());
 
+        /// Updates the cell and records its previous state.
+        #[inline]
+        fn set(grid: &mut Grid, delta: &mut Option<&mut StepDelta>, p: Vec2D, t: CellT) {
+            if let Some(delta) = delta {
+                delta.cells.push((p, grid[p]));
+            }
+            grid[p].t = t;
+        }
+
         // Pop tail
-        for snake in &mut self.snakes {
-            if snake.alive() {
-                let tail = snake.body.pop_front().unwrap();
-                let new_tail = snake.body[0];
-                if tail != new_tail {
-                    self.grid[tail].t = CellT::Free;
+        let squads = self.snakes.iter().any(|s| s.squad.is_some());
+        for id in 0..self.snakes.len() {
+            if self.snakes[id].alive() {
+                let tail = self.snakes[id].body.pop_front().unwrap();
+                let snake = &self.snakes[id];
+                // Teammates might still occupy the cell
+                if tail != snake.body[0]
+                    && !(squads
+                        && self
+                            .snakes
+                            .iter()
+                            .any(|s| s.alive() && s.is_teammate(snake) && s.body.contains(&tail)))
+                {
+                    set(&mut self.grid, &mut delta, tail, CellT::Free);
+                }
+                if let Some(delta) = &mut delta {
+                    delta.snakes[id].tail = Some(tail);
                 }
             }
         }
 
         // Move head & eat
-        for (id, snake) in self.snakes.iter_mut().enumerate() {
-            if snake.alive() {
-                let dir = moves[id];
-                let head = snake.head().apply(dir);
+        for (id, &dir) in moves.iter().enumerate().take(self.snakes.len()) {
+            if self.snakes[id].alive() {
+                let snake = &self.snakes[id];
+                let head = self.apply(snake.head(), dir);
 
                 if !self.grid.has(head) {
-                    snake.health = 0;
+                    self.snakes[id].health = 0;
                     continue;
                 }
 
-                snake.body.push_back(head);
-
                 let g_cell = self.grid[head];
-                if g_cell.t == CellT::Owned {
-                    snake.health = 0;
+                if g_cell.t == CellT::Owned && !Self::squad_passable(&self.snakes, snake, head) {
+                    self.snakes[id].health = 0;
                     continue;
                 }
 
+                let snake = &mut self.snakes[id];
+                snake.body.push_back(head);
+                if let Some(delta) = &mut delta {
+                    delta.snakes[id].head = true;
+                    delta.snakes[id].grown = g_cell.t == CellT::Food;
+                }
+
                 snake.health = if g_cell.t == CellT::Food {
+                    // Grow by duplicating the tail, which vacates one turn later
                     snake.body.push_front(*snake.body.front().unwrap());
                     100
                 } else {
-                    snake
-                        .health
-                        .saturating_sub(if g_cell.hazard { HAZARD_DAMAGE } else { 1 })
+                    snake.health.saturating_sub(self.grid.damage(head))
                 };
             }
         }
 
         // Check head to head
-        // Warning: This is only accurate for head to head on two snakes but not more
-        for i in 0..self.snakes.len() - 1 {
-            if self.snakes[i].alive() {
-                for j in i + 1..self.snakes.len() {
-                    if self.snakes[j].alive() && self.snakes[i].head() == self.snakes[j].head() {
-                        use std::cmp::Ordering;
-                        match self.snakes[i].body.len().cmp(&self.snakes[j].body.len()) {
-                            Ordering::Less => self.snakes[i].health = 0,
-                            Ordering::Greater => self.snakes[j].health = 0,
-                            Ordering::Equal => {
-                                self.snakes[i].health = 0;
-                                self.snakes[j].health = 0;
-                            }
-                        }
-                    }
-                }
+        // Snakes die if another snake with at least the same length moved
+        // onto the same cell. Lengths are compared before any elimination.
+        let mut eliminated = 0u64;
+        for (i, snake) in self.snakes.iter().enumerate() {
+            if snake.alive()
+                && self.snakes.iter().enumerate().any(|(j, other)| {
+                    i != j
+                        && other.alive()
+                        && !other.is_teammate(snake)
+                        && other.head() == snake.head()
+                        && other.body.len() >= snake.body.len()
+                })
+            {
+                eliminated |= 1 << i;
+            }
+        }
+        for (i, snake) in self.snakes.iter_mut().enumerate() {
+            if eliminated & (1 << i) != 0 {
+                snake.health = 0;
             }
         }
 
         // Clear died snakes
         let grid = &mut self.grid;
-        for snake in &mut self.snakes {
-            if snake.alive() {
-                let head_cell = &mut grid[snake.head()];
-                head_cell.t = CellT::Owned;
-            } else {
+        for (id, snake) in self.snakes.iter_mut().enumerate() {
+            if !snake.alive() {
                 for &p in &snake.body {
-                    grid[p].t = CellT::Free;
+                    set(grid, &mut delta, p, CellT::Free);
+                }
+                match &mut delta {
+                    Some(delta) => std::mem::swap(&mut snake.body, &mut delta.snakes[id].body),
+                    None => snake.body.clear(),
+                }
+            }
+        }
+        // Their heads might have been on the same cell as the survivors
+        for snake in &self.snakes {
+            if snake.alive() {
+                set(grid, &mut delta, snake.head(), CellT::Owned);
+            }
+        }
+        // Teammates might overlap, restore the cells that were freed
+        if squads {
+            for snake in &self.snakes {
+                if snake.alive() {
+                    for &p in &snake.body {
+                        if grid[p].t != CellT::Owned {
+                            set(grid, &mut delta, p, CellT::Owned);
+                        }
+                    }
                 }
-                snake.body.clear();
             }
         }
 
@@ -280,6 +758,40 @@ impl Game {
     }
 }
 
+/// Changes of a single `Game::step_undoable`, which are reverted by
+/// `Game::undo_step`.
+#[derive(Debug, Clone, Default)]
+pub struct StepDelta {
+    /// Modified cells with their previous state (in order of modification)
+    cells: Vec<(Vec2D, Cell)>,
+    snakes: Vec<SnakeDelta>,
+}
+
+/// Token of `Game::make` to revert the moves with `Game::unmake`.
+pub type MoveUndo = StepDelta;
+
+#[derive(Debug, Clone, Default)]
+struct SnakeDelta {
+    health: u8,
+    /// Popped tail
+    tail: Option<Vec2D>,
+    /// Pushed head
+    head: bool,
+    /// Duplicated tail after eating
+    grown: bool,
+    /// Body of a snake that died in this step
+    body: VecDeque<Vec2D>,
+}
+
+impl SnakeDelta {
+    fn new(health: u8) -> Self {
+        Self {
+            health,
+            ..Self::default()
+        }
+    }
+}
+
 impl Game {
     /// Parses textual human readable board representation used in test.
     #[must_use]
@@ -312,7 +824,7 @@ impl Game {
             .collect();
         let height = txt.lines().count();
 
-        if raw_cells.len() % height != 0 {
+        if !raw_cells.len().is_multiple_of(height) {
             return None;
         }
         let width = raw_cells.len() / height;
@@ -320,9 +832,9 @@ impl Game {
         let mut grid = Grid::new(width, height);
         for (i, cell) in raw_cells.iter().enumerate() {
             grid[Vec2D::new((i % width) as _, (i / width) as _)] = match cell {
-                RawCell::Free => Cell::new(CellT::Free, false),
-                RawCell::Food => Cell::new(CellT::Food, false),
-                _ => Cell::new(CellT::Owned, false),
+                RawCell::Free => Cell::new(CellT::Free, 0),
+                RawCell::Food => Cell::new(CellT::Food, 0),
+                _ => Cell::new(CellT::Owned, 0),
             }
         }
 
@@ -332,11 +844,12 @@ impl Game {
                 let mut p = Vec2D::new((p % width) as _, (p / width) as _);
                 let mut body = VecDeque::new();
                 body.push_front(p);
-                while let Some(next) = Direction::all().into_iter().find_map(|d| {
-                    let next = p.apply(d);
-                    (next.within(width, height)
-                        && raw_cells[(next.x + next.y * width as i16) as usize]
-                            == RawCell::Body(d.invert()))
+                // Segments pointing out of the board continue on the opposite
+                // edge, as on wrapped boards
+                while let Some(next) = Direction::iter().find_map(|d| {
+                    let next = grid.wrapping_apply(p, d);
+                    (raw_cells[(next.x + next.y * width as i16) as usize]
+                        == RawCell::Body(d.invert()))
                     .then_some(next)
                 }) {
                     p = next;
@@ -355,8 +868,56 @@ impl Game {
             turn: 0,
             grid,
             snakes,
+            wrapped: false,
         })
     }
+
+    /// Returns the board in the format of [Game::parse].
+    ///
+    /// Heads are the snake ids, the body segments point towards the head, and
+    /// `o` is food. Hazards, health, and stacked segments (after eating)
+    /// are not represented. Only the first ten snakes are supported.
+    pub fn to_ascii(&self) -> String {
+        let (width, height) = (self.grid.width, self.grid.height);
+        let mut cells: Vec<char> = self
+            .grid
+            .cells
+            .iter()
+            .map(|c| if c.t == CellT::Food { 'o' } else { '.' })
+            .collect();
+
+        for (id, snake) in self.snakes.iter().enumerate().take(10) {
+            if !snake.alive() || snake.body.is_empty() {
+                continue;
+            }
+            for (&p, &next) in snake.body.iter().zip(snake.body.iter().skip(1)) {
+                if p == next {
+                    continue;
+                }
+                cells[p.x as usize + p.y as usize * width] = match Direction::of_step(next - p) {
+                    Direction::Up => '^',
+                    Direction::Right => '>',
+                    Direction::Down => 'v',
+                    Direction::Left => '<',
+                };
+            }
+            let head = snake.head();
+            cells[head.x as usize + head.y as usize * width] =
+                char::from_digit(id as _, 10).unwrap_or('.');
+        }
+
+        let mut out = String::with_capacity(2 * width * height);
+        for y in (0..height).rev() {
+            for (x, c) in cells[y * width..(y + 1) * width].iter().enumerate() {
+                if x > 0 {
+                    out.push(' ');
+                }
+                out.push(*c);
+            }
+            out.push('\n');
+        }
+        out
+    }
 }
 
 impl Debug for Game {
@@ -404,7 +965,7 @@ impl Debug for Game {
                 } else {
                     FmtCell::Free
                 };
-                cell.1 = g_cell.hazard;
+                cell.1 = g_cell.hazard > 0;
             }
         }
 
@@ -416,8 +977,9 @@ impl Debug for Game {
             let mut last_body = *snake.body.front().unwrap();
 
             for next_body in snake.body.iter().skip(1).copied() {
+                let dir = Direction::of_step(next_body - last_body);
                 cells[last_body.y as usize * self.grid.width + last_body.x as usize].0 =
-                    FmtCell::Tail(Direction::from(next_body - last_body), id as _);
+                    FmtCell::Tail(dir, id as _);
 
                 last_body = next_body;
             }
@@ -561,6 +1123,66 @@ mod test {
         info!("{game:?}");
     }
 
+    #[test]
+    fn game_from_request_royale() {
+        use super::*;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{"game":{"id":"17d30fe5-a90f-45c0-bb81-1f8bd54781e1","ruleset":{"damagePerTurn":"14","foodSpawnChance":"15","minimumFood":"1","name":"royale","shrinkEveryNTurns":"25"},"timeout":500},"turn":64,"board":{"width":11,"height":11,"food":[{"x":10,"y":7}],"hazards":[{"x":0,"y":0},{"x":0,"y":1},{"x":0,"y":2},{"x":0,"y":3},{"x":0,"y":4},{"x":0,"y":5},{"x":0,"y":6},{"x":0,"y":7},{"x":0,"y":8},{"x":0,"y":9},{"x":0,"y":10},{"x":1,"y":0},{"x":2,"y":0},{"x":3,"y":0},{"x":4,"y":0},{"x":5,"y":0},{"x":6,"y":0},{"x":7,"y":0},{"x":8,"y":0},{"x":9,"y":0},{"x":10,"y":0}],"snakes":[{"id":"gs_c6BKHbpSr47cqd76mmWTj7dB","name":"unsigned long long","body":[{"x":5,"y":7},{"x":5,"y":6},{"x":5,"y":5},{"x":4,"y":5},{"x":3,"y":5},{"x":2,"y":5}],"health":93,"latency":471,"head":{"x":5,"y":7},"length":6,"shout":"","squad":""},{"id":"gs_BWkm6pVmC6kTmYShrGTrRHfW","name":"marrrvin","body":[{"x":4,"y":4},{"x":3,"y":4},{"x":3,"y":3},{"x":2,"y":3},{"x":1,"y":3}],"health":56,"latency":25,"head":{"x":4,"y":4},"length":5,"shout":"","squad":""}]},"you":{"id":"gs_c6BKHbpSr47cqd76mmWTj7dB","name":"unsigned long long","body":[{"x":5,"y":7},{"x":5,"y":6},{"x":5,"y":5},{"x":4,"y":5},{"x":3,"y":5},{"x":2,"y":5}],"health":93,"latency":471,"head":{"x":5,"y":7},"length":6,"shout":"","squad":""}}"#
+        ).unwrap();
+
+        let legacy = &request.game.ruleset.legacy;
+        assert_eq!(legacy.damage_per_turn, Some(14));
+        assert_eq!(legacy.food_spawn_chance, Some(15));
+        assert_eq!(legacy.minimum_food, Some(1));
+        assert_eq!(legacy.shrink_every_n_turns, Some(25));
+        assert_eq!(request.game.ruleset.hazard_damage(), 14);
+
+        let mut game = Game::from_request(&request);
+        assert_eq!(game.grid.hazard_damage, 14);
+        info!("{game:?}");
+
+        // Snake 1 moves into a hazard
+        game.grid[Vec2D::new(4, 3)].hazard = 1;
+        game.step(&[Direction::Up, Direction::Down]);
+        assert_eq!(game.snakes[0].health, 92);
+        assert_eq!(game.snakes[1].health, 56 - 15);
+    }
+
+    #[test]
+    fn game_debug_non_square() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . o . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . 0 < < . . .
+            . . . . . . .
+            . . . 1 . . .
+            . . . ^ . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.grid.width, 7);
+        assert_eq!(game.grid.height, 11);
+
+        let txt = format!("{game:?}");
+        info!("{txt}");
+        let lines = txt.lines().collect::<Vec<_>>();
+        // Header, rows, snakes and closing bracket
+        assert_eq!(lines.len(), 11 + 3);
+        for row in &lines[1..12] {
+            assert_eq!(row.split_whitespace().count(), 7);
+        }
+    }
+
     #[test]
     fn game_step() {
         use super::*;
@@ -613,6 +1235,323 @@ mod test {
         }
     }
 
+    #[test]
+    fn game_step_head_to_head() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            > > > > 0 . 1 < < < .
+            . . . . . 2 . . . . .
+            . . . . . ^ . . . . .
+            . . . . . ^ . . . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . ."#,
+        )
+        .unwrap();
+
+        {
+            // The unique longest snake survives
+            let mut game = game.clone();
+            game.step(&[Right, Left, Up]);
+            info!("{game:?}");
+            assert!(game.snake_is_alive(0));
+            assert!(!game.snake_is_alive(1));
+            assert!(!game.snake_is_alive(2));
+            assert!(game.grid[Vec2D::new(5, 5)].t == CellT::Owned);
+            assert!(game.grid[Vec2D::new(6, 5)].t == CellT::Free);
+            assert!(game.grid[Vec2D::new(5, 4)].t == CellT::Free);
+        }
+
+        {
+            // The two longest snakes are equally long, all snakes die
+            let mut game = game.clone();
+            game.snakes[1].body.push_front(Vec2D::new(10, 5));
+            game.grid[Vec2D::new(10, 5)].t = CellT::Owned;
+            game.step(&[Right, Left, Up]);
+            info!("{game:?}");
+            assert!(!game.snake_is_alive(0));
+            assert!(!game.snake_is_alive(1));
+            assert!(!game.snake_is_alive(2));
+            assert!(game.grid[Vec2D::new(5, 5)].t == CellT::Free);
+        }
+    }
+
+    #[test]
+    fn game_step_eat() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            . . 0 o .
+            . . ^ . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+        game.snakes[0].health = 50;
+        let len = game.snakes[0].body.len();
+
+        game.step(&[Right]);
+        info!("{game:?}");
+        let snake = &game.snakes[0];
+        assert_eq!(snake.health, 100);
+        assert_eq!(snake.body.len(), len + 1);
+        assert_eq!(snake.head(), Vec2D::new(3, 2));
+        assert_eq!(snake.body[0], Vec2D::new(2, 1));
+        assert_eq!(snake.body[0], snake.body[1]);
+        assert!(game.grid[Vec2D::new(2, 1)].t == CellT::Owned);
+        assert!(game.grid[Vec2D::new(2, 0)].t == CellT::Free);
+
+        // The duplicated tail stays for one more turn
+        game.step(&[Up]);
+        info!("{game:?}");
+        let snake = &game.snakes[0];
+        assert_eq!(snake.body.len(), len + 1);
+        assert_eq!(snake.body[0], Vec2D::new(2, 1));
+        assert!(game.grid[Vec2D::new(2, 1)].t == CellT::Owned);
+    }
+
+    #[test]
+    fn game_undo_step() {
+        use super::*;
+        use rand::{rngs::SmallRng, seq::IteratorRandom, SeedableRng};
+        logging();
+
+        let mut rng = SmallRng::seed_from_u64(42);
+        let mut delta = StepDelta::default();
+        for _ in 0..20 {
+            let mut game = Game::parse(
+                r#"
+                . . . . . . . o . . .
+                o . . . . . . . . o .
+                . . o 3 . . . . . . .
+                . . . . . . 0 o . . .
+                . . o . . . . . . . .
+                . . . . . o . . . o .
+                . o . . o . . . . . .
+                . . . . . . . 2 . o .
+                . . . 1 . . . o . . .
+                o . . o . . . . . . .
+                . . . . . o . . o . ."#,
+            )
+            .unwrap();
+            game.grid[Vec2D::new(5, 5)].hazard = 1;
+
+            while game.outcome() == Outcome::None {
+                // Random moves, including invalid ones
+                let moves = [(); 4].map(|_| Direction::iter().choose(&mut rng).unwrap());
+
+                let prev = game.clone();
+                game.step_undoable(&moves, &mut delta);
+                let mut next = prev.clone();
+                next.step(&moves);
+
+                game.undo_step(&mut delta);
+                assert_eq!(game.turn, prev.turn);
+                assert_eq!(game.snakes, prev.snakes);
+                assert!(game.grid.cells == prev.grid.cells);
+
+                game.step_undoable(&moves, &mut delta);
+                assert_eq!(game.snakes, next.snakes);
+                assert!(game.grid.cells == next.grid.cells);
+            }
+            info!("{game:?}");
+        }
+    }
+
+    #[test]
+    fn game_make_unmake() {
+        use super::*;
+        use rand::{rngs::SmallRng, seq::IteratorRandom, Rng, SeedableRng};
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . . o . . .
+            o . . . . . . . . o .
+            . . o 3 . . . . . . .
+            . . . . . . 0 o . . .
+            . . o . . . . . . . .
+            . . . . . o . . . o .
+            . o . . o . . . . . .
+            . . . . . . . 2 . o .
+            . . . 1 . . . o . . .
+            o . . o . . . . . . .
+            . . . . . o . . o . ."#,
+        )
+        .unwrap();
+        let start = game.clone();
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        for _ in 0..100 {
+            // Apply a sequence of mostly valid moves
+            let mut stack = Vec::new();
+            for _ in 0..rng.gen_range(1..12) {
+                if game.outcome() != Outcome::None {
+                    break;
+                }
+                let moves = [0, 1, 2, 3].map(|i| {
+                    game.valid_moves(i)
+                        .choose(&mut rng)
+                        .unwrap_or(Direction::Up)
+                });
+                let baseline = game.clone();
+                stack.push((game.make(&moves), baseline));
+            }
+            info!("{game:?}");
+
+            // Undo them in reverse order
+            while let Some((undo, baseline)) = stack.pop() {
+                game.unmake(undo);
+                assert_eq!(game.turn, baseline.turn);
+                assert_eq!(game.snakes, baseline.snakes);
+                assert!(game.grid.cells == baseline.grid.cells);
+            }
+            assert_eq!(game.snakes, start.snakes);
+            assert!(game.grid.cells == start.grid.cells);
+        }
+    }
+
+    #[test]
+    fn game_step_wrapped() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            0 < < . .
+            . v . . .
+            . 1 . . .
+            . . . . ."#,
+        )
+        .unwrap();
+        assert!(!game.move_is_valid(0, Left));
+
+        game.wrapped = true;
+        assert!(game.move_is_valid(0, Left));
+
+        game.step(&[Left, Down]);
+        info!("{game:?}");
+        assert!(game.snake_is_alive(0));
+        assert_eq!(game.snakes[0].head(), Vec2D::new(4, 3));
+        assert!(game.grid[Vec2D::new(4, 3)].t == CellT::Owned);
+
+        // Snake 1 crosses the bottom edge
+        game.step(&[Left, Down]);
+        info!("{game:?}");
+        assert!(game.snake_is_alive(0));
+        assert!(game.snake_is_alive(1));
+        assert_eq!(game.snakes[1].head(), Vec2D::new(1, 5));
+        assert!(game.grid[Vec2D::new(1, 5)].t == CellT::Owned);
+        assert!(game.grid[Vec2D::new(1, 0)].t == CellT::Owned);
+
+        // Snake 0 bites itself over the edge
+        let mut game = Game::parse(
+            r#"
+            > > > > 0
+            . . . . ."#,
+        )
+        .unwrap();
+        game.wrapped = true;
+        game.snakes[0].body.push_front(Vec2D::new(0, 1));
+        game.step(&[Right]);
+        assert!(!game.snake_is_alive(0));
+    }
+
+    #[test]
+    fn game_from_request_wrapped() {
+        use super::*;
+        logging();
+
+        let request = |ruleset: &str| -> GameRequest {
+            serde_json::from_str(&format!(
+                r#"{{"game":{{"id":"g","ruleset":{{"name":"{ruleset}"}},"timeout":200}},"turn":5,"board":{{"height":5,"width":5,"food":[],"hazards":[],"snakes":[{{"id":"a","name":"a","health":100,"body":[{{"x":0,"y":2}},{{"x":1,"y":2}},{{"x":2,"y":2}}]}}]}},"you":{{"id":"a","name":"a","health":100,"body":[{{"x":0,"y":2}},{{"x":1,"y":2}},{{"x":2,"y":2}}]}}}}"#
+            ))
+            .unwrap()
+        };
+
+        let game = Game::from_request(&request("standard"));
+        assert!(!game.wrapped);
+        assert!(!game.move_is_valid(0, Direction::Left));
+
+        let mut game = Game::from_request(&request("wrapped"));
+        assert!(game.wrapped);
+        assert!(game.move_is_valid(0, Direction::Left));
+        game.step(&[Direction::Left]);
+        info!("{game:?}");
+        assert!(game.snake_is_alive(0));
+        assert_eq!(game.snakes[0].head(), Vec2D::new(4, 2));
+    }
+
+    #[test]
+    fn game_zobrist_hash() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        const BOARD: &str = r#"
+            . . . . . . . . . . .
+            . . . . . . . . o . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            . . . . . 0 < < . . .
+            . . . . . . . ^ . . .
+            . . . . . > > ^ . . .
+            . . . . . . . . . . .
+            . . . . . . . . . . .
+            v . . . . . . . . . .
+            1 . . . . . . . . . ."#;
+        let game = Game::parse(BOARD).unwrap();
+        let other = Game::parse(BOARD).unwrap();
+        assert_eq!(game.zobrist_hash(), other.zobrist_hash());
+
+        let mut stepped = game.clone();
+        stepped.step(&[Up, Right]);
+        assert_ne!(game.zobrist_hash(), stepped.zobrist_hash());
+
+        let mut other = game.clone();
+        other.grid[Vec2D::new(3, 3)].hazard = 1;
+        assert_ne!(game.zobrist_hash(), other.zobrist_hash());
+
+        let mut other = game.clone();
+        other.snakes[1].health -= 1;
+        assert_ne!(game.zobrist_hash(), other.zobrist_hash());
+
+        // Same cells but different order of the body
+        let a = Game::parse(
+            r#"
+            . . . .
+            . 0 < .
+            . . ^ .
+            . . . ."#,
+        )
+        .unwrap();
+        let b = Game::parse(
+            r#"
+            . . . .
+            . > v .
+            . . 0 .
+            . . . ."#,
+        )
+        .unwrap();
+        assert!(a.grid.cells == b.grid.cells);
+        assert_ne!(a.zobrist_hash(), b.zobrist_hash());
+    }
+
     #[test]
     fn test_valid_moves() {
         use super::*;
@@ -647,4 +1586,549 @@ mod test {
         info!("{game:?}");
         assert!([Right].iter().cloned().eq(game.valid_moves(0)));
     }
+
+    #[test]
+    fn game_board_editor() {
+        use super::*;
+        use crate::env::v2;
+        logging();
+
+        let game = Game::from_board_json(
+            r#"{
+                "width": 7,
+                "height": 7,
+                "food": [{"x": 3, "y": 3}, {"x": 0, "y": 6}],
+                "hazards": [{"x": 0, "y": 0}],
+                "snakes": [
+                    {"name": "a", "head": {"x": 1, "y": 1}, "body": [{"x": 1, "y": 2}, {"x": 1, "y": 3}]},
+                    {"name": "b", "health": 50, "head": {"x": 5, "y": 5}, "body": [{"x": 5, "y": 5}, {"x": 5, "y": 4}, {"x": 5, "y": 3}]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        info!("{game:?}");
+        assert_eq!((game.grid.width, game.grid.height), (7, 7));
+        assert_eq!(game.snakes.len(), 2);
+        assert_eq!(game.snakes[0].head(), v2(1, 1));
+        assert_eq!(game.snakes[0].body.len(), 3);
+        assert_eq!(game.snakes[1].health, 50);
+        assert_eq!(game.snakes[1].body.len(), 3);
+        let food = game
+            .grid
+            .cells
+            .iter()
+            .filter(|c| c.t == CellT::Food)
+            .count();
+        assert_eq!(food, 2);
+        assert_eq!(game.grid[v2(0, 0)].hazard, 1);
+
+        // Nested in a board object
+        let nested =
+            Game::from_board_json(r#"{"board": {"width": 5, "height": 5, "snakes": []}}"#).unwrap();
+        assert_eq!(nested.grid.width, 5);
+        assert!(Game::from_board_json(r#"{"width": 5}"#).is_err());
+    }
+
+    #[test]
+    fn game_serde() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . o .
+            . ^ . . .
+            . ^ 1 < <
+            . . . . ."#,
+        )
+        .unwrap();
+        game.grid[Vec2D::new(0, 0)].hazard = 1;
+        game.snakes[1].health = 42;
+        game.turn = 17;
+
+        let json = serde_json::to_string(&game).unwrap();
+        info!("{json}");
+        let restored: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, game);
+        assert_eq!(restored.zobrist_hash(), game.zobrist_hash());
+
+        // The restored game continues the same
+        let mut next = game.clone();
+        let mut restored = restored;
+        next.step(&[Direction::Up, Direction::Down]);
+        restored.step(&[Direction::Up, Direction::Down]);
+        assert_eq!(restored, next);
+    }
+
+    #[test]
+    fn game_to_ascii() {
+        use super::*;
+        use crate::env::v2;
+        logging();
+
+        let boards = [
+            r#"
+            . . . . .
+            . 0 . o .
+            . ^ . . .
+            . ^ 1 < <
+            . . . . ."#,
+            r#"
+            o . . . . . .
+            . > > v . 2 .
+            . ^ . v . ^ .
+            . ^ . 0 . ^ .
+            . ^ . . . ^ <
+            . ^ < < 1 . ^
+            . . . . . > ^"#,
+            r#"
+            0 < . o
+            . 1 . .
+            o ^ . ."#,
+        ];
+        for board in boards {
+            let game = Game::parse(board).unwrap();
+            let ascii = game.to_ascii();
+            info!("\n{ascii}");
+            let parsed = Game::parse(&ascii).unwrap();
+            assert_eq!(parsed.snakes, game.snakes);
+            assert_eq!(parsed.grid, game.grid);
+            // Formatted the same
+            assert_eq!(parsed.to_ascii(), ascii);
+        }
+
+        // Wrapped around the edges
+        let mut game = Game::parse(
+            r#"
+            . . ^ . .
+            . . . . .
+            0 . . . >
+            . . . . ^
+            . . 1 . ."#,
+        )
+        .unwrap();
+        game.wrapped = true;
+        assert_eq!(game.snakes[0].body, [v2(4, 1), v2(4, 2), v2(0, 2)]);
+        assert_eq!(game.snakes[1].body, [v2(2, 4), v2(2, 4), v2(2, 0)]);
+        let ascii = game.to_ascii();
+        info!("\n{ascii}");
+        assert_eq!(
+            ascii,
+            ". . ^ . .\n. . . . .\n0 . . . >\n. . . . ^\n. . 1 . .\n"
+        );
+        let parsed = Game::parse(&ascii).unwrap();
+        assert_eq!(parsed.snakes, game.snakes);
+
+        // Moved without eating
+        let mut game = Game::parse(boards[1]).unwrap();
+        game.step(&[Direction::Left, Direction::Left, Direction::Up]);
+        let parsed = Game::parse(&game.to_ascii()).unwrap();
+        for (parsed, snake) in parsed.snakes.iter().zip(&game.snakes) {
+            assert_eq!(parsed.body, snake.body);
+        }
+    }
+
+    #[test]
+    fn game_to_request() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . 0 . o . . .
+            . ^ . . 2 . .
+            . ^ 1 < ^ . .
+            o . . . ^ . ."#,
+        )
+        .unwrap();
+        game.grid[Vec2D::new(6, 0)].hazard = 1;
+        game.grid[Vec2D::new(6, 1)].hazard = 1;
+        game.grid.hazard_damage = 7;
+        game.snakes[1].health = 42;
+
+        let request = game.to_request(12, "royale");
+        let json = serde_json::to_string(&request).unwrap();
+        info!("{json}");
+        assert_eq!(request.turn, 12);
+        assert_eq!(request.you.id, request.board.snakes[0].id);
+        // Head to tail
+        assert_eq!(request.you.body[0], game.snakes[0].head());
+        assert_eq!(request.board.food.len(), 2);
+        assert_eq!(request.board.hazards.len(), 2);
+
+        let request: GameRequest = serde_json::from_str(&json).unwrap();
+        let restored = Game::from_request(&request);
+        assert_eq!(restored.snakes, game.snakes);
+        assert_eq!(restored.grid, game.grid);
+
+        // Dead snakes are omitted
+        game.snakes[1].health = 0;
+        game.snakes[1].body.clear();
+        let request = game.to_request(12, "standard");
+        assert_eq!(request.board.snakes.len(), 2);
+    }
+
+    #[test]
+    fn game_successors() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . 1 < <
+            . . . . . . .
+            . . . . . . .
+            . . 0 < < . ."#,
+        )
+        .unwrap();
+        let counts: Vec<_> = (0..2).map(|i| game.valid_moves(i).count()).collect();
+        assert_eq!(counts, [2, 3]);
+
+        let successors = game.successors();
+        info!(
+            "{:?}",
+            successors.iter().map(|(a, _)| a).collect::<Vec<_>>()
+        );
+        assert_eq!(successors.len(), counts.iter().product::<usize>());
+        for (i, (actions, next)) in successors.iter().enumerate() {
+            assert!(successors[..i].iter().all(|(a, _)| a != actions));
+            let mut expected = game.clone();
+            expected.step(&actions[..2]);
+            assert_eq!(next.snakes, expected.snakes);
+            assert_eq!(next.turn, game.turn + 1);
+        }
+
+        let crowded = Game::parse(
+            r#"
+            . . . . . . .
+            0 < . . . . .
+            . . . . . . .
+            1 < . . . . .
+            . . . . . . .
+            2 < . . 3 < .
+            . . . . . . .
+            4 < . . . . ."#,
+        )
+        .unwrap();
+        let counts: Vec<_> = (0..5).map(|i| crowded.valid_moves(i).count()).collect();
+        let successors = crowded.successors();
+        assert_eq!(successors.len(), counts.iter().product::<usize>());
+        for (actions, next) in &successors {
+            let mut expected = crowded.clone();
+            expected.step(&actions[..5]);
+            assert_eq!(next.snakes, expected.snakes);
+        }
+    }
+
+    #[test]
+    fn game_tail_chase() {
+        use super::*;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . > > v . . .
+            . ^ 0 < . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.snakes[0].body.len(), 6);
+        assert_eq!(game.can_tail_chase(0), Some(Direction::Left));
+
+        // The cycle repeats
+        for _ in 0..12 {
+            let dir = game.can_tail_chase(0).unwrap();
+            game.step(&[dir]);
+            assert!(game.snake_is_alive(0));
+        }
+        info!("{game:?}");
+        assert_eq!(game.snakes[0].body.len(), 6);
+
+        // Not enough health for a full cycle
+        game.snakes[0].health = 6;
+        assert_eq!(game.can_tail_chase(0), None);
+
+        // An enemy could take the vacated tail
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . > > v . . .
+            . ^ 0 < . . .
+            . 1 < < . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.can_tail_chase(0), None);
+
+        // Stacked tails (after eating) are not vacated
+        let mut game = game.clone();
+        game.snakes[1].health = 0;
+        assert_eq!(game.can_tail_chase(0), Some(Direction::Left));
+        let tail = game.snakes[0].body[0];
+        game.snakes[0].body.push_front(tail);
+        assert_eq!(game.can_tail_chase(0), None);
+    }
+
+    #[test]
+    fn game_safe_moves() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . > 0 . 1 < <
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        let valid: Vec<_> = game.valid_moves(0).collect();
+        let safe: Vec<_> = game.safe_moves(0).collect();
+        info!("valid {valid:?} safe {safe:?}");
+        // Right ends next to the equally long enemy
+        assert_eq!(valid, [Direction::Up, Direction::Right, Direction::Down]);
+        assert_eq!(safe, [Direction::Up, Direction::Down]);
+        assert_eq!(game.fallback_move(0), Direction::Up);
+
+        // Shorter enemies are no danger
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            v . . . . . .
+            > > 0 . 1 < .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.snakes[0].body.len(), 4);
+        assert!(game.safe_moves(0).eq(game.valid_moves(0)));
+
+        // Only dangerous moves left
+        let game = Game::parse(
+            r#"
+            > 0 . . .
+            ^ . 1 . .
+            ^ . ^ . .
+            . . ^ . .
+            . . ^ . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.valid_moves(0).count(), 2);
+        assert_eq!(game.safe_moves(0).count(), 0);
+        assert_eq!(game.fallback_move(0), Direction::Right);
+    }
+
+    #[test]
+    fn game_squads() {
+        use super::*;
+        use crate::env::v2;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . v . . .
+            > > 0 v . . .
+            . . . 1 . . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        // Without squads we bite into the body
+        let mut solo = game.clone();
+        assert!(!solo.move_is_valid(0, Direction::Right));
+        solo.step(&[Direction::Right, Direction::Down]);
+        assert!(!solo.snake_is_alive(0));
+
+        let mut squad = game.clone();
+        squad.snakes[0].squad = Some(0);
+        squad.snakes[1].squad = Some(0);
+        assert!(squad.snakes[0].is_teammate(&squad.snakes[1]));
+        assert!(squad.move_is_valid(0, Direction::Right));
+        assert_eq!(squad.outcome(), Outcome::Winner(1));
+
+        let mut delta = StepDelta::default();
+        let before = squad.clone();
+        squad.step_undoable(&[Direction::Right, Direction::Down], &mut delta);
+        info!("{squad:?}");
+        assert!(squad.snake_is_alive(0));
+        assert!(squad.snake_is_alive(1));
+        // The crossing is occupied by both
+        assert_eq!(squad.snakes[0].head(), v2(3, 3));
+        assert!(squad.snakes[1].body.contains(&v2(3, 3)));
+        squad.undo_step(&mut delta);
+        assert!(squad == before);
+        squad.step(&[Direction::Right, Direction::Down]);
+
+        squad.step(&[Direction::Right, Direction::Down]);
+        squad.step(&[Direction::Right, Direction::Left]);
+        assert!(squad.snake_is_alive(0));
+        assert!(squad.snake_is_alive(1));
+        // The cell is still occupied after one of them left it
+        assert!(squad.grid[v2(3, 3)].t == CellT::Owned);
+        for snake in &squad.snakes {
+            for &p in &snake.body {
+                assert!(squad.grid[p].t == CellT::Owned);
+            }
+        }
+
+        // Teammates do not lose head to heads
+        let mut h2h = before.clone();
+        h2h.snakes[1].body = [v2(3, 6), v2(3, 5), v2(3, 4)].into();
+        for p in [v2(3, 3), v2(3, 2)] {
+            h2h.grid[p].t = CellT::Free;
+        }
+        for p in [v2(3, 6), v2(3, 5)] {
+            h2h.grid[p].t = CellT::Owned;
+        }
+        let mut solo = h2h.clone();
+        solo.snakes[0].squad = None;
+        solo.step(&[Direction::Right, Direction::Down]);
+        assert_eq!(solo.outcome(), Outcome::Match);
+        h2h.step(&[Direction::Right, Direction::Down]);
+        assert!(h2h.snake_is_alive(0));
+        assert!(h2h.snake_is_alive(1));
+
+        // Squads from the API
+        let mut request = game.to_request(0, "squad");
+        request.board.snakes[0].squad = "red".into();
+        request.board.snakes[1].squad = "red".into();
+        request.you.squad = "red".into();
+        let game = Game::from_request(&request);
+        assert_eq!(game.snakes[0].squad, Some(0));
+        assert!(game.snakes[0].is_teammate(&game.snakes[1]));
+        let request = game.to_request(0, "squad");
+        assert_eq!(request.board.snakes[0].squad, request.board.snakes[1].squad);
+    }
+
+    #[test]
+    fn game_stacked_hazards() {
+        use super::*;
+        use crate::env::v2;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . . . . .
+            0 . . . 1
+            ^ . . . ^
+            ^ . . . ^"#,
+        )
+        .unwrap();
+        game.grid.hazard_damage = 14;
+        game.grid.add_hazards(&[v2(0, 3), v2(4, 3), v2(4, 3)]);
+        assert_eq!(game.grid[v2(0, 3)].hazard, 1);
+        assert_eq!(game.grid[v2(4, 3)].hazard, 2);
+        assert_eq!(game.grid.damage(v2(1, 1)), 1);
+
+        game.step(&[Direction::Up, Direction::Up]);
+        info!("{game:?}");
+        let single = 100 - game.snakes[0].health;
+        let double = 100 - game.snakes[1].health;
+        assert_eq!(single, 1 + 14);
+        assert_eq!(double, 1 + 2 * 14);
+
+        // The layers are kept in the API requests
+        let request = game.to_request(1, "standard");
+        assert_eq!(request.board.hazards.len(), 3);
+        let mut restored = Game::from_request(&request);
+        restored.grid.hazard_damage = 14;
+        assert!(restored.grid.cells == game.grid.cells);
+        // Different number of layers
+        let mut single = game.clone();
+        single.grid[v2(4, 3)].hazard = 1;
+        assert_ne!(game.zobrist_hash(), single.zobrist_hash());
+    }
+
+    #[test]
+    fn game_max_enemies() {
+        use super::*;
+        logging();
+
+        // Snake 6 is the farthest away
+        let game = Game::parse(
+            r#"
+            . . . . . . . . . . 6
+            . . . . . . . . . . ^
+            . . . . . . . . . . ^
+            . . . . . . . . . . .
+            . . 4 . 5 . . . . . .
+            . . ^ . ^ . . . . . .
+            . . ^ . ^ . . . . . .
+            . 2 . 0 . 3 . . . . .
+            . ^ . ^ . ^ . . . . .
+            . ^ . ^ . ^ 1 < . . .
+            . . . . . . . ^ . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.snakes.len(), 7);
+        let request = game.to_request(game.turn, "standard");
+
+        let kept = Game::from_request_with(&request, 5);
+        info!("{kept:?}");
+        assert_eq!(kept.snakes.len(), 6);
+        assert!(kept.snakes[0].body == game.snakes[0].body);
+        assert!(kept.snakes.iter().all(|s| s.body != game.snakes[6].body));
+
+        assert_eq!(Game::from_request(&request).snakes.len(), MAX_ENEMIES + 1);
+        assert_eq!(Game::from_request_with(&request, 6).snakes.len(), 7);
+        // No limit at all
+        assert_eq!(
+            Game::from_request_with(&request, usize::MAX).snakes.len(),
+            7
+        );
+    }
+
+    #[test]
+    fn game_threat_map() {
+        use super::*;
+        logging();
+
+        // 1 is longer and 2 is shorter than 0
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . 1 . . . . .
+            . ^ . . . . .
+            . ^ . 0 . . .
+            . ^ . ^ . 2 .
+            . ^ . ^ . ^ .
+            . . . ^ . . ."#,
+        )
+        .unwrap();
+        let threats = game.threat_map();
+        let threat = |x: usize, y: usize| threats[x + y * game.grid.width];
+        info!("{threats:?}");
+
+        assert_eq!(threat(1, 6), 1.0);
+        assert_eq!(threat(0, 5), 1.0);
+        assert_eq!(threat(5, 3), 0.5);
+        assert_eq!(threat(6, 2), 0.5);
+        assert_eq!(threat(2, 5), 1.0);
+        assert_eq!(threat(4, 2), 0.5);
+        assert!(threat(1, 6) > threat(5, 3));
+        // Unreachable cells
+        assert_eq!(threat(3, 4), 0.0);
+        assert_eq!(threat(1, 1), 0.0);
+        assert_eq!(threat(6, 6), 0.0);
+    }
 }
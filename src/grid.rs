@@ -1,13 +1,15 @@
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 use std::mem::size_of;
 use std::ops::{Index, IndexMut};
-use std::{f64, usize};
+
+use serde::{Deserialize, Serialize};
 
 use crate::env::{Direction, Vec2D, HAZARD_DAMAGE};
 use crate::util::OrdPair;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CellT {
     Free,
     Food,
@@ -15,15 +17,16 @@ pub enum CellT {
 }
 
 /// Represents a single tile of the board
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cell {
     pub t: CellT,
-    pub hazard: bool,
+    /// Number of stacked hazard layers.
+    pub hazard: u8,
 }
 const _: () = assert!(size_of::<Cell>() == 2);
 
 impl Cell {
-    pub const fn new(t: CellT, hazard: bool) -> Self {
+    pub const fn new(t: CellT, hazard: u8) -> Self {
         Self { t, hazard }
     }
 }
@@ -32,7 +35,7 @@ impl std::fmt::Debug for Cell {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use owo_colors::{OwoColorize, Style};
 
-        let style = if self.hazard {
+        let style = if self.hazard > 0 {
             Style::new().on_bright_black()
         } else {
             Style::new()
@@ -50,11 +53,14 @@ impl std::fmt::Debug for Cell {
 ///
 /// This is allows fast access to specific positions on the grid and
 /// if they are occupied by enemies or food.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Grid {
     pub width: usize,
     pub height: usize,
     pub cells: Vec<Cell>,
+    /// Health damage for ending a turn in a hazard, per layer.
+    /// This stacks on top of the regular 1 damage per turn.
+    pub hazard_damage: u8,
 }
 
 impl Grid {
@@ -64,7 +70,8 @@ impl Grid {
         Self {
             width,
             height,
-            cells: vec![Cell::new(CellT::Free, false); width * height],
+            cells: vec![Cell::new(CellT::Free, 0); width * height],
+            hazard_damage: HAZARD_DAMAGE,
         }
     }
 
@@ -79,13 +86,14 @@ impl Grid {
             width,
             height,
             cells,
+            hazard_damage: HAZARD_DAMAGE,
         }
     }
 
     /// Clears the grid.
     pub fn clear(&mut self) {
         for c in &mut self.cells {
-            *c = Cell::new(CellT::Food, false);
+            *c = Cell::new(CellT::Food, 0);
         }
     }
 
@@ -111,14 +119,46 @@ impl Grid {
     pub fn add_hazards(&mut self, hazards: &[Vec2D]) {
         for &p in hazards {
             if self.has(p) {
-                self[p].hazard = true;
+                self[p].hazard = self[p].hazard.saturating_add(1);
             }
         }
     }
 
     /// Returns if the cell is hazardous.
     pub fn is_hazardous(&self, p: Vec2D) -> bool {
-        self.has(p) && self[p].hazard
+        self.has(p) && self[p].hazard > 0
+    }
+
+    /// Returns the health damage for ending a turn on the cell.
+    /// The hazard damage is applied for every layer in addition to the
+    /// regular 1 damage per turn.
+    #[inline]
+    pub fn damage(&self, p: Vec2D) -> u8 {
+        self[p]
+            .hazard
+            .saturating_mul(self.hazard_damage)
+            .saturating_add(1)
+    }
+
+    /// Returns the centroid of all cells without hazards, which is the
+    /// geometric center if there are no hazards (or only hazards).
+    pub fn safe_center(&self) -> Vec2D {
+        let (mut x, mut y, mut n) = (0, 0, 0);
+        for py in 0..self.height {
+            for px in 0..self.width {
+                if self[Vec2D::new(px as _, py as _)].hazard == 0 {
+                    x += px;
+                    y += py;
+                    n += 1;
+                }
+            }
+        }
+        if n == 0 {
+            return Vec2D::new(self.width as i16 / 2, self.height as i16 / 2);
+        }
+        // Rounding half up matches the geometric center of even boards
+        let n = n as f64;
+        Vec2D::new((x as f64 / n).round() as _, (y as f64 / n).round() as _)
     }
 
     /// Returns if `p` is within the boundaries of this grid.
@@ -127,8 +167,21 @@ impl Grid {
         p.within(self.width, self.height)
     }
 
+    /// Applies the direction to `p` and wraps the result around the board
+    /// edges, as in the `wrapped` ruleset.
+    #[inline]
+    pub fn wrapping_apply(&self, p: Vec2D, d: Direction) -> Vec2D {
+        let p = p.apply(d);
+        Vec2D::new(
+            p.x.rem_euclid(self.width as i16),
+            p.y.rem_euclid(self.height as i16),
+        )
+    }
+
     /// Performes an A* search that applies the `first_move_heuristic` as
     /// additional costs for the first move.
+    ///
+    /// Hazards cost the `hazard_damage` of the ruleset.
     #[must_use]
     pub fn a_star(
         &self,
@@ -136,17 +189,67 @@ impl Grid {
         target: Vec2D,
         first_move_heuristic: &[f64; 4],
     ) -> Option<Vec<Vec2D>> {
-        fn make_path(data: &HashMap<Vec2D, (Vec2D, f64)>, target: Vec2D) -> Vec<Vec2D> {
-            let mut path = Vec::new();
-            let mut p = target;
-            while p.x >= 0 {
-                path.push(p);
-                p = data.get(&p).unwrap().0;
-            }
-            path.reverse();
-            path
+        self.a_star_hazard(
+            start,
+            target,
+            first_move_heuristic,
+            self.hazard_damage,
+            None,
+        )
+    }
+
+    /// Performes an A* search like `a_star`, where entering a hazard costs
+    /// `hazard_damage` in addition to the step.
+    ///
+    /// If the `health` is given, the hazard costs are scaled inversely to it
+    /// (`100 / health`), so that weak snakes avoid hazards more strongly.
+    #[must_use]
+    pub fn a_star_hazard(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        first_move_heuristic: &[f64; 4],
+        hazard_damage: u8,
+        health: Option<u8>,
+    ) -> Option<Vec<Vec2D>> {
+        let hazard_cost = match health {
+            Some(health) => hazard_damage as f64 * 100.0 / health.max(1) as f64,
+            None => hazard_damage as f64,
+        };
+        self.a_star_impl(start, target, first_move_heuristic, hazard_cost, false)
+    }
+
+    /// Performes an A* search like `a_star` on a wrapped board,
+    /// where paths can cross the board edges.
+    #[must_use]
+    pub fn a_star_wrapped(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        first_move_heuristic: &[f64; 4],
+    ) -> Option<Vec<Vec2D>> {
+        let hazard_cost = self.hazard_damage as f64;
+        self.a_star_impl(start, target, first_move_heuristic, hazard_cost, true)
+    }
+
+    /// Returns the manhattan distance between `a` and `b`, which is the
+    /// minimum over both ways around the board if `wrapped`.
+    fn distance(&self, a: Vec2D, b: Vec2D, wrapped: bool) -> u64 {
+        if wrapped {
+            a.manhattan_wrapped(b, self.width, self.height)
+        } else {
+            (a - b).manhattan()
         }
+    }
 
+    fn a_star_impl(
+        &self,
+        start: Vec2D,
+        target: Vec2D,
+        first_move_heuristic: &[f64; 4],
+        hazard_cost: f64,
+        wrapped: bool,
+    ) -> Option<Vec<Vec2D>> {
         let mut queue = BinaryHeap::new();
         let mut data: HashMap<Vec2D, (Vec2D, f64)> = HashMap::new();
         data.insert(start, (Vec2D::new(-1, -1), 0.0));
@@ -160,10 +263,14 @@ impl Grid {
             }
 
             for d in Direction::all() {
-                let neighbor = front.apply(d);
+                let neighbor = if wrapped {
+                    self.wrapping_apply(front, d)
+                } else {
+                    front.apply(d)
+                };
                 let mut neighbor_cost = cost + 1.0;
                 if self.is_hazardous(neighbor) {
-                    neighbor_cost += HAZARD_DAMAGE as f64;
+                    neighbor_cost += hazard_cost;
                 }
                 if front == start {
                     neighbor_cost += first_move_heuristic[d as usize];
@@ -174,7 +281,8 @@ impl Grid {
                     if neighbor_cost < cost_so_far {
                         data.insert(neighbor, (front, neighbor_cost));
                         // queue does not accept float
-                        let estimated_cost = neighbor_cost + (neighbor - start).manhattan() as f64;
+                        let estimated_cost =
+                            neighbor_cost + self.distance(neighbor, target, wrapped) as f64;
                         queue.push(OrdPair(Reverse((estimated_cost * 10.0) as usize), neighbor));
                     }
                 }
@@ -183,6 +291,155 @@ impl Grid {
 
         None
     }
+
+    /// Performes a Dijkstra search from `start` that stops at the first
+    /// reached target, returning this target and the path to it.
+    ///
+    /// The costs are the same as for `a_star`, including the
+    /// `first_move_heuristic`.
+    /// This is faster than an `a_star` for every target.
+    #[must_use]
+    pub fn nearest_target(
+        &self,
+        start: Vec2D,
+        targets: &[Vec2D],
+        first_move_heuristic: &[f64; 4],
+    ) -> Option<(Vec2D, Vec<Vec2D>)> {
+        let mut queue = BinaryHeap::new();
+        let mut data: HashMap<Vec2D, (Vec2D, f64)> = HashMap::new();
+        data.insert(start, (Vec2D::new(-1, -1), 0.0));
+
+        queue.push(OrdPair(Reverse(0), start));
+        while let Some(OrdPair(Reverse(queued), front)) = queue.pop() {
+            let cost = data.get(&front).unwrap().1;
+            // skip outdated entries
+            if queued > (cost * 10.0) as usize {
+                continue;
+            }
+
+            if targets.contains(&front) {
+                return Some((front, make_path(&data, front)));
+            }
+
+            for d in Direction::all() {
+                let neighbor = front.apply(d);
+                let mut neighbor_cost = cost + 1.0;
+                if self.is_hazardous(neighbor) {
+                    neighbor_cost += self.hazard_damage as f64;
+                }
+                if front == start {
+                    neighbor_cost += first_move_heuristic[d as usize];
+                }
+
+                if self.has(neighbor) && self[neighbor].t != CellT::Owned {
+                    let cost_so_far = data.get(&neighbor).map_or(f64::MAX, |(_, c)| *c);
+                    if neighbor_cost < cost_so_far {
+                        data.insert(neighbor, (front, neighbor_cost));
+                        // queue does not accept float
+                        queue.push(OrdPair(Reverse((neighbor_cost * 10.0) as usize), neighbor));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the number of steps from `start` to every cell (row-major
+    /// from the bottom left), or `None` if the cell is not reachable.
+    ///
+    /// Cells owned by snakes are blocked, except for the `start` itself.
+    #[must_use]
+    pub fn bfs_distances(&self, start: Vec2D) -> Vec<Option<u32>> {
+        let mut distances = vec![None; self.cells.len()];
+        if !self.has(start) {
+            return distances;
+        }
+
+        distances[start.x as usize + start.y as usize * self.width] = Some(0);
+        let mut queue = VecDeque::from([(start, 0)]);
+        while let Some((p, distance)) = queue.pop_front() {
+            for d in Direction::all() {
+                let p = p.apply(d);
+                if self.has(p) && self[p].t != CellT::Owned {
+                    let i = p.x as usize + p.y as usize * self.width;
+                    if distances[i].is_none() {
+                        distances[i] = Some(distance + 1);
+                        queue.push_back((p, distance + 1));
+                    }
+                }
+            }
+        }
+        distances
+    }
+
+    /// Returns the chokepoints of the free space reachable from `from`,
+    /// the cells whose removal would disconnect this space.
+    ///
+    /// This is the lowlink algorithm of Tarjan, implemented as iterative DFS
+    /// over the cells that are not owned by snakes.
+    /// `from` itself (usually the head) is never reported.
+    #[must_use]
+    pub fn articulation_points(&self, from: Vec2D) -> Vec<Vec2D> {
+        let index = |p: Vec2D| p.x as usize + p.y as usize * self.width;
+
+        // Discovery times (0 = unvisited) and lowest reachable discovery times
+        let mut discovered = vec![0; self.cells.len()];
+        let mut low = vec![0; self.cells.len()];
+        let mut points = vec![false; self.cells.len()];
+
+        let mut time = 1;
+        discovered[index(from)] = time;
+        low[index(from)] = time;
+
+        // Cells on the DFS path and their next direction
+        let mut stack = vec![(from, 0)];
+        while let Some((p, next)) = stack.last_mut() {
+            let p = *p;
+            if let Some(&d) = Direction::all().get(*next) {
+                *next += 1;
+                let neighbor = p.apply(d);
+                if !self.has(neighbor) || self[neighbor].t == CellT::Owned {
+                    continue;
+                }
+
+                if discovered[index(neighbor)] == 0 {
+                    time += 1;
+                    discovered[index(neighbor)] = time;
+                    low[index(neighbor)] = time;
+                    stack.push((neighbor, 0));
+                } else {
+                    low[index(p)] = low[index(p)].min(discovered[index(neighbor)]);
+                }
+            } else {
+                stack.pop();
+                if let Some(&(parent, _)) = stack.last() {
+                    low[index(parent)] = low[index(parent)].min(low[index(p)]);
+                    // The subtree of p cannot reach anything above its parent
+                    if parent != from && low[index(p)] >= discovered[index(parent)] {
+                        points[index(parent)] = true;
+                    }
+                }
+            }
+        }
+
+        (0..self.height as i16)
+            .flat_map(|y| (0..self.width as i16).map(move |x| Vec2D::new(x, y)))
+            .filter(|&p| points[index(p)])
+            .collect()
+    }
+}
+
+/// Reconstructs the path to `target` from the predecessors of a search.
+fn make_path(data: &HashMap<Vec2D, (Vec2D, f64)>, target: Vec2D) -> Vec<Vec2D> {
+    let mut path = Vec::new();
+    let mut p = target;
+    while p.x >= 0 {
+        path.push(p);
+        p = data.get(&p).unwrap().0;
+    }
+    path.reverse();
+    path
 }
 
 impl Index<Vec2D> for Grid {
@@ -255,4 +512,194 @@ mod test {
         assert_eq!(path[0], Vec2D::new(0, 2));
         assert_eq!(path[path.len() - 1], Vec2D::new(4, 2));
     }
+
+    #[test]
+    fn grid_a_star_hazard_damage() {
+        use super::*;
+        logging();
+        let mut grid = Grid::new(5, 5);
+        grid.add_hazards(&[
+            Vec2D::new(2, 0),
+            Vec2D::new(2, 1),
+            Vec2D::new(2, 2),
+            Vec2D::new(2, 3),
+        ]);
+        let start = Vec2D::new(0, 2);
+        let target = Vec2D::new(4, 2);
+        let costs = [0.0; 4];
+
+        // Low damage: straight through the hazard
+        let path = grid.a_star_hazard(start, target, &costs, 1, None).unwrap();
+        info!("{:?}", path);
+        assert_eq!(path.len(), 5);
+        assert!(path.iter().any(|&p| grid.is_hazardous(p)));
+
+        // High damage: around the hazard
+        let path = grid.a_star_hazard(start, target, &costs, 14, None).unwrap();
+        info!("{:?}", path);
+        assert_eq!(path.len(), 9);
+        assert!(!path.iter().any(|&p| grid.is_hazardous(p)));
+
+        // Low health avoids even low damage hazards
+        let path = grid
+            .a_star_hazard(start, target, &costs, 2, Some(100))
+            .unwrap();
+        assert_eq!(path.len(), 5);
+        let path = grid
+            .a_star_hazard(start, target, &costs, 2, Some(20))
+            .unwrap();
+        info!("{:?}", path);
+        assert_eq!(path.len(), 9);
+    }
+
+    #[test]
+    fn grid_a_star_wrapped() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        // The wall blocks the direct path
+        let game = Game::parse(
+            r#"
+            . . . . . . . . .
+            . . v < < < < . .
+            . . v . . . ^ . .
+            . 0 v . . . ^ . .
+            . . v . . . ^ . .
+            . . > > > > ^ . .
+            . . . . . . . . ."#,
+        )
+        .unwrap();
+        let start = game.snakes[0].head();
+        let target = Vec2D::new(7, 3);
+        let costs = [0.0; 4];
+
+        let path = game.grid.a_star(start, target, &costs).unwrap();
+        info!("{path:?}");
+        assert_eq!(path.len(), 13);
+
+        let path = game.grid.a_star_wrapped(start, target, &costs).unwrap();
+        info!("{path:?}");
+        assert_eq!(path.len(), 4);
+        assert_eq!(path[1], Vec2D::new(0, 3));
+        assert_eq!(path[2], Vec2D::new(8, 3));
+        assert_eq!(*path.last().unwrap(), target);
+    }
+
+    #[test]
+    fn grid_safe_center() {
+        use super::*;
+        logging();
+
+        assert_eq!(Grid::new(11, 11).safe_center(), Vec2D::new(5, 5));
+        assert_eq!(Grid::new(8, 6).safe_center(), Vec2D::new(4, 3));
+
+        // Hazards on the left side move the center to the right
+        let mut grid = Grid::new(11, 11);
+        let hazards: Vec<_> = (0..4)
+            .flat_map(|x| (0..11).map(move |y| Vec2D::new(x, y)))
+            .collect();
+        grid.add_hazards(&hazards);
+        info!("{:?}", grid.safe_center());
+        assert_eq!(grid.safe_center(), Vec2D::new(7, 5));
+
+        // Only hazards
+        let all: Vec<_> = (0..11)
+            .flat_map(|x| (0..11).map(move |y| Vec2D::new(x, y)))
+            .collect();
+        grid.add_hazards(&all);
+        assert_eq!(grid.safe_center(), Vec2D::new(5, 5));
+    }
+
+    #[test]
+    fn grid_bfs_distances() {
+        use super::*;
+        logging();
+
+        let grid = Grid::new(11, 11);
+        let distances = grid.bfs_distances(Vec2D::new(5, 5));
+        let distance = |x: usize, y: usize| distances[x + y * grid.width];
+        assert_eq!(distance(5, 5), Some(0));
+        assert_eq!(distance(0, 0), Some(10));
+        assert_eq!(distance(10, 0), Some(10));
+        assert_eq!(distance(0, 10), Some(10));
+        assert_eq!(distance(10, 10), Some(10));
+        assert!(distances.iter().all(Option::is_some));
+
+        // Wall with a single opening at the top
+        let mut grid = Grid::new(5, 5);
+        grid.add_snake((0..4).map(|y| Vec2D::new(2, y)));
+        let distances = grid.bfs_distances(Vec2D::new(0, 0));
+        info!("{grid:?} {distances:?}");
+        let distance = |x: usize, y: usize| distances[x + y * grid.width];
+        assert_eq!(distance(2, 4), Some(6));
+        assert_eq!(distance(4, 0), Some(12));
+        assert_eq!(distance(2, 0), None);
+        assert_eq!(distances.iter().filter(|d| d.is_none()).count(), 4);
+    }
+
+    #[test]
+    fn grid_articulation_points() {
+        use super::*;
+        logging();
+
+        // Two rooms connected by a single corridor cell at (3, 2)
+        let mut grid = Grid::new(7, 5);
+        grid.add_snake((0..5).filter(|&y| y != 2).map(|y| Vec2D::new(3, y)));
+        info!("{grid:?}");
+
+        let points = grid.articulation_points(Vec2D::new(0, 0));
+        info!("{points:?}");
+        // The corridor and its entrances
+        assert_eq!(
+            points,
+            [Vec2D::new(2, 2), Vec2D::new(3, 2), Vec2D::new(4, 2)]
+        );
+
+        // Without the corridor there are no chokepoints
+        let grid = Grid::new(7, 5);
+        assert!(grid.articulation_points(Vec2D::new(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn grid_nearest_target() {
+        use super::*;
+        use crate::game::Game;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            o . . . . . .
+            . . . . . . .
+            . . > > v . o
+            . . ^ . 0 . .
+            . . ^ . . . .
+            . . . . . . .
+            o . . . . o ."#,
+        )
+        .unwrap();
+        let food = [
+            Vec2D::new(0, 6),
+            Vec2D::new(6, 4),
+            Vec2D::new(0, 0),
+            Vec2D::new(5, 0),
+        ];
+        let start = game.snakes[0].head();
+        let costs = [0.0; 4];
+
+        let expected = food
+            .iter()
+            .filter_map(|&p| game.grid.a_star(start, p, &costs))
+            .min_by_key(|path| path.len())
+            .unwrap();
+
+        let (target, path) = game.grid.nearest_target(start, &food, &costs).unwrap();
+        info!("{target:?} {path:?}");
+        assert_eq!(target, *expected.last().unwrap());
+        assert_eq!(path.len(), expected.len());
+        assert_eq!(path[0], start);
+        assert_eq!(*path.last().unwrap(), target);
+
+        assert!(game.grid.nearest_target(start, &[], &costs).is_none());
+    }
 }
@@ -1,21 +1,85 @@
 use env_logger::fmt::Formatter;
 use owo_colors::{AnsiColors, OwoColorize};
 use std::io::Write;
+use std::str::FromStr;
 
 // Exported to be accessable in benchmarks
 pub mod agents;
+pub mod dataset;
+pub mod debug;
 pub mod env;
 pub mod floodfill;
 pub mod game;
 pub mod grid;
+pub mod metrics;
+pub mod render;
+pub mod savegame;
 pub mod search;
+pub mod server;
+pub mod simulation;
 mod util;
+pub mod zobrist;
+
+/// Environment variable that selects the [LogFormat].
+pub const LOG_FORMAT_ENV: &str = "SNORK_LOG_FORMAT";
+
+/// Output format of the log records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Colored text for humans.
+    #[default]
+    Text,
+    /// One JSON object per line for log aggregators.
+    Json,
+}
+
+impl LogFormat {
+    /// Format selected by the `SNORK_LOG_FORMAT` environment variable.
+    pub fn from_env() -> Self {
+        std::env::var(LOG_FORMAT_ENV)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown log format {s:?}")),
+        }
+    }
+}
 
 pub fn logging() {
-    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .is_test(cfg!(test))
-        .format(logging_format)
-        .try_init();
+    logging_with(LogFormat::from_env())
+}
+
+pub fn logging_with(format: LogFormat) {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    builder.is_test(cfg!(test));
+    match format {
+        LogFormat::Text => builder.format(logging_format),
+        LogFormat::Json => builder.format(|buf, record| writeln!(buf, "{}", json_record(record))),
+    };
+    let _ = builder.try_init();
+}
+
+/// Returns a fresh temporary directory path that is unique to this test run.
+/// The directory itself is not created.
+#[cfg(test)]
+pub(crate) fn unique_temp_dir(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("{name}_{}_{n}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
 }
 
 fn logging_format(buf: &mut Formatter, record: &log::Record) -> std::io::Result<()> {
@@ -40,3 +104,61 @@ fn logging_format(buf: &mut Formatter, record: &log::Record) -> std::io::Result<
         .color(color)
     )
 }
+
+/// Serializes the record to a single line of JSON.
+/// The `game_id` is taken from the key-values of the record, if present.
+fn json_record(record: &log::Record) -> String {
+    let mut line = serde_json::json!({
+        "level": record.level().as_str(),
+        "file": record.file().unwrap_or_default(),
+        "line": record.line().unwrap_or_default(),
+        "msg": record.args().to_string(),
+    });
+    if let Some(game_id) = record.key_values().get("game_id".into()) {
+        line["game_id"] = game_id.to_string().into();
+    }
+    line.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    #[test]
+    fn logging_json() {
+        use super::*;
+        logging();
+
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!("Text".parse(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
+
+        let kvs = [("game_id", "abc")];
+        let record = log::Record::builder()
+            .args(format_args!("move \"up\""))
+            .level(log::Level::Warn)
+            .file(Some("src/bin/server.rs"))
+            .line(Some(42))
+            .key_values(&kvs)
+            .build();
+        let line = json_record(&record);
+        info!("{line}");
+        assert!(!line.contains('\n'));
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["msg"], "move \"up\"");
+        assert_eq!(value["file"], "src/bin/server.rs");
+        assert_eq!(value["line"], 42);
+        assert_eq!(value["game_id"], "abc");
+
+        // Without a game
+        let record = log::Record::builder()
+            .args(format_args!("index"))
+            .level(log::Level::Info)
+            .build();
+        let value: serde_json::Value = serde_json::from_str(&json_record(&record)).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert!(value.get("game_id").is_none());
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Number of recent response times used for the percentiles.
+const RESPONSE_WINDOW: usize = 1024;
+
+/// Server statistics, exported in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    games_started: AtomicU64,
+    games_ended: AtomicU64,
+    moves: AtomicU64,
+    /// Sum of all response times in µs
+    response_total: AtomicU64,
+    /// Recent response times in µs
+    responses: Mutex<VecDeque<u64>>,
+}
+
+impl Metrics {
+    pub fn start(&self) {
+        self.games_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn end(&self) {
+        self.games_ended.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a served move and its response time.
+    pub fn record_move(&self, time: Duration) {
+        let time = time.as_micros() as u64;
+        self.moves.fetch_add(1, Ordering::Relaxed);
+        self.response_total.fetch_add(time, Ordering::Relaxed);
+
+        let mut responses = self.responses.lock().unwrap();
+        if responses.len() >= RESPONSE_WINDOW {
+            responses.pop_front();
+        }
+        responses.push_back(time);
+    }
+
+    /// Number of served moves.
+    pub fn moves(&self) -> u64 {
+        self.moves.load(Ordering::Relaxed)
+    }
+
+    /// Average response time of all moves.
+    pub fn average(&self) -> Duration {
+        let moves = self.moves();
+        let total = self.response_total.load(Ordering::Relaxed);
+        Duration::from_micros(total.checked_div(moves).unwrap_or_default())
+    }
+
+    /// Percentile (0 to 100) of the recent response times.
+    pub fn percentile(&self, p: usize) -> Duration {
+        let mut responses: Vec<u64> = self.responses.lock().unwrap().iter().copied().collect();
+        if responses.is_empty() {
+            return Duration::ZERO;
+        }
+        responses.sort_unstable();
+        let i = (responses.len() * p.min(100))
+            .div_ceil(100)
+            .saturating_sub(1);
+        Duration::from_micros(responses[i])
+    }
+
+    /// Returns the metrics in the Prometheus text format.
+    pub fn render(&self, active_games: usize) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+            let _ = writeln!(out, "# HELP snork_{name} {help}");
+            let _ = writeln!(out, "# TYPE snork_{name} {kind}");
+            let _ = writeln!(out, "snork_{name} {value}");
+        };
+        metric(
+            "games_started_total",
+            "counter",
+            "Number of started games.",
+            self.games_started.load(Ordering::Relaxed) as f64,
+        );
+        metric(
+            "games_ended_total",
+            "counter",
+            "Number of ended games.",
+            self.games_ended.load(Ordering::Relaxed) as f64,
+        );
+        metric(
+            "moves_total",
+            "counter",
+            "Number of served moves.",
+            self.moves() as f64,
+        );
+        metric(
+            "response_seconds_avg",
+            "gauge",
+            "Average response time of the moves.",
+            self.average().as_secs_f64(),
+        );
+        metric(
+            "response_seconds_p99",
+            "gauge",
+            "99th percentile of the recent response times.",
+            self.percentile(99).as_secs_f64(),
+        );
+        metric(
+            "active_games",
+            "gauge",
+            "Number of running game instances.",
+            active_games as f64,
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn metrics() {
+        use super::*;
+        logging();
+
+        let metrics = Metrics::default();
+        metrics.start();
+        for ms in [10, 20, 30, 100] {
+            metrics.record_move(Duration::from_millis(ms));
+        }
+        metrics.end();
+
+        assert_eq!(metrics.moves(), 4);
+        assert_eq!(metrics.average(), Duration::from_millis(40));
+        assert_eq!(metrics.percentile(50), Duration::from_millis(20));
+        assert_eq!(metrics.percentile(99), Duration::from_millis(100));
+
+        let text = metrics.render(1);
+        info!("{text}");
+        assert!(text.lines().any(|l| l == "snork_moves_total 4"));
+        assert!(text.lines().any(|l| l == "snork_games_started_total 1"));
+        assert!(text.lines().any(|l| l == "snork_games_ended_total 1"));
+        assert!(text.lines().any(|l| l == "snork_response_seconds_p99 0.1"));
+        assert!(text.lines().any(|l| l == "snork_active_games 1"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Renders games to SVG images, for sharing and debugging simulated games.
+//!
+//! The colors match the `Debug` output of the [Game].
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::env::Vec2D;
+use crate::game::Game;
+use crate::grid::CellT;
+
+/// Size of a cell in pixels.
+const CELL: usize = 20;
+
+/// Colors of the snakes by their index.
+fn snake_color(id: usize) -> &'static str {
+    match id {
+        0 => "#4caf50",
+        1 => "#fdd835",
+        2 => "#1e88e5",
+        3 => "#d81b60",
+        _ => "#00acc1",
+    }
+}
+
+/// Returns the game as SVG image.
+///
+/// As for the `Debug` output, the top row is the highest `y` coordinate.
+pub fn svg(game: &Game) -> String {
+    let width = game.grid.width * CELL;
+    let height = game.grid.height * CELL;
+    // Upper left corner of a cell
+    let corner = |p: Vec2D| {
+        (
+            p.x as usize * CELL,
+            (game.grid.height - 1 - p.y as usize) * CELL,
+        )
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = writeln!(
+        out,
+        r##"<rect width="100%" height="100%" fill="#212121"/>"##
+    );
+
+    for y in 0..game.grid.height {
+        for x in 0..game.grid.width {
+            let p = Vec2D::new(x as _, y as _);
+            let (cx, cy) = corner(p);
+            let cell = game.grid[p];
+            if cell.hazard > 0 {
+                let _ = writeln!(
+                    out,
+                    r##"<rect x="{cx}" y="{cy}" width="{CELL}" height="{CELL}" fill="#757575" fill-opacity="0.5"/>"##
+                );
+            }
+            if cell.t == CellT::Food {
+                let _ = writeln!(
+                    out,
+                    r##"<circle cx="{}" cy="{}" r="{}" fill="#e53935"/>"##,
+                    cx + CELL / 2,
+                    cy + CELL / 2,
+                    CELL / 4
+                );
+            }
+        }
+    }
+
+    for (id, snake) in game.snakes.iter().enumerate() {
+        if !snake.alive() {
+            continue;
+        }
+        let color = snake_color(id);
+        for &p in snake.body.iter().rev().skip(1) {
+            let (cx, cy) = corner(p);
+            let _ = writeln!(
+                out,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{color}" fill-opacity="0.7"/>"#,
+                cx + 1,
+                cy + 1,
+                CELL - 2,
+                CELL - 2
+            );
+        }
+        let (cx, cy) = corner(snake.head());
+        let _ = writeln!(
+            out,
+            r#"<rect x="{cx}" y="{cy}" width="{CELL}" height="{CELL}" rx="4" fill="{color}"/>"#
+        );
+        let _ = writeln!(
+            out,
+            r##"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central" fill="#212121">{id}</text>"##,
+            cx + CELL / 2,
+            cy + CELL / 2,
+            CELL * 3 / 4
+        );
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Writes the game as `turn_<turn>.svg` frame into `dir`.
+pub fn save_frame(dir: &Path, game: &Game) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("turn_{:04}.svg", game.turn));
+    fs::write(&path, svg(game))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[test]
+    fn render_frames() {
+        use super::*;
+        use crate::env::Direction;
+        logging();
+
+        let mut game = Game::parse(
+            r#"
+            . . . . .
+            . 0 . o .
+            . ^ . . .
+            . ^ 1 < <
+            . . . . ."#,
+        )
+        .unwrap();
+        game.grid[Vec2D::new(0, 0)].hazard = 1;
+
+        let svg = svg(&game);
+        info!("{svg}");
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        // Food and hazard
+        assert_eq!(svg.matches("<circle").count(), 1);
+        assert!(svg.contains("#757575"));
+        // The top row is the highest y
+        assert!(svg.contains(r#"<rect x="20" y="20" width="20" height="20" rx="4""#));
+
+        let dir = crate::unique_temp_dir("snork_render");
+        save_frame(&dir, &game).unwrap();
+        for _ in 0..3 {
+            game.step(&[Direction::Right, Direction::Down]);
+            save_frame(&dir, &game).unwrap();
+        }
+
+        let mut frames: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        frames.sort();
+        let headers: Vec<bool> = frames
+            .iter()
+            .map(|f| fs::read_to_string(f).unwrap().starts_with("<svg "))
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[3].file_name().unwrap(), "turn_0003.svg");
+        assert!(headers.into_iter().all(|h| h));
+    }
+}
@@ -0,0 +1,231 @@
+//! Saved games for the post-mortem analysis of losses.
+//!
+//! A game is saved as `<game id>.jsonl` file, which contains the requests
+//! of the server (`/start`, every `/move` and `/end`) in the order they were
+//! received, one JSON encoded [GameRequest] per line.
+//! Duplicated turns, like the `/start` and first `/move`, are skipped on load.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::agents::Agent;
+use crate::env::{Direction, GameRequest};
+
+/// Returns the file of the game within `dir`.
+pub fn path(dir: &Path, game_id: &str) -> PathBuf {
+    // The ids are uuids, but better be sure
+    let name: String = game_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{name}.jsonl"))
+}
+
+/// Appends the request to the file of its game.
+pub fn save(dir: &Path, request: &GameRequest) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path(dir, &request.game.id))?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())
+}
+
+/// Appends the requests to the saved games in a background task,
+/// so that the server does not wait for the file system.
+///
+/// The requests are written in the order they are sent.
+pub struct SaveWriter {
+    sender: mpsc::UnboundedSender<GameRequest>,
+    task: JoinHandle<()>,
+}
+
+impl SaveWriter {
+    /// Spawns the writer of the saved games within `dir`.
+    pub fn spawn(dir: PathBuf) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<GameRequest>();
+        let task = tokio::task::spawn_blocking(move || {
+            while let Some(request) = receiver.blocking_recv() {
+                if let Err(e) = save(&dir, &request) {
+                    warn!("savegame {request}: {e}");
+                }
+            }
+        });
+        Self { sender, task }
+    }
+
+    /// Queues the request to be appended to the file of its game.
+    pub fn save(&self, request: &GameRequest) {
+        if self.sender.send(request.clone()).is_err() {
+            warn!("savegame {request}: writer stopped");
+        }
+    }
+
+    /// Waits until all queued requests are written.
+    pub async fn close(self) {
+        drop(self.sender);
+        if let Err(e) = self.task.await {
+            warn!("savegame writer failed: {e}");
+        }
+    }
+}
+
+/// Loads the requests of a saved game, one per turn.
+pub fn load(path: &Path) -> io::Result<Vec<GameRequest>> {
+    let mut requests: Vec<GameRequest> = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: GameRequest = serde_json::from_str(&line)?;
+        if requests.last().is_none_or(|last| last.turn < request.turn) {
+            requests.push(request);
+        }
+    }
+    Ok(requests)
+}
+
+/// Loads the request of the given turn from a saved game.
+pub fn load_turn(path: &Path, turn: usize) -> io::Result<GameRequest> {
+    load(path)?
+        .into_iter()
+        .find(|request| request.turn == turn)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No turn {turn}")))
+}
+
+/// Returns the move our snake made between the two requests,
+/// or `None` if it died.
+pub fn played_move(request: &GameRequest, next: &GameRequest) -> Option<Direction> {
+    let head = *request.you.body.first()?;
+    let snake = next.board.snakes.iter().find(|s| s.id == request.you.id)?;
+    Some(Direction::of_step(*snake.body.first()? - head))
+}
+
+/// Move of the agent compared to the actually played move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayTurn {
+    pub turn: usize,
+    pub chosen: Direction,
+    /// `None` for the last turn or if we died.
+    pub played: Option<Direction>,
+}
+
+/// Replays the saved game with the `agent`.
+pub async fn replay(
+    agent: &Agent,
+    requests: &[GameRequest],
+    latency: u64,
+    max_board_size: usize,
+) -> Vec<ReplayTurn> {
+    let Some(first) = requests.first() else {
+        return Vec::new();
+    };
+    let agent = agent.instance_for_game(&first.game.id);
+    let mut turns = Vec::with_capacity(requests.len());
+    for (i, request) in requests.iter().enumerate() {
+        let response = agent.step(request, latency, max_board_size).await;
+        let played = requests
+            .get(i + 1)
+            .and_then(|next| played_move(request, next));
+        turns.push(ReplayTurn {
+            turn: request.turn,
+            chosen: response.r#move,
+            played,
+        });
+    }
+    turns
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn savegame_replay() {
+        use super::*;
+        use crate::agents::MAX_BOARD_SIZE;
+        logging();
+
+        let request = |turn: usize, you: &str, enemy: &str| -> GameRequest {
+            serde_json::from_str(&format!(
+                r#"{{"game":{{"id":"replay-test","ruleset":{{"name":"standard"}},"timeout":200}},"turn":{turn},"board":{{"height":7,"width":7,"food":[],"hazards":[],"snakes":[{{"id":"a","name":"a","health":100,"body":[{you}]}},{{"id":"b","name":"b","health":100,"body":[{enemy}]}}]}},"you":{{"id":"a","name":"a","health":100,"body":[{you}]}}}}"#
+            ))
+            .unwrap()
+        };
+        let requests = [
+            // start and first move
+            request(
+                0,
+                r#"{"x":1,"y":1},{"x":1,"y":1},{"x":1,"y":1}"#,
+                r#"{"x":5,"y":5},{"x":5,"y":5},{"x":5,"y":5}"#,
+            ),
+            request(
+                0,
+                r#"{"x":1,"y":1},{"x":1,"y":1},{"x":1,"y":1}"#,
+                r#"{"x":5,"y":5},{"x":5,"y":5},{"x":5,"y":5}"#,
+            ),
+            request(
+                1,
+                r#"{"x":1,"y":2},{"x":1,"y":1},{"x":1,"y":1}"#,
+                r#"{"x":5,"y":4},{"x":5,"y":5},{"x":5,"y":5}"#,
+            ),
+            request(
+                2,
+                r#"{"x":2,"y":2},{"x":1,"y":2},{"x":1,"y":1}"#,
+                r#"{"x":4,"y":4},{"x":5,"y":4},{"x":5,"y":5}"#,
+            ),
+        ];
+
+        let dir = crate::unique_temp_dir("snork_savegame");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = path(&dir, "replay-test");
+        let writer = SaveWriter::spawn(dir.clone());
+        for request in &requests {
+            writer.save(request);
+        }
+        writer.close().await;
+        let loaded = load(&file);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.len(), 3);
+        for (loaded, saved) in loaded.iter().zip(&requests[1..]) {
+            assert_eq!(
+                serde_json::to_string(loaded).unwrap(),
+                serde_json::to_string(saved).unwrap()
+            );
+        }
+
+        let turns = replay(&Agent::default(), &loaded, 0, MAX_BOARD_SIZE).await;
+        info!("{turns:?}");
+        assert_eq!(turns.len(), 3);
+        let played: Vec<_> = turns.iter().map(|t| (t.turn, t.played)).collect();
+        assert_eq!(
+            played,
+            [
+                (0, Some(Direction::Up)),
+                (1, Some(Direction::Right)),
+                (2, None)
+            ]
+        );
+        for (turn, request) in turns.iter().zip(&loaded) {
+            let game = crate::game::Game::from_request(request);
+            assert!(game.move_is_valid(0, turn.chosen));
+        }
+    }
+}
@@ -1,7 +1,12 @@
+use std::cmp::Reverse;
 use std::sync::Arc;
 
-use super::{Heuristic, DRAW, LOSS, WIN};
+use super::{
+    imminent_collision, loss_at, win_at, Entry, Heuristic, SearchLimit, TranspositionTable, DRAW,
+    LOSS, WIN,
+};
 use crate::env::*;
+use crate::floodfill::FloodFill;
 use crate::game::{Game, Outcome};
 
 use async_recursion::async_recursion;
@@ -31,8 +36,8 @@ async fn async_alphabeta_rec(
         let mut game = game.clone();
         game.step(&actions);
         match game.outcome() {
-            Outcome::Winner(0) => return (Direction::Up, WIN),
-            Outcome::Winner(_) => return (Direction::Up, LOSS),
+            Outcome::Winner(0) => return (Direction::Up, win_at(depth)),
+            Outcome::Winner(_) => return (Direction::Up, loss_at(depth)),
             Outcome::Match => return (Direction::Up, DRAW),
             Outcome::None => {}
         }
@@ -105,16 +110,421 @@ async fn async_alphabeta_rec(
     }
 }
 
+/// Predicted moves of all snakes for every turn.
+pub type Variation = Vec<[Direction; 4]>;
+
+/// Order in which alphabeta searches the moves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MoveOrder {
+    /// Fixed order of `Direction::all`.
+    #[default]
+    None,
+    /// Our moves are ordered by the space they leave us.
+    Space,
+    /// Additionally, the moves of both snakes are ordered by killer moves and
+    /// the history heuristic.
+    Killer,
+}
+
 /// Alpha-Beta tree search.
 ///
 /// @see https://en.wikipedia.org/wiki/Alpha%E2%80%93beta_pruning
 /// - Assumes the maximizing agent has id 0
 /// - Assumes only two snakes are alive
-pub fn alphabeta(game: &Game, depth: usize, heuristic: &dyn Heuristic) -> (Direction, f64) {
-    alphabeta_rec(game, [Direction::Up; 4], depth, 0, LOSS, WIN, heuristic)
+///
+/// The search is aborted if any of the limits is hit, returning the best of
+/// the completely searched moves.
+/// A better move `order` results in more cutoffs.
+/// The optional transposition table caches the results and bounds of the subtrees.
+pub fn alphabeta(
+    game: &Game,
+    limit: &SearchLimit,
+    heuristic: &dyn Heuristic,
+    order: MoveOrder,
+    tt: Option<&mut TranspositionTable>,
+) -> (Direction, f64) {
+    let mut context = Context::new(game, heuristic, order, limit, tt);
+    let actions = [Direction::Up; 4];
+    let depth = limit.max_depth;
+    let result = alphabeta_rec(
+        &mut game.clone(),
+        actions,
+        depth,
+        0,
+        LOSS,
+        WIN,
+        &mut context,
+        None,
+    );
+    valid_result(game, result)
+}
+
+/// Alpha-Beta tree search with an aspiration window around the `expected`
+/// value, usually the result of the previous iteration.
+///
+/// If the value lies outside of the window, the search is repeated with the
+/// failing side of the window widened to the full range.
+pub fn aspiration(
+    game: &Game,
+    limit: &SearchLimit,
+    heuristic: &dyn Heuristic,
+    order: MoveOrder,
+    expected: f64,
+    margin: f64,
+    tt: Option<&mut TranspositionTable>,
+) -> (Direction, f64) {
+    let mut context = Context::new(game, heuristic, order, limit, tt);
+    let mut alpha = (expected - margin).max(LOSS);
+    let mut beta = (expected + margin).min(WIN);
+    loop {
+        let actions = [Direction::Up; 4];
+        let depth = limit.max_depth;
+        let result = alphabeta_rec(
+            &mut game.clone(),
+            actions,
+            depth,
+            0,
+            alpha,
+            beta,
+            &mut context,
+            None,
+        );
+
+        match result {
+            Some((_, value)) if !limit.aborted() && value <= alpha && alpha > LOSS => alpha = LOSS,
+            Some((_, value)) if !limit.aborted() && value >= beta && beta < WIN => beta = WIN,
+            _ => return valid_result(game, result),
+        }
+    }
+}
+
+/// Alpha-Beta tree search that additionally returns the principal variation,
+/// the predicted moves of all snakes down to the leaf.
+///
+/// The variation ends early on terminal states and transposition table hits.
+pub fn alphabeta_pv(
+    game: &Game,
+    depth: usize,
+    heuristic: &dyn Heuristic,
+    order: MoveOrder,
+    tt: Option<&mut TranspositionTable>,
+) -> (Direction, f64, Variation) {
+    let limit = SearchLimit::depth(depth);
+    let mut context = Context::new(game, heuristic, order, &limit, tt);
+    let actions = [Direction::Up; 4];
+    let mut pv = Variation::new();
+    let result = alphabeta_rec(
+        &mut game.clone(),
+        actions,
+        depth,
+        0,
+        LOSS,
+        WIN,
+        &mut context,
+        Some(&mut pv),
+    );
+    let (dir, value) = valid_result(game, result);
+    (dir, value, pv)
+}
+
+/// Returns a valid move if the search has not found anything better than a loss.
+fn valid_result(game: &Game, result: Option<(Direction, f64)>) -> (Direction, f64) {
+    match result {
+        Some((dir, value)) if game.move_is_valid(0, dir) => (dir, value),
+        _ => (game.fallback_move(0), LOSS),
+    }
+}
+
+/// Search parameters and move ordering statistics of the whole tree.
+struct Context<'a> {
+    heuristic: &'a dyn Heuristic,
+    order: MoveOrder,
+    limit: &'a SearchLimit,
+    tt: Option<&'a mut TranspositionTable>,
+    /// Last two moves that caused a cutoff for every level of the tree.
+    killers: Vec<[Option<Direction>; 2]>,
+    /// Cutoff scores for every snake, head position, and move.
+    history: Vec<usize>,
+    /// Number of turns the current branch is extended.
+    extension: usize,
+}
+
+impl<'a> Context<'a> {
+    fn new(
+        game: &Game,
+        heuristic: &'a dyn Heuristic,
+        order: MoveOrder,
+        limit: &'a SearchLimit,
+        tt: Option<&'a mut TranspositionTable>,
+    ) -> Self {
+        let (killers, history) = if order == MoveOrder::Killer {
+            let levels = (limit.max_depth + 1) * game.snakes.len();
+            let cells = game.grid.width * game.grid.height;
+            (
+                vec![[None; 2]; levels],
+                vec![0; game.snakes.len() * cells * 4],
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        Self {
+            heuristic,
+            order,
+            limit,
+            tt,
+            killers,
+            history,
+            extension: 0,
+        }
+    }
+
+    /// Level of the node in the tree.
+    fn level(&self, game: &Game, depth: usize, ply: usize) -> usize {
+        (self.limit.max_depth - depth) * game.snakes.len() + ply
+    }
+
+    fn history_index(game: &Game, ply: usize, d: Direction) -> Option<usize> {
+        let snake = &game.snakes[ply];
+        if !snake.alive() {
+            return None;
+        }
+        let head = snake.head();
+        let cell = head.x as usize + head.y as usize * game.grid.width;
+        let cells = game.grid.width * game.grid.height;
+        Some((ply * cells + cell) * 4 + d as usize)
+    }
+
+    /// Returns the moves of the snake in the order they should be searched.
+    fn moves(&self, game: &Game, depth: usize, ply: usize) -> [Direction; 4] {
+        let mut moves = if ply == 0 && self.order != MoveOrder::None {
+            order_moves(game)
+        } else {
+            Direction::all()
+        };
+        if self.order == MoveOrder::Killer {
+            let killers = self.killers[self.level(game, depth, ply)];
+            moves.sort_by_key(|&d| {
+                let killer = killers.iter().rev().position(|&k| k == Some(d));
+                let history = Self::history_index(game, ply, d).map_or(0, |i| self.history[i]);
+                Reverse((killer, history))
+            });
+        }
+        moves
+    }
+
+    /// Returns if the enemy move can be skipped, because it kills the enemy
+    /// and the enemy has better alternatives.
+    fn pruned(&self, game: &Game, ply: usize, d: Direction) -> bool {
+        self.limit.prune_suicides
+            && !game.move_is_valid(ply as u8, d)
+            && game.valid_moves(ply as u8).next().is_some()
+    }
+
+    /// Remembers a move that caused a cutoff.
+    fn cutoff(&mut self, game: &Game, depth: usize, ply: usize, d: Direction) {
+        if self.order != MoveOrder::Killer {
+            return;
+        }
+        let level = self.level(game, depth, ply);
+        let killers = &mut self.killers[level];
+        if killers[0] != Some(d) {
+            killers[1] = killers[0];
+            killers[0] = Some(d);
+        }
+        if let Some(i) = Self::history_index(game, ply, d) {
+            self.history[i] += (depth + 1) * (depth + 1);
+        }
+    }
 }
 
+/// Orders our moves by the space that is reachable after them.
+fn order_moves(game: &Game) -> [Direction; 4] {
+    let mut flood_fill = FloodFill::for_game(game);
+    let mut space = [0; 4];
+    for d in Direction::all() {
+        if game.move_is_valid(0, d) {
+            let mut snakes = game.snakes.clone();
+            let you = &mut snakes[0];
+            you.body.pop_front();
+            you.body.push_back(game.apply(you.head(), d));
+            flood_fill.flood_snakes(&game.grid, &snakes);
+            // Valid moves are always preferred
+            space[d as usize] = flood_fill.count_space(0) + 1;
+        }
+    }
+    let mut moves = Direction::all();
+    moves.sort_by_key(|&d| Reverse(space[d as usize]));
+    moves
+}
+
+/// If `pv` is given, it is filled with the principal variation of the subtree.
+/// Returns `None` if the search has been aborted.
+#[allow(clippy::too_many_arguments)]
 fn alphabeta_rec(
+    game: &mut Game,
+    actions: [Direction; 4],
+    depth: usize,
+    ply: usize,
+    mut alpha: f64,
+    mut beta: f64,
+    context: &mut Context,
+    mut pv: Option<&mut Variation>,
+) -> Option<(Direction, f64)> {
+    if ply == game.snakes.len() {
+        if context.limit.exceeded() {
+            return None;
+        }
+
+        let undo = game.make(&actions);
+        if let Some(pv) = pv.as_deref_mut() {
+            pv.push(actions);
+        }
+        let result = match game.outcome() {
+            Outcome::Winner(0) => Some((Direction::Up, win_at(depth))),
+            Outcome::Winner(_) => Some((Direction::Up, loss_at(depth))),
+            Outcome::Match => Some((Direction::Up, DRAW)),
+            Outcome::None if depth == 0 => {
+                if context.extension < context.limit.max_extension && imminent_collision(game) {
+                    // Quiescence extension
+                    context.extension += 1;
+                    let actions = [Direction::Up; 4];
+                    let result = alphabeta_rec(game, actions, 0, 0, alpha, beta, context, pv);
+                    context.extension -= 1;
+                    result
+                } else {
+                    Some((Direction::Up, context.heuristic.eval(game)))
+                }
+            }
+            Outcome::None => alphabeta_rec(
+                game,
+                [Direction::Up; 4],
+                depth - 1,
+                0,
+                alpha,
+                beta,
+                context,
+                pv,
+            ),
+        };
+        game.unmake(undo);
+        result
+    } else if ply == 0 {
+        // Extended nodes are not cached as their depth is not comparable
+        let hash = match context.tt {
+            Some(_) if context.extension == 0 => Some(TranspositionTable::hash(game)),
+            _ => None,
+        };
+        if let (Some(tt), Some(hash)) = (context.tt.as_deref_mut(), hash) {
+            if let Some(entry) = tt.probe(hash, depth, alpha, beta) {
+                return Some((entry.dir, entry.value));
+            }
+        }
+
+        let alpha_start = alpha;
+        let mut value = (Direction::Up, LOSS);
+        let mut best = Variation::new();
+        for d in context.moves(game, depth, ply) {
+            let mut actions = actions;
+            actions[ply] = d;
+            let mut line = Variation::new();
+            let result = alphabeta_rec(
+                game,
+                actions,
+                depth,
+                ply + 1,
+                alpha,
+                beta,
+                context,
+                pv.is_some().then_some(&mut line),
+            );
+            let Some((_, outcome)) = result else {
+                if depth == context.limit.max_depth {
+                    // keep the completely searched moves at the root
+                    break;
+                }
+                return None;
+            };
+            if outcome > value.1 {
+                value = (d, outcome);
+                best = line;
+            }
+            alpha = alpha.max(outcome);
+            if alpha >= beta {
+                context.cutoff(game, depth, ply, d);
+                break;
+            }
+        }
+
+        // Results of aborted searches are incomplete
+        if let (Some(tt), Some(hash)) = (context.tt.as_deref_mut(), hash) {
+            if !context.limit.aborted() {
+                tt.insert(hash, depth, Entry::new(value.1, alpha_start, beta, value.0));
+            }
+        }
+        if let Some(pv) = pv {
+            pv.append(&mut best);
+        }
+        Some(value)
+    } else {
+        let mut value = (Direction::Up, WIN);
+        let mut best = Variation::new();
+        for d in context.moves(game, depth, ply) {
+            if context.pruned(game, ply, d) {
+                continue;
+            }
+            let mut actions = actions;
+            actions[ply] = d;
+            let mut line = Variation::new();
+            let (_, outcome) = alphabeta_rec(
+                game,
+                actions,
+                depth,
+                ply + 1,
+                alpha,
+                beta,
+                context,
+                pv.is_some().then_some(&mut line),
+            )?;
+            if outcome < value.1 {
+                value = (d, outcome);
+                best = line;
+            }
+            beta = beta.min(outcome);
+            if alpha >= beta {
+                context.cutoff(game, depth, ply, d);
+                break;
+            }
+        }
+        if let Some(pv) = pv {
+            pv.append(&mut best);
+        }
+        Some(value)
+    }
+}
+
+/// Width of the null window, small compared to the `WIN`/`LOSS` scale.
+const PVS_EPSILON: f64 = WIN * 1e-9;
+
+/// Principal-Variation search.
+///
+/// @see https://en.wikipedia.org/wiki/Principal_variation_search
+/// Variant of alphabeta that searches our first move with the full window and
+/// the remaining moves with a null window, re-searching them only if they
+/// turn out to be better.
+/// The simultaneous moves of the opponent are searched with the full window.
+/// - Assumes the maximizing agent has id 0
+/// - Assumes only two snakes are alive
+pub fn pvs(
+    game: &Game,
+    depth: usize,
+    heuristic: &dyn Heuristic,
+    tt: Option<&mut TranspositionTable>,
+) -> (Direction, f64) {
+    pvs_rec(game, [Direction::Up; 4], depth, 0, LOSS, WIN, heuristic, tt)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pvs_rec(
     game: &Game,
     actions: [Direction; 4],
     depth: usize,
@@ -122,13 +532,14 @@ fn alphabeta_rec(
     mut alpha: f64,
     mut beta: f64,
     heuristic: &dyn Heuristic,
+    mut tt: Option<&mut TranspositionTable>,
 ) -> (Direction, f64) {
     if ply == game.snakes.len() {
         let mut game = game.clone();
         game.step(&actions);
         match game.outcome() {
-            Outcome::Winner(0) => return (Direction::Up, WIN),
-            Outcome::Winner(_) => return (Direction::Up, LOSS),
+            Outcome::Winner(0) => return (Direction::Up, win_at(depth)),
+            Outcome::Winner(_) => return (Direction::Up, loss_at(depth)),
             Outcome::Match => return (Direction::Up, DRAW),
             Outcome::None => {}
         }
@@ -136,7 +547,7 @@ fn alphabeta_rec(
         if depth == 0 {
             (Direction::Up, heuristic.eval(&game))
         } else {
-            alphabeta_rec(
+            pvs_rec(
                 &game,
                 [Direction::Up; 4],
                 depth - 1,
@@ -144,14 +555,51 @@ fn alphabeta_rec(
                 alpha,
                 beta,
                 heuristic,
+                tt,
             )
         }
     } else if ply == 0 {
+        let hash = tt.as_ref().map(|_| TranspositionTable::hash(game));
+        if let (Some(tt), Some(hash)) = (tt.as_deref_mut(), hash) {
+            if let Some(entry) = tt.probe(hash, depth, alpha, beta) {
+                return (entry.dir, entry.value);
+            }
+        }
+
+        let alpha_start = alpha;
         let mut value = (Direction::Up, LOSS);
-        for d in Direction::all() {
+        for (i, d) in Direction::iter().enumerate() {
             let mut actions = actions;
             actions[ply] = d;
-            let (_, outcome) = alphabeta_rec(game, actions, depth, ply + 1, alpha, beta, heuristic);
+            let window = if i == 0 {
+                (alpha, beta)
+            } else {
+                (alpha, alpha + PVS_EPSILON)
+            };
+            let (_, mut outcome) = pvs_rec(
+                game,
+                actions,
+                depth,
+                ply + 1,
+                window.0,
+                window.1,
+                heuristic,
+                tt.as_deref_mut(),
+            );
+            if i > 0 && outcome > alpha && outcome < beta {
+                // fail high: re-search, the outcome is a lower bound
+                let (_, exact) = pvs_rec(
+                    game,
+                    actions,
+                    depth,
+                    ply + 1,
+                    outcome,
+                    beta,
+                    heuristic,
+                    tt.as_deref_mut(),
+                );
+                outcome = outcome.max(exact);
+            }
             if outcome > value.1 {
                 value = (d, outcome);
             }
@@ -160,13 +608,27 @@ fn alphabeta_rec(
                 break;
             }
         }
+
+        if let (Some(tt), Some(hash)) = (tt, hash) {
+            tt.insert(hash, depth, Entry::new(value.1, alpha_start, beta, value.0));
+        }
         value
     } else {
+        // The opponent moves simultaneously, search its moves with the full window
         let mut value = (Direction::Up, WIN);
         for d in Direction::all() {
             let mut actions = actions;
             actions[ply] = d;
-            let (_, outcome) = alphabeta_rec(game, actions, depth, ply + 1, alpha, beta, heuristic);
+            let (_, outcome) = pvs_rec(
+                game,
+                actions,
+                depth,
+                ply + 1,
+                alpha,
+                beta,
+                heuristic,
+                tt.as_deref_mut(),
+            );
             if outcome < value.1 {
                 value = (d, outcome);
             }
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use tokio::task::JoinSet;
+
+use super::minimax::{first_actions, Actions};
+use super::{loss_at, win_at, Heuristic, DRAW, LOSS, MAX_SNAKES};
+use crate::env::Direction;
+use crate::game::{Game, Outcome};
+
+/// Expectimax search, where the enemies are modeled as chance nodes.
+/// The player with id 0 is the maximizing player, the enemies pick one of
+/// their legal moves uniformly at random.
+/// Illegal moves are not part of the average, as they are never chosen.
+///
+/// The return value contains the expected value for each of the four moves
+/// of the maximizing player, invalid moves are `LOSS`.
+///
+/// The subtrees of the root are searched in parallel.
+pub async fn expectimax(game: &Game, depth: usize, heuristic: Arc<dyn Heuristic>) -> [f64; 4] {
+    assert!(game.snakes.len() <= MAX_SNAKES);
+
+    let mut set = JoinSet::new();
+    for d in Direction::all() {
+        if !game.move_is_valid(0, d) {
+            continue;
+        }
+
+        let game = game.clone();
+        let heuristic = heuristic.clone();
+
+        // Create tasks for subtrees.
+        set.spawn(async move {
+            let value = expectimax_rec(&game, depth, 1, first_actions(d), &*heuristic);
+            (d, value)
+        });
+    }
+
+    let mut result = [LOSS; 4];
+    while let Some(r) = set.join_next().await {
+        if let Ok((d, value)) = r {
+            result[d as usize] = value;
+        }
+    }
+    result
+}
+
+/// Synchronous version of `expectimax`.
+pub fn expectimax_sync(game: &Game, depth: usize, heuristic: &dyn Heuristic) -> [f64; 4] {
+    assert!(game.snakes.len() <= MAX_SNAKES);
+
+    let mut result = [LOSS; 4];
+    for d in Direction::all() {
+        if game.move_is_valid(0, d) {
+            result[d as usize] = expectimax_rec(game, depth, 1, first_actions(d), heuristic);
+        }
+    }
+    result
+}
+
+fn expectimax_rec(
+    game: &Game,
+    depth: usize,
+    ply: usize,
+    actions: Actions,
+    heuristic: &dyn Heuristic,
+) -> f64 {
+    if ply == game.snakes.len() {
+        // simulate
+        let mut game = game.clone();
+        game.step(&actions[..]);
+
+        match game.outcome() {
+            Outcome::Winner(0) => return win_at(depth),
+            Outcome::Winner(_) => return loss_at(depth),
+            Outcome::Match => return DRAW,
+            Outcome::None => {}
+        }
+
+        if depth <= 1 {
+            heuristic.eval(&game)
+        } else {
+            expectimax_rec(&game, depth - 1, 0, [Direction::Up; MAX_SNAKES], heuristic)
+        }
+    } else if ply == 0 {
+        // max
+        let mut max = LOSS;
+        for d in Direction::all() {
+            if game.move_is_valid(0, d) {
+                let mut actions = actions;
+                actions[ply] = d;
+                max = max.max(expectimax_rec(game, depth, ply + 1, actions, heuristic));
+            }
+        }
+        max
+    } else {
+        // chance: average over the legal moves
+        let mut sum = 0.0;
+        let mut moves = 0;
+        for d in Direction::all() {
+            if game.move_is_valid(ply as u8, d) {
+                let mut actions = actions;
+                actions[ply] = d;
+                sum += expectimax_rec(game, depth, ply + 1, actions, heuristic);
+                moves += 1;
+            }
+        }
+        if moves > 0 {
+            sum / moves as f64
+        } else {
+            // continue with next agent
+            expectimax_rec(game, depth, ply + 1, actions, heuristic)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use log::info;
+
+    use super::*;
+    use crate::floodfill::FloodFill;
+    use crate::logging;
+    use crate::util::argmax;
+
+    #[derive(Debug, Clone, Default)]
+    struct TestH;
+    impl Heuristic for TestH {
+        fn eval(&self, game: &Game) -> f64 {
+            let mut flood_fill = FloodFill::new(game.grid.width, game.grid.height);
+            if game.snake_is_alive(0) {
+                flood_fill.flood_snakes(&game.grid, &game.snakes);
+                flood_fill.count_space(0) as f64
+            } else {
+                0.0
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expectimax_sync_async() {
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let sync = expectimax_sync(&game, 2, &TestH);
+        let parallel = expectimax(&game, 2, Arc::new(TestH)).await;
+        info!("sync {sync:?} async {parallel:?}");
+
+        assert_eq!(sync, parallel);
+        assert_eq!(
+            argmax(sync.iter().copied()),
+            argmax(parallel.iter().copied())
+        );
+    }
+
+    #[test]
+    fn expectimax_cornered_enemy() {
+        logging();
+
+        let game = Game::parse(
+            r#"
+            1 < < . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let result = expectimax_sync(&game, 1, &TestH);
+        info!("{result:?}");
+
+        for d in Direction::all() {
+            if !game.move_is_valid(0, d) {
+                continue;
+            }
+            // average over the legal replies of the cornered enemy
+            let mut sum = 0.0;
+            let mut legal = 0;
+            for e in Direction::all() {
+                if game.move_is_valid(1, e) {
+                    let mut game = game.clone();
+                    game.step(&[d, e]);
+                    sum += TestH.eval(&game);
+                    legal += 1;
+                }
+            }
+            assert_eq!(legal, 1);
+            let expected = sum / legal as f64;
+            // averaging over all four replies would count each illegal one as a loss
+            let biased = (sum + (4 - legal) as f64 * LOSS) / 4.0;
+            info!("{d:?}: {expected} (biased {biased})");
+            assert_eq!(result[d as usize], expected);
+            assert!(result[d as usize] > biased);
+        }
+    }
+
+    #[test]
+    fn expectimax_terminal() {
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        // The enemy starves in the first turn
+        let mut starving = game.clone();
+        starving.snakes[1].health = 1;
+        let result = expectimax_sync(&starving, 2, &TestH);
+        info!("win {result:?}");
+        for d in Direction::all() {
+            if game.move_is_valid(0, d) {
+                assert_eq!(result[d as usize], win_at(2));
+            }
+        }
+
+        // We starve in the first turn
+        let mut starving = game.clone();
+        starving.snakes[0].health = 1;
+        let result = expectimax_sync(&starving, 2, &TestH);
+        info!("loss {result:?}");
+        for d in Direction::all() {
+            if game.move_is_valid(0, d) {
+                assert_eq!(result[d as usize], loss_at(2));
+            }
+        }
+    }
+}
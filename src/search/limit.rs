@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Number of nodes between two checks of the deadline.
+const CHECK_INTERVAL: usize = 64;
+
+/// Limits of a tree search, which is aborted as soon as one of them is hit.
+///
+/// The node counter is shared between all tasks of the search.
+#[derive(Debug)]
+pub struct SearchLimit {
+    pub max_depth: usize,
+    pub max_nodes: usize,
+    pub deadline: Instant,
+    /// Number of turns the search is extended beyond `max_depth` if
+    /// snakes are about to collide head to head.
+    pub max_extension: usize,
+    /// Skip enemy moves into walls or bodies, which a rational opponent
+    /// would not make, unless there are no other moves.
+    pub prune_suicides: bool,
+    /// Aborts the search if set, e.g. when its task has been cancelled.
+    pub cancel: Option<Arc<AtomicBool>>,
+    nodes: AtomicUsize,
+    aborted: AtomicBool,
+}
+
+impl SearchLimit {
+    pub fn new(max_depth: usize, max_nodes: usize, deadline: Instant) -> Self {
+        Self {
+            max_depth,
+            max_nodes,
+            deadline,
+            max_extension: 0,
+            prune_suicides: false,
+            cancel: None,
+            nodes: AtomicUsize::new(0),
+            aborted: AtomicBool::new(false),
+        }
+    }
+
+    /// Only limits the depth of the search.
+    pub fn depth(max_depth: usize) -> Self {
+        // Far enough in the future for any search
+        let deadline = Instant::now() + Duration::from_secs(60 * 60 * 24);
+        Self::new(max_depth, usize::MAX, deadline)
+    }
+
+    /// Counts a new node and returns if the search has to be aborted.
+    pub fn exceeded(&self) -> bool {
+        if self.aborted.load(Ordering::Relaxed) {
+            return true;
+        }
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        if nodes > self.max_nodes
+            || (nodes.is_multiple_of(CHECK_INTERVAL)
+                && (Instant::now() >= self.deadline || self.cancelled()))
+        {
+            self.aborted.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Returns if any of the limits has been hit.
+    pub fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// Number of searched nodes.
+    pub fn nodes(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed)
+    }
+}
@@ -6,7 +6,22 @@ use crate::{env::Direction, game::Outcome};
 use async_recursion::async_recursion;
 use tokio::task::JoinSet;
 
-use super::{Heuristic, DRAW, LOSS, WIN};
+use super::{
+    imminent_collision, is_loss, is_win, loss_at, win_at, Bound, Entry, Heuristic, SearchLimit,
+    TranspositionTable, DRAW, LOSS, MAX_SNAKES, WIN,
+};
+
+/// Moves of all snakes of the current turn.
+pub(super) type Actions = [Direction; MAX_SNAKES];
+/// Value of the game for every snake.
+type Values = [f64; MAX_SNAKES];
+
+/// Actions where the maximizing player moves into the given direction.
+pub(super) fn first_actions(d: Direction) -> Actions {
+    let mut actions = [Direction::Up; MAX_SNAKES];
+    actions[0] = d;
+    actions
+}
 
 /// This algorithm is more or less a hacky variation of minmax with multiple agents.
 /// The player with id 0 is the maximizing player, the others are minimizing.
@@ -15,8 +30,17 @@ use super::{Heuristic, DRAW, LOSS, WIN};
 ///
 /// If the maximizing player dies traversal ends and min is returned.
 /// Dead enemies are skipped.
-pub async fn async_max_n(game: &Game, depth: usize, heuristic: Arc<dyn Heuristic>) -> [f64; 4] {
-    assert!(game.snakes.len() <= 4);
+/// Up to `MAX_SNAKES` snakes are supported.
+///
+/// The search is aborted if any of the limits is hit.
+/// In this case only the moves, whose subtrees have been searched completely,
+/// have a value, the others are `LOSS`.
+pub async fn async_max_n(
+    game: &Game,
+    limit: Arc<SearchLimit>,
+    heuristic: Arc<dyn Heuristic>,
+) -> [f64; 4] {
+    assert!(game.snakes.len() <= MAX_SNAKES);
 
     let mut set = JoinSet::new();
     for d in Direction::all() {
@@ -24,51 +48,74 @@ pub async fn async_max_n(game: &Game, depth: usize, heuristic: Arc<dyn Heuristic
             continue;
         }
 
-        let actions = [d, Direction::Up, Direction::Up, Direction::Up];
+        let actions = first_actions(d);
         let game = game.clone();
+        let limit = limit.clone();
         let heuristic = heuristic.clone();
 
         // Create tasks for subtrees.
         set.spawn(async move {
-            let r = async_max_n_rec(&game, depth, 1, actions, heuristic).await;
+            let (depth, extension) = (limit.max_depth, limit.max_extension);
+            let r = async_max_n_rec(&game, depth, extension, 1, actions, limit, heuristic).await;
             (d, r)
         });
     }
 
     let mut result = [LOSS; 4];
     while let Some(r) = set.join_next().await {
-        if let Ok((d, r)) = r {
+        if let Ok((d, Some(r))) = r {
             result[d as usize] = r;
         }
     }
     result
 }
 
+/// Returns `None` if the search has been aborted.
+/// `extension` is the number of turns the search can still be extended.
 #[async_recursion]
 async fn async_max_n_rec(
     game: &Game,
     depth: usize,
+    extension: usize,
     ply: usize,
-    actions: [Direction; 4],
+    actions: Actions,
+    limit: Arc<SearchLimit>,
     heuristic: Arc<dyn Heuristic>,
-) -> f64 {
+) -> Option<f64> {
     if ply == game.snakes.len() {
+        if limit.exceeded() {
+            return None;
+        }
+
         // simulate
         let mut game = game.clone();
         game.step(&actions[..]);
 
         match game.outcome() {
-            Outcome::Winner(0) => return WIN + heuristic.eval(&game),
-            Outcome::Winner(_) => return LOSS,
-            Outcome::Match => return DRAW,
+            Outcome::Winner(0) => return Some(win_at(depth)),
+            Outcome::Winner(_) => return Some(loss_at(depth)),
+            Outcome::Match => return Some(DRAW),
             Outcome::None => {}
         }
 
-        if depth <= 1 {
+        if depth <= 1 && extension > 0 && imminent_collision(&game) {
+            // Quiescence extension
+            let actions = [Direction::Up; MAX_SNAKES];
+            async_max_n_rec(&game, 1, extension - 1, 0, actions, limit, heuristic).await
+        } else if depth <= 1 {
             // eval
-            heuristic.eval(&game)
+            Some(heuristic.eval(&game))
         } else {
-            async_max_n_rec(&game, depth - 1, 0, [Direction::Up; 4], heuristic).await
+            async_max_n_rec(
+                &game,
+                depth - 1,
+                extension,
+                0,
+                [Direction::Up; MAX_SNAKES],
+                limit,
+                heuristic,
+            )
+            .await
         }
     } else if ply == 0 {
         // max
@@ -78,23 +125,24 @@ async fn async_max_n_rec(
                 continue;
             }
 
-            let actions = [d, Direction::Up, Direction::Up, Direction::Up];
+            let actions = first_actions(d);
             let game = game.clone();
+            let limit = limit.clone();
             let heuristic = heuristic.clone();
 
             // Create tasks for subtrees.
-            set.spawn(
-                async move { async_max_n_rec(&game, depth, ply + 1, actions, heuristic).await },
-            );
+            set.spawn(async move {
+                async_max_n_rec(&game, depth, extension, ply + 1, actions, limit, heuristic).await
+            });
         }
 
         let mut max = LOSS;
         while let Some(r) = set.join_next().await {
             if let Ok(r) = r {
-                max = max.max(r);
+                max = max.max(r?);
             }
         }
-        max
+        Some(max)
     } else {
         // min
         let mut min = 2.0 * WIN;
@@ -106,83 +154,198 @@ async fn async_max_n_rec(
 
             let mut actions = actions;
             actions[ply] = d;
-            let val = async_max_n_rec(game, depth, ply + 1, actions, heuristic.clone()).await;
+            let val = async_max_n_rec(
+                game,
+                depth,
+                extension,
+                ply + 1,
+                actions,
+                limit.clone(),
+                heuristic.clone(),
+            )
+            .await?;
             if val < min {
                 min = val;
                 moved = true;
 
                 // skip if already lowest possible outcome
-                if val <= LOSS {
+                if is_loss(val) {
                     break;
                 }
             }
         }
         if !moved {
             // continue with next agent
-            min = async_max_n_rec(game, depth, ply + 1, actions, heuristic).await;
+            min =
+                async_max_n_rec(game, depth, extension, ply + 1, actions, limit, heuristic).await?;
         }
-        min
+        Some(min)
     }
 }
 
+/// Opponent model of the `max_n` search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Every enemy picks the reply that is best for itself.
+    /// The heuristic is evaluated from the perspective of each snake.
+    MaxN,
+    /// All enemies jointly minimize the heuristic of the maximizing player.
+    #[default]
+    Paranoid,
+}
+
 /// This algorithm is more or less a hacky variation of minmax with multiple agents.
-/// The player with id 0 is the maximizing player, the others are minimizing.
+/// The player with id 0 is the maximizing player, the others are either
+/// minimizing or maximizing their own heuristic, depending on the `mode`.
 ///
 /// The return value contains the heuristic for each of the four moves of the maximizing player.
 ///
 /// If the maximizing player dies traversal ends and min is returned.
 /// Dead enemies are skipped.
-pub fn max_n(game: &Game, depth: usize, heuristic: &dyn Heuristic) -> [f64; 4] {
+/// Up to `MAX_SNAKES` snakes are supported.
+///
+/// The optional transposition table caches the results of the subtrees.
+/// It is only used in the paranoid mode.
+pub fn max_n(
+    game: &Game,
+    depth: usize,
+    heuristic: &dyn Heuristic,
+    mode: SearchMode,
+    mut tt: Option<&mut TranspositionTable>,
+) -> [f64; 4] {
+    assert!(game.snakes.len() <= MAX_SNAKES);
+
+    if mode != SearchMode::Paranoid {
+        tt = None;
+    }
+
+    // Searched in place
+    let mut game = game.clone();
+    let game = &mut game;
+
     let mut result = [LOSS; 4];
     for d in Direction::all() {
         if game.move_is_valid(0, d) {
-            let actions = [d, Direction::Up, Direction::Up, Direction::Up];
-            result[d as usize] = max_n_rec(game, depth, 1, actions, heuristic);
+            let actions = first_actions(d);
+            let values = max_n_rec(game, depth, 1, actions, heuristic, mode, tt.as_deref_mut());
+            result[d as usize] = values[0];
         }
     }
     result
 }
 
+/// Evaluates the game for every snake, or only for the maximizing player in
+/// the paranoid mode.
+fn max_n_eval(game: &mut Game, heuristic: &dyn Heuristic, mode: SearchMode) -> Values {
+    let mut values = [LOSS; MAX_SNAKES];
+    values[0] = heuristic.eval(game);
+    if mode == SearchMode::MaxN {
+        for (i, value) in values
+            .iter_mut()
+            .enumerate()
+            .take(game.snakes.len())
+            .skip(1)
+        {
+            if game.snake_is_alive(i as u8) {
+                // evaluate from the perspective of the enemy
+                game.snakes.swap(0, i);
+                *value = heuristic.eval(game);
+                game.snakes.swap(0, i);
+            }
+        }
+    }
+    values
+}
+
+/// Returns the values for each snake at the end of the game,
+/// with `depth` turns left to search.
+fn max_n_outcome(game: &Game, depth: usize) -> Option<Values> {
+    match game.outcome() {
+        Outcome::Winner(i) => {
+            let mut values = [loss_at(depth); MAX_SNAKES];
+            values[i as usize] = win_at(depth);
+            Some(values)
+        }
+        Outcome::Match => Some([DRAW; MAX_SNAKES]),
+        Outcome::None => None,
+    }
+}
+
 fn max_n_rec(
-    game: &Game,
+    game: &mut Game,
     depth: usize,
     ply: usize,
-    actions: [Direction; 4],
+    actions: Actions,
     heuristic: &dyn Heuristic,
-) -> f64 {
+    mode: SearchMode,
+    mut tt: Option<&mut TranspositionTable>,
+) -> Values {
     if ply == game.snakes.len() {
         // simulate
-        let mut game = game.clone();
-        game.step(&actions[..]);
+        let undo = game.make(&actions[..]);
 
-        match game.outcome() {
-            Outcome::Winner(0) => return WIN + heuristic.eval(&game),
-            Outcome::Winner(_) => return LOSS,
-            Outcome::Match => return DRAW,
-            Outcome::None => {}
-        }
-
-        if depth <= 1 {
-            // eval
-            heuristic.eval(&game)
+        let values = if let Some(values) = max_n_outcome(game, depth) {
+            values
+        } else if depth <= 1 {
+            max_n_eval(game, heuristic, mode)
         } else {
-            max_n_rec(&game, depth - 1, 0, [Direction::Up; 4], heuristic)
-        }
+            max_n_rec(
+                game,
+                depth - 1,
+                0,
+                [Direction::Up; MAX_SNAKES],
+                heuristic,
+                mode,
+                tt,
+            )
+        };
+        game.unmake(undo);
+        values
     } else if ply == 0 {
+        let hash = tt.as_ref().map(|_| TranspositionTable::hash(game));
+        if let (Some(tt), Some(hash)) = (tt.as_deref_mut(), hash) {
+            if let Some(entry) = tt.get(hash, depth) {
+                let mut values = [LOSS; MAX_SNAKES];
+                values[0] = entry.value;
+                return values;
+            }
+        }
+
         // collect all outcomes instead of max
-        let mut max = LOSS;
+        let mut max = [LOSS; MAX_SNAKES];
+        let mut best = Direction::Up;
         for d in Direction::all() {
             if !game.move_is_valid(0, d) {
                 continue;
             }
             let mut actions = actions;
             actions[ply] = d;
-            max = max.max(max_n_rec(game, depth, ply + 1, actions, heuristic));
+            let values = max_n_rec(
+                game,
+                depth,
+                ply + 1,
+                actions,
+                heuristic,
+                mode,
+                tt.as_deref_mut(),
+            );
+            if values[0] > max[0] {
+                max = values;
+                best = d;
+            }
+        }
+
+        if let (Some(tt), Some(hash)) = (tt, hash) {
+            let entry = Entry {
+                value: max[0],
+                bound: Bound::Exact,
+                dir: best,
+            };
+            tt.insert(hash, depth, entry);
         }
         max
     } else {
-        let mut min = 2.0 * WIN;
-        let mut moved = false;
+        let mut best: Option<Values> = None;
         for d in Direction::all() {
             if !game.move_is_valid(ply as u8, d) {
                 continue;
@@ -190,22 +353,38 @@ fn max_n_rec(
 
             let mut actions = actions;
             actions[ply] = d;
-            let val = max_n_rec(game, depth, ply + 1, actions, heuristic);
-            if val < min {
-                min = val;
-                moved = true;
-
-                // skip if already lowest possible outcome
-                if val <= LOSS {
-                    break;
+            let values = max_n_rec(
+                game,
+                depth,
+                ply + 1,
+                actions,
+                heuristic,
+                mode,
+                tt.as_deref_mut(),
+            );
+            match mode {
+                SearchMode::Paranoid => {
+                    if best.is_none_or(|best| values[0] < best[0]) {
+                        best = Some(values);
+                        // skip if already lowest possible outcome
+                        if is_loss(values[0]) {
+                            break;
+                        }
+                    }
+                }
+                SearchMode::MaxN => {
+                    if best.is_none_or(|best| values[ply] > best[ply]) {
+                        best = Some(values);
+                        // skip if already highest possible outcome
+                        if is_win(values[ply]) {
+                            break;
+                        }
+                    }
                 }
             }
         }
-        if !moved {
-            // continue with next agent
-            min = max_n_rec(game, depth, ply + 1, actions, heuristic);
-        }
-        min
+        // continue with next agent if there are no valid moves
+        best.unwrap_or_else(|| max_n_rec(game, depth, ply + 1, actions, heuristic, mode, tt))
     }
 }
 
@@ -216,7 +395,7 @@ mod test {
     use crate::floodfill::FloodFill;
     use crate::game::Game;
     use crate::logging;
-    use crate::search::{alphabeta, Heuristic};
+    use crate::search::{alphabeta, Heuristic, MoveOrder};
 
     #[derive(Debug, Clone, Default)]
     struct TestH;
@@ -270,7 +449,7 @@ mod test {
         info!("{:?}", game.grid);
         let start = Instant::now();
 
-        let moves = max_n(&game, 3, &TestH);
+        let moves = max_n(&game, 3, &TestH, SearchMode::Paranoid, None);
         let end = Instant::now();
         info!("{:?}", moves);
         info!("time {}ms", (end - start).as_millis());
@@ -313,7 +492,7 @@ mod test {
         let game = Game::new(0, 11, 11, snakes, &[], &[]);
         info!("{:?}", game.grid);
         let start = Instant::now();
-        let moves = async_max_n(&game, 3, Arc::new(TestH)).await;
+        let moves = async_max_n(&game, Arc::new(SearchLimit::depth(3)), Arc::new(TestH)).await;
         let end = Instant::now();
         info!("{:?}", moves);
         info!("async time {}ms", (end - start).as_millis());
@@ -349,15 +528,487 @@ mod test {
         info!("{:?}", game.grid);
 
         let start = Instant::now();
-        let moves = max_n(&game, 6, &TestH);
+        let moves = max_n(&game, 6, &TestH, SearchMode::Paranoid, None);
         let end = Instant::now();
         info!("max_n {:?}", moves);
         info!("max_n time {}ms", (end - start).as_millis());
 
         let start = Instant::now();
-        let moves = alphabeta(&game, 6, &TestH);
+        let moves = alphabeta(&game, &SearchLimit::depth(6), &TestH, MoveOrder::None, None);
         let end = Instant::now();
         info!("alpha_beta {:?}", moves);
         info!("alpha_beta time {}ms", (end - start).as_millis());
     }
+
+    #[test]
+    fn transposition() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let expected = max_n(&game, 3, &TestH, SearchMode::Paranoid, None);
+        let mut tt = TranspositionTable::new();
+        let cached = max_n(&game, 3, &TestH, SearchMode::Paranoid, Some(&mut tt));
+        info!("max_n {expected:?} {cached:?} {}", tt.len());
+        assert_eq!(expected, cached);
+        // Searching again reuses the cached subtrees
+        let cached = max_n(&game, 3, &TestH, SearchMode::Paranoid, Some(&mut tt));
+        assert_eq!(expected, cached);
+        assert!(tt.hits > 0);
+
+        let expected = alphabeta(&game, &SearchLimit::depth(3), &TestH, MoveOrder::None, None);
+        let mut tt = TranspositionTable::new();
+        let cached = alphabeta(
+            &game,
+            &SearchLimit::depth(3),
+            &TestH,
+            MoveOrder::None,
+            Some(&mut tt),
+        );
+        info!("alphabeta {expected:?} {cached:?} {}", tt.len());
+        assert_eq!(expected, cached);
+        let cached = alphabeta(
+            &game,
+            &SearchLimit::depth(3),
+            &TestH,
+            MoveOrder::None,
+            Some(&mut tt),
+        );
+        assert_eq!(expected, cached);
+        assert!(tt.hits > 0);
+    }
+
+    #[test]
+    fn pvs_duel() {
+        use super::*;
+        use crate::env::Vec2D;
+        use crate::game::Snake;
+        use crate::search::{pvs, NodeCounter};
+        logging();
+
+        let snakes = vec![
+            Snake::new(
+                vec![
+                    Vec2D::new(0, 3),
+                    Vec2D::new(1, 3),
+                    Vec2D::new(2, 3),
+                    Vec2D::new(3, 3),
+                ]
+                .into(),
+                100,
+            ),
+            Snake::new(
+                vec![Vec2D::new(10, 7), Vec2D::new(10, 6), Vec2D::new(10, 5)].into(),
+                100,
+            ),
+        ];
+        let game = Game::new(0, 11, 11, snakes, &[], &[]);
+
+        let heuristic = NodeCounter::new(TestH);
+        let expected = alphabeta(
+            &game,
+            &SearchLimit::depth(3),
+            &heuristic,
+            MoveOrder::None,
+            None,
+        );
+        let alphabeta_nodes = heuristic.nodes();
+
+        let heuristic = NodeCounter::new(TestH);
+        let result = pvs(&game, 3, &heuristic, None);
+        let pvs_nodes = heuristic.nodes();
+
+        info!("alphabeta {expected:?} {alphabeta_nodes} nodes");
+        info!("pvs {result:?} {pvs_nodes} nodes");
+        assert_eq!(expected, result);
+        assert!(pvs_nodes < alphabeta_nodes);
+    }
+
+    #[test]
+    fn alphabeta_ordering() {
+        use super::*;
+        use crate::search::NodeCounter;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let heuristic = NodeCounter::new(TestH);
+        let expected = alphabeta(
+            &game,
+            &SearchLimit::depth(3),
+            &heuristic,
+            MoveOrder::None,
+            None,
+        );
+        let unordered_nodes = heuristic.nodes();
+
+        let heuristic = NodeCounter::new(TestH);
+        let result = alphabeta(
+            &game,
+            &SearchLimit::depth(3),
+            &heuristic,
+            MoveOrder::Space,
+            None,
+        );
+        let ordered_nodes = heuristic.nodes();
+
+        info!("unordered {expected:?} {unordered_nodes} nodes");
+        info!("ordered {result:?} {ordered_nodes} nodes");
+        assert_eq!(expected, result);
+        assert!(ordered_nodes < unordered_nodes);
+    }
+
+    #[test]
+    fn alphabeta_principal_variation() {
+        use super::*;
+        use crate::env::Vec2D;
+        use crate::game::{Outcome, Snake};
+        use crate::search::alphabeta_pv;
+        logging();
+
+        let snakes = vec![
+            Snake::new(
+                vec![
+                    Vec2D::new(0, 3),
+                    Vec2D::new(1, 3),
+                    Vec2D::new(2, 3),
+                    Vec2D::new(3, 3),
+                ]
+                .into(),
+                100,
+            ),
+            Snake::new(
+                vec![Vec2D::new(10, 7), Vec2D::new(10, 6), Vec2D::new(10, 5)].into(),
+                100,
+            ),
+        ];
+        let game = Game::new(0, 11, 11, snakes, &[], &[]);
+
+        let depth = 3;
+        let (dir, value, pv) = alphabeta_pv(&game, depth, &TestH, MoveOrder::None, None);
+        info!("{dir:?} {value} {pv:?}");
+        assert_eq!(
+            (dir, value),
+            alphabeta(
+                &game,
+                &SearchLimit::depth(depth),
+                &TestH,
+                MoveOrder::None,
+                None
+            )
+        );
+        assert_eq!(pv[0][0], dir);
+
+        let mut game = game;
+        for actions in &pv {
+            assert_eq!(game.outcome(), Outcome::None);
+            game.step(actions);
+        }
+        info!("{game:?}");
+        assert!(game.outcome() != Outcome::None || pv.len() == depth + 1);
+        if game.outcome() == Outcome::None {
+            assert_eq!(TestH.eval(&game), value);
+        }
+    }
+
+    #[test]
+    fn search_mode() {
+        use super::*;
+        use crate::util::argmax;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . 1 < < . . .
+            . . . . . . .
+            . . . 0 < . .
+            . . . . . 2 .
+            . . . . . ^ ."#,
+        )
+        .unwrap();
+
+        let paranoid = max_n(&game, 1, &TestH, SearchMode::Paranoid, None);
+        let maxn = max_n(&game, 1, &TestH, SearchMode::MaxN, None);
+        info!("paranoid {paranoid:?} maxn {maxn:?}");
+
+        assert_eq!(
+            argmax(paranoid.iter().copied()),
+            Some(Direction::Up as usize)
+        );
+        assert_eq!(argmax(maxn.iter().copied()), Some(Direction::Left as usize));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn search_limit() {
+        use super::*;
+        use std::time::{Duration, Instant};
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        let limit = SearchLimit::new(6, 1, Instant::now() + Duration::from_secs(10));
+        let (dir, value) = alphabeta(&game, &limit, &TestH, MoveOrder::None, None);
+        info!("alphabeta {dir:?} {value} {} nodes", limit.nodes());
+        assert!(limit.aborted());
+        assert!(game.move_is_valid(0, dir));
+
+        let limit = Arc::new(SearchLimit::new(
+            6,
+            10,
+            Instant::now() + Duration::from_secs(10),
+        ));
+        let result = async_max_n(&game, limit.clone(), Arc::new(TestH)).await;
+        info!("async_max_n {result:?} {} nodes", limit.nodes());
+        assert!(limit.aborted());
+        for d in Direction::all() {
+            if result[d as usize] > LOSS {
+                assert!(game.move_is_valid(0, d));
+            }
+        }
+    }
+
+    #[test]
+    fn prune_suicides() {
+        use super::*;
+        logging();
+
+        // The enemy can only move left or right
+        let game = Game::parse(
+            r#"
+            . . . 1 . . .
+            . . . ^ . . .
+            . . . ^ . . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.valid_moves(1).count(), 2);
+
+        let mut results = Vec::new();
+        for prune in [false, true] {
+            let mut limit = SearchLimit::depth(4);
+            limit.prune_suicides = prune;
+            let result = alphabeta(&game, &limit, &TestH, MoveOrder::None, None);
+            info!("prune={prune}: {result:?} {} nodes", limit.nodes());
+            results.push((result, limit.nodes()));
+        }
+        let (full, full_nodes) = results[0];
+        let (pruned, pruned_nodes) = results[1];
+        assert_eq!(full, pruned);
+        assert!(pruned_nodes < full_nodes);
+    }
+
+    #[test]
+    fn faster_win() {
+        use super::*;
+        use crate::search::{alphabeta, is_win, MoveOrder};
+        logging();
+
+        // Down wins the head to head, after Up the enemy is trapped
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            0 < < < < < .
+            . 1 < < . . ."#,
+        )
+        .unwrap();
+
+        let moves = max_n(&game, 3, &TestH, SearchMode::Paranoid, None);
+        info!("max_n {moves:?}");
+        assert!(is_win(moves[Direction::Up as usize]));
+        assert!(moves[Direction::Down as usize] > moves[Direction::Up as usize]);
+
+        let (dir, value) = alphabeta(&game, &SearchLimit::depth(3), &TestH, MoveOrder::None, None);
+        info!("alphabeta {dir:?} {value}");
+        assert_eq!(dir, Direction::Down);
+        assert!(is_win(value));
+    }
+
+    #[test]
+    fn max_n_six_snakes() {
+        use super::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . 1 < . 2 < .
+            . . . . . . .
+            . 3 < 0 < . .
+            . . . . . . .
+            . 4 < . 5 < .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(game.snakes.len(), 6);
+
+        let moves = max_n(&game, 2, &TestH, SearchMode::Paranoid, None);
+        info!("{moves:?}");
+        for d in Direction::all() {
+            assert_eq!(moves[d as usize] > LOSS, game.move_is_valid(0, d));
+        }
+    }
+
+    #[test]
+    fn aspiration_window() {
+        use super::*;
+        use crate::env::Vec2D;
+        use crate::game::Snake;
+        use crate::search::aspiration;
+        logging();
+
+        let snakes = vec![
+            Snake::new(
+                vec![
+                    Vec2D::new(0, 3),
+                    Vec2D::new(1, 3),
+                    Vec2D::new(2, 3),
+                    Vec2D::new(3, 3),
+                ]
+                .into(),
+                100,
+            ),
+            Snake::new(
+                vec![Vec2D::new(10, 7), Vec2D::new(10, 6), Vec2D::new(10, 5)].into(),
+                100,
+            ),
+        ];
+        let game = Game::new(0, 11, 11, snakes, &[], &[]);
+
+        let (_, previous) = alphabeta(&game, &SearchLimit::depth(2), &TestH, MoveOrder::None, None);
+        let expected = alphabeta(&game, &SearchLimit::depth(3), &TestH, MoveOrder::None, None);
+        info!("previous {previous} expected {expected:?}");
+
+        // Window contains the result, fails low, and fails high
+        for (guess, margin) in [
+            (previous, 10.0),
+            (previous + 20.0, 1.0),
+            (previous - 20.0, 1.0),
+        ] {
+            let limit = SearchLimit::depth(3);
+            let result = aspiration(&game, &limit, &TestH, MoveOrder::None, guess, margin, None);
+            info!("{guess}±{margin}: {result:?} {} nodes", limit.nodes());
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[test]
+    fn alphabeta_killer() {
+        use super::*;
+        use crate::agents::FloodHeuristic;
+        use crate::env::GameRequest;
+        use crate::search::NodeCounter;
+        logging();
+
+        let request: GameRequest = serde_json::from_str(
+            r#"{"game":{"id":"17d30fe5-a90f-45c0-bb81-1f8bd54781e1","ruleset":{"damagePerTurn":"14","foodSpawnChance":"15","minimumFood":"1","name":"royale","shrinkEveryNTurns":"25"},"timeout":500},"turn":64,"board":{"width":11,"height":11,"food":[{"x":10,"y":7}],"hazards":[{"x":0,"y":0},{"x":0,"y":1},{"x":0,"y":2},{"x":0,"y":3},{"x":0,"y":4},{"x":0,"y":5},{"x":0,"y":6},{"x":0,"y":7},{"x":0,"y":8},{"x":0,"y":9},{"x":0,"y":10},{"x":1,"y":0},{"x":2,"y":0},{"x":3,"y":0},{"x":4,"y":0},{"x":5,"y":0},{"x":6,"y":0},{"x":7,"y":0},{"x":8,"y":0},{"x":9,"y":0},{"x":10,"y":0}],"snakes":[{"id":"gs_c6BKHbpSr47cqd76mmWTj7dB","name":"unsigned long long","body":[{"x":5,"y":7},{"x":5,"y":6},{"x":5,"y":5},{"x":4,"y":5},{"x":3,"y":5},{"x":2,"y":5}],"health":93,"latency":471,"head":{"x":5,"y":7},"length":6,"shout":"","squad":""},{"id":"gs_BWkm6pVmC6kTmYShrGTrRHfW","name":"marrrvin","body":[{"x":4,"y":4},{"x":3,"y":4},{"x":3,"y":3},{"x":2,"y":3},{"x":1,"y":3}],"health":56,"latency":25,"head":{"x":4,"y":4},"length":5,"shout":"","squad":""},{"id":"gs_Q6FcKJtmmFCC6YtvTM4RVqXM","name":"marrrvin","body":[{"x":7,"y":7},{"x":7,"y":6},{"x":7,"y":5},{"x":8,"y":5},{"x":9,"y":5},{"x":9,"y":4}],"health":86,"latency":26,"head":{"x":7,"y":7},"length":6,"shout":"","squad":""},{"id":"gs_kqMqF4c7rCppw9mSm7vT6Xvb","name":"marrrvin","body":[{"x":9,"y":3},{"x":9,"y":2},{"x":8,"y":2},{"x":7,"y":2},{"x":7,"y":1}],"health":72,"latency":29,"head":{"x":9,"y":3},"length":5,"shout":"","squad":""}]},"you":{"id":"gs_c6BKHbpSr47cqd76mmWTj7dB","name":"unsigned long long","body":[{"x":5,"y":7},{"x":5,"y":6},{"x":5,"y":5},{"x":4,"y":5},{"x":3,"y":5},{"x":2,"y":5}],"health":93,"latency":471,"head":{"x":5,"y":7},"length":6,"shout":"","squad":""}}"#
+        ).unwrap();
+        let game = Game::from_request(&request);
+
+        let mut results = Vec::new();
+        for order in [MoveOrder::None, MoveOrder::Space, MoveOrder::Killer] {
+            let heuristic = NodeCounter::new(FloodHeuristic::default());
+            let result = alphabeta(&game, &SearchLimit::depth(1), &heuristic, order, None);
+            info!("{order:?} {result:?} {} nodes", heuristic.nodes());
+            results.push((result, heuristic.nodes()));
+        }
+        let (space, space_nodes) = results[1];
+        let (killer, killer_nodes) = results[2];
+        assert_eq!(results[0].0, space);
+        assert_eq!(space, killer);
+        assert!(killer_nodes < space_nodes);
+    }
+
+    #[test]
+    fn quiescence_extension() {
+        use super::*;
+        use crate::env::v2;
+        use crate::grid::CellT;
+        logging();
+
+        /// Distance to the nearest food.
+        #[derive(Debug, Clone, Default)]
+        struct FoodH;
+        impl Heuristic for FoodH {
+            fn eval(&self, game: &Game) -> f64 {
+                if !game.snake_is_alive(0) {
+                    return LOSS;
+                }
+                let head = game.snakes[0].head();
+                let mut dist = None;
+                for y in 0..game.grid.height as i16 {
+                    for x in 0..game.grid.width as i16 {
+                        if game.grid[v2(x, y)].t == CellT::Food {
+                            let d = (v2(x, y) - head).manhattan();
+                            dist = Some(dist.map_or(d, |m: u64| m.min(d)));
+                        }
+                    }
+                }
+                dist.map_or(-100.0, |d| -(d as f64))
+            }
+        }
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . . . .
+            . . . . . . .
+            . o . 0 < < .
+            . . . . . . .
+            . 1 < < < < .
+            . . . . . . ."#,
+        )
+        .unwrap();
+
+        // Without extension we rush towards the food
+        let (dir, _) = alphabeta(&game, &SearchLimit::depth(0), &FoodH, MoveOrder::None, None);
+        info!("without extension {dir:?}");
+        assert_eq!(dir, Direction::Left);
+
+        // The longer enemy would contest the food head to head
+        let mut limit = SearchLimit::depth(0);
+        limit.max_extension = 2;
+        let (dir, value) = alphabeta(&game, &limit, &FoodH, MoveOrder::None, None);
+        info!("with extension {dir:?} {value}");
+        assert_ne!(dir, Direction::Left);
+    }
 }
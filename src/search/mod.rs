@@ -2,10 +2,17 @@ mod minimax;
 pub use minimax::*;
 mod alphabeta;
 pub use alphabeta::*;
+mod expectimax;
+pub use expectimax::*;
 mod mcts;
 pub use mcts::*;
+mod tt;
+pub use tt::*;
+mod limit;
+pub use limit::*;
 
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::game::Game;
 
@@ -13,7 +20,74 @@ pub const WIN: f64 = 10000.0;
 pub const DRAW: f64 = 0.0;
 pub const LOSS: f64 = -10000.0;
 
+/// Wins and losses are discounted by at most this value, so that sooner wins
+/// and later losses are preferred.
+const MAX_DISCOUNT: f64 = 100.0;
+
+/// Value of a win with `depth` turns left to search.
+pub fn win_at(depth: usize) -> f64 {
+    WIN - (MAX_DISCOUNT - depth as f64).max(0.0)
+}
+
+/// Value of a loss with `depth` turns left to search.
+pub fn loss_at(depth: usize) -> f64 {
+    LOSS + (MAX_DISCOUNT - depth as f64).max(0.0)
+}
+
+/// Returns if the value is a (discounted) win.
+pub fn is_win(value: f64) -> bool {
+    value >= WIN - MAX_DISCOUNT
+}
+
+/// Returns if the value is a (discounted) loss.
+pub fn is_loss(value: f64) -> bool {
+    value <= LOSS + MAX_DISCOUNT
+}
+
+/// Maximum number of snakes the tree searches support.
+pub const MAX_SNAKES: usize = 8;
+
+/// Returns if two living snakes might collide head to head in the next turn.
+///
+/// This is used to extend the search beyond its depth, as the heuristic is
+/// misleading for these unstable positions.
+pub fn imminent_collision(game: &Game) -> bool {
+    let heads = game.snakes.iter().filter(|s| s.alive()).map(|s| s.head());
+    heads
+        .clone()
+        .enumerate()
+        .any(|(i, a)| heads.clone().skip(i + 1).any(|b| (a - b).manhattan() <= 2))
+}
+
 /// A heuristic that evaluates the game state at the leafs of a tree search.
 pub trait Heuristic: Debug + Send + Sync + 'static {
     fn eval(&self, game: &Game) -> f64;
 }
+
+/// Wraps a heuristic and counts the number of evaluated leafs.
+#[derive(Debug, Default)]
+pub struct NodeCounter<H: Heuristic> {
+    pub heuristic: H,
+    nodes: AtomicUsize,
+}
+
+impl<H: Heuristic> NodeCounter<H> {
+    pub fn new(heuristic: H) -> Self {
+        Self {
+            heuristic,
+            nodes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of evaluated nodes.
+    pub fn nodes(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed)
+    }
+}
+
+impl<H: Heuristic> Heuristic for NodeCounter<H> {
+    fn eval(&self, game: &Game) -> f64 {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        self.heuristic.eval(game)
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::env::Direction;
+use crate::game::Game;
+use crate::zobrist;
+
+/// Relation of a stored value to the real value of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The value is exact.
+    Exact,
+    /// The real value is at least as large (beta cutoff).
+    Lower,
+    /// The real value is at most as large (no move raised alpha).
+    Upper,
+}
+
+/// Search result of a position.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub value: f64,
+    pub bound: Bound,
+    /// Best move of the maximizing player.
+    pub dir: Direction,
+}
+
+impl Entry {
+    /// Creates a new entry for the value that was searched with the given window.
+    pub fn new(value: f64, alpha: f64, beta: f64, dir: Direction) -> Self {
+        let bound = if value <= alpha {
+            Bound::Upper
+        } else if value >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        Self { value, bound, dir }
+    }
+}
+
+/// Stores the results of already searched positions.
+///
+/// Entries are keyed by the position hash and the remaining depth, so that
+/// cached values are identical to the ones of the uncached search.
+///
+/// Every entry is tagged with the generation it was stored in,
+/// so that a table can be reused over multiple turns, see `age`.
+#[derive(Debug, Default)]
+pub struct TranspositionTable {
+    entries: HashMap<(u64, usize), (usize, Entry)>,
+    generation: usize,
+    /// Number of successful lookups.
+    pub hits: usize,
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the key of the game, which also includes the turn as the
+    /// heuristics might depend on it.
+    pub fn hash(game: &Game) -> u64 {
+        game.zobrist_hash() ^ zobrist::turn(game.turn)
+    }
+
+    pub fn get(&mut self, hash: u64, depth: usize) -> Option<Entry> {
+        let entry = self.entries.get(&(hash, depth)).map(|&(_, entry)| entry);
+        if entry.is_some() {
+            self.hits += 1;
+        }
+        entry
+    }
+
+    /// Returns the entry if its value can be used for the given search window.
+    pub fn probe(&mut self, hash: u64, depth: usize, alpha: f64, beta: f64) -> Option<Entry> {
+        self.get(hash, depth).filter(|entry| match entry.bound {
+            Bound::Exact => true,
+            Bound::Lower => entry.value >= beta,
+            Bound::Upper => entry.value <= alpha,
+        })
+    }
+
+    pub fn insert(&mut self, hash: u64, depth: usize, entry: Entry) {
+        self.entries.insert((hash, depth), (self.generation, entry));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Starts a new generation and removes all entries that are more than
+    /// `max_age` generations old.
+    ///
+    /// This is used to keep the table between turns, where the positions of
+    /// older turns are not reachable anymore.
+    pub fn age(&mut self, max_age: usize) {
+        self.generation += 1;
+        let generation = self.generation;
+        self.entries.retain(|_, (g, _)| generation - *g <= max_age);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.hits = 0;
+    }
+}
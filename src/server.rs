@@ -0,0 +1,232 @@
+//! Routes of the Battlesnake webserver.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+
+use log::{info, warn};
+use warp::Filter;
+
+use crate::agents::{Instances, RulesetConfigs};
+use crate::debug::DebugStream;
+use crate::env::{GameRequest, IndexResponse, API_VERSION};
+use crate::metrics::Metrics;
+use crate::savegame::SaveWriter;
+
+pub const PACKAGE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Runtime server configuration.
+pub struct State {
+    pub latency: u64,
+    pub max_board_size: usize,
+    pub color: String,
+    pub head: String,
+    pub tail: String,
+    pub author: String,
+    pub configs: RulesetConfigs,
+    /// Agents of the running games.
+    pub instances: Instances,
+    pub metrics: Metrics,
+    /// Streams the served moves to the debugging clients.
+    pub debug: Arc<DebugStream>,
+    /// Writer of the saved games, if enabled.
+    pub savegames: Option<SaveWriter>,
+}
+
+impl State {
+    /// Customization of the snake, returned by `/` and `/start`.
+    fn customization(&self) -> warp::reply::Json {
+        warp::reply::json(&IndexResponse::new(
+            API_VERSION,
+            &self.author,
+            &self.color,
+            &self.head,
+            &self.tail,
+            PACKAGE_VERSION,
+        ))
+    }
+
+    /// Appends the request to the saved game in the background, if enabled.
+    fn save(&self, request: &GameRequest) {
+        if let Some(writer) = &self.savegames {
+            writer.save(request);
+        }
+    }
+}
+
+/// Battlesnake API, metrics and debugging routes.
+pub fn routes(
+    state: Arc<State>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let index = warp::get()
+        .and(warp::path::end())
+        .and(with_state(state.clone()))
+        .map(|state: Arc<State>| {
+            warn!("index");
+            state.customization()
+        });
+
+    let start = warp::path("start")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(start);
+
+    let r#move = warp::path("move")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(step);
+
+    let end = warp::path("end")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .map(|request: GameRequest, state: Arc<State>| {
+            warn!(game_id = request.game.id.as_str(); "end {request}");
+            state.save(&request);
+            state.instances.end(&request);
+            state.metrics.end();
+            warp::reply()
+        });
+
+    let metrics = warp::path("metrics")
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .map(|state: Arc<State>| state.metrics.render(state.instances.len()));
+
+    let debug = state.debug.clone().filter();
+
+    index.or(start).or(r#move).or(end).or(metrics).or(debug)
+}
+
+fn with_state(
+    config: Arc<State>,
+) -> impl Filter<Extract = (Arc<State>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+async fn start(request: GameRequest, state: Arc<State>) -> Result<impl warp::Reply, Infallible> {
+    warn!(game_id = request.game.id.as_str(); "start {request}");
+    state.save(&request);
+
+    let timer = Instant::now();
+    state
+        .instances
+        .start(
+            state.configs.select(&request.game.ruleset.name),
+            &request,
+            state.latency,
+            state.max_board_size,
+        )
+        .await;
+    info!("warmed up, {:?}ms", timer.elapsed().as_millis());
+    state.metrics.start();
+
+    Ok(state.customization())
+}
+
+async fn step(request: GameRequest, state: Arc<State>) -> Result<impl warp::Reply, Infallible> {
+    warn!(game_id = request.game.id.as_str(); "move {request}");
+    state.save(&request);
+
+    let timer = Instant::now();
+    let next_move = state
+        .instances
+        .step(
+            state.configs.select(&request.game.ruleset.name),
+            &request,
+            state.latency,
+            state.max_board_size,
+        )
+        .await;
+    let elapsed = timer.elapsed();
+    info!(game_id = request.game.id.as_str(); "{next_move:?}, {:?}ms", elapsed.as_millis());
+    state.metrics.record_move(elapsed);
+    state.debug.publish(&request, &next_move);
+
+    Ok(warp::reply::json(&next_move))
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn server_routes() {
+        use super::*;
+        use crate::agents::{Agent, MAX_BOARD_SIZE};
+        use crate::env::MoveResponse;
+        use crate::game::Game;
+        logging();
+
+        let state = Arc::new(State {
+            latency: 0,
+            max_board_size: MAX_BOARD_SIZE,
+            color: "#FF7043".into(),
+            head: "sand-worm".into(),
+            tail: "pixel".into(),
+            author: "wrenger".into(),
+            configs: RulesetConfigs::new(Agent::default()),
+            instances: Instances::default(),
+            metrics: Metrics::default(),
+            debug: Default::default(),
+            savegames: None,
+        });
+        let routes = routes(state.clone());
+
+        let game = Game::parse(
+            r#"
+            . . . . . . .
+            . . . . 1 . .
+            . . . . v . .
+            . . . . . . .
+            . . 0 < < . .
+            . . . . . . .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        let mut request = game.to_request(0, "standard");
+        request.game.id = "g".into();
+        request.game.timeout = 200;
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        let index: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(index["color"], "#FF7043");
+
+        // Responds with the customization
+        let response = warp::test::request()
+            .method("POST")
+            .path("/start")
+            .json(&request)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        let start: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(start, index);
+        assert_eq!(state.instances.len(), 1);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/move")
+            .json(&request)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        let response: MoveResponse = serde_json::from_slice(response.body()).unwrap();
+        info!("{response:?}");
+        assert!(game.move_is_valid(0, response.r#move));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/end")
+            .json(&request)
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(state.instances.len(), 0);
+    }
+}
@@ -0,0 +1,1463 @@
+//! Simulation of whole games between agents, used to compare configurations.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use log::{debug, info, warn};
+use rand::prelude::*;
+use rand::seq::IteratorRandom;
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use crate::agents::{Agent, AgentInstance, MAX_BOARD_SIZE};
+use crate::dataset::{self, Sample};
+use crate::env::{v2, Direction, GameRequest, Vec2D};
+use crate::game::{Game, Outcome, Snake};
+use crate::grid::CellT;
+use crate::render;
+
+/// Rules of the simulated games.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    /// Time each snake has for a turn.
+    pub timeout: u64,
+    /// Larger boards are played by the random agent.
+    pub max_board_size: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Chance new food spawns.
+    pub food_rate: f64,
+    /// Food is refilled up to this amount every turn.
+    pub min_food: usize,
+    /// Number of turns after which the hazard expands.
+    pub shrink_turns: usize,
+    pub hazards: HazardSchedule,
+    /// Start config, instead of a random one.
+    pub init: Option<GameRequest>,
+    /// Directory for the SVG frames of every turn.
+    /// [Simulation::run] uses a subdirectory for every game.
+    pub render: Option<PathBuf>,
+    /// Record the training [Sample]s of every turn in [GameStats::samples].
+    pub samples: bool,
+    /// The game is a draw if a position is repeated this many times within
+    /// the last `REPETITION_WINDOW` turns, disabled if zero.
+    /// Only if the snakes have the same health, otherwise one starves first.
+    pub repetitions: usize,
+}
+
+/// Number of previous turns that are checked for repeated positions.
+const REPETITION_WINDOW: usize = 64;
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self {
+            timeout: 200,
+            max_board_size: MAX_BOARD_SIZE,
+            width: 11,
+            height: 11,
+            food_rate: 0.15,
+            min_food: 1,
+            shrink_turns: 25,
+            hazards: HazardSchedule::Royale,
+            init: None,
+            render: None,
+            samples: false,
+            repetitions: 3,
+        }
+    }
+}
+
+impl Simulation {
+    /// Creates the initial game for the given number of agents.
+    pub fn new_game(&self, num_agents: usize, rng: &mut SmallRng) -> Game {
+        if let Some(request) = &self.init {
+            Game::from_request(request)
+        } else {
+            init_game(self.width, self.height, num_agents, rng)
+        }
+    }
+
+    /// Plays the game until it ends.
+    ///
+    /// The `agents` control the snakes with the same index,
+    /// additional agents are ignored.
+    pub async fn play(
+        &self,
+        agents: &[AgentInstance],
+        game: &mut Game,
+        rng: &mut SmallRng,
+    ) -> GameStats {
+        assert!(
+            agents.len() >= game.snakes.len(),
+            "Every snake requires an agent"
+        );
+        debug!("init: {game:?}");
+
+        let mut stats = GameStats {
+            players: (0..game.snakes.len()).collect(),
+            winner: None,
+            turns: 0,
+            lengths: game.snakes.iter().map(|s| s.body.len()).collect(),
+            deaths: vec![None; game.snakes.len()],
+            samples: Vec::new(),
+        };
+
+        let mut shrink = HazardShrink::from_game(self.hazards, self.shrink_turns, game);
+        let mut history = VecDeque::with_capacity(REPETITION_WINDOW);
+        history.push_back(game.position_hash());
+
+        self.render(game);
+        for turn in game.turn.. {
+            let mut moves = vec![Direction::Up; game.snakes.len()];
+            for i in 0..game.snakes.len() {
+                if game.snakes[i].alive() {
+                    // Agents assume player 0 is you.
+                    game.snakes.swap(0, i);
+
+                    let response = agents[i]
+                        .step_internal(self.timeout, self.max_board_size, game)
+                        .await;
+                    moves[i] = response.r#move;
+                    if self.samples {
+                        stats.samples.push(Sample::new(game, i, moves[i]));
+                    }
+
+                    game.snakes.swap(0, i);
+                }
+            }
+            debug!("Moves: {moves:?}");
+
+            let before = game.clone();
+            game.step(&moves);
+
+            debug!("{}: {:?}", turn, game);
+            self.render(game);
+
+            stats.turns = game.turn;
+            for (i, snake) in game.snakes.iter().enumerate() {
+                if snake.alive() {
+                    stats.lengths[i] = snake.body.len();
+                } else if before.snakes[i].alive() {
+                    stats.deaths[i] = Some(Death::of(&before, &moves, i));
+                }
+            }
+
+            let outcome = game.outcome();
+            if outcome != Outcome::None {
+                warn!("game: {outcome:?} after {turn} turns");
+                if let Outcome::Winner(winner) = outcome {
+                    stats.winner = Some(winner as usize);
+                }
+                dataset::label(&mut stats.samples, stats.winner);
+                return stats;
+            }
+
+            spawn_food(game, self.min_food, self.food_rate, rng);
+
+            shrink.step(game, turn, rng);
+
+            // Adjudicate stalling snakes as draw, if they would starve together
+            let hash = game.position_hash();
+            let repeated = history.iter().filter(|&&h| h == hash).count() + 1;
+            let mut health = game.snakes.iter().filter(|s| s.alive()).map(|s| s.health);
+            let first = health.next();
+            let equal_health = health.all(|h| Some(h) == first);
+            if self.repetitions > 0 && repeated >= self.repetitions && equal_health {
+                warn!("game: draw by repetition after {turn} turns");
+                dataset::label(&mut stats.samples, None);
+                return stats;
+            }
+            if history.len() == REPETITION_WINDOW {
+                history.pop_front();
+            }
+            history.push_back(hash);
+        }
+        stats
+    }
+
+    /// Plays `game_count` games with up to `jobs` games running concurrently.
+    ///
+    /// Every game has its own rng, seeded with `seed` and its index, so that
+    /// the results do not depend on the number of jobs.
+    /// A `seed` of zero chooses random seeds.
+    /// The games are numbered from `first` for the rendered frames.
+    /// Returns the records ordered by their games.
+    pub async fn run(
+        &self,
+        agents: &[Agent],
+        first: usize,
+        game_count: usize,
+        jobs: usize,
+        seed: u64,
+    ) -> Vec<GameStats> {
+        if let Some(request) = &self.init {
+            assert!(
+                agents.len() >= request.board.snakes.len(),
+                "The start config requires an agent for each snake"
+            );
+        }
+        let mut tasks = JoinSet::new();
+        let mut records = vec![None; game_count];
+        let mut games = 0..game_count;
+
+        loop {
+            while tasks.len() < jobs.max(1) {
+                let Some(i) = games.next() else {
+                    break;
+                };
+                let mut simulation = self.clone();
+                simulation.render = self
+                    .render
+                    .as_ref()
+                    .map(|dir| dir.join(format!("game_{}", first + i)));
+                let agents: Vec<AgentInstance> = agents.iter().map(Agent::instance).collect();
+                tasks.spawn(async move {
+                    let mut rng = if seed == 0 {
+                        SmallRng::from_entropy()
+                    } else {
+                        SmallRng::seed_from_u64(seed.wrapping_add(i as u64))
+                    };
+                    let mut game = simulation.new_game(agents.len(), &mut rng);
+                    (i, simulation.play(&agents, &mut game, &mut rng).await)
+                });
+            }
+
+            let Some(result) = tasks.join_next().await else {
+                break;
+            };
+            let (i, stats) = result.expect("Simulation failed");
+            info!("game {}: {:?}", first + i, stats.winner);
+            records[i] = Some(stats);
+        }
+        records.into_iter().flatten().collect()
+    }
+
+    fn render(&self, game: &Game) {
+        if let Some(dir) = &self.render {
+            if let Err(e) = render::save_frame(dir, game) {
+                warn!("render {dir:?}: {e}");
+            }
+        }
+    }
+
+    /// Plays `game_count` games between the two agents in each position,
+    /// with the same seeds for both, to compare `a` against `b`.
+    pub async fn head_to_head(
+        &self,
+        a: &Agent,
+        b: &Agent,
+        game_count: usize,
+        jobs: usize,
+        seed: u64,
+    ) -> MatchReport {
+        let mut report = MatchReport::default();
+        let lineup = [a.clone(), b.clone()];
+        for stats in self.run(&lineup, 0, game_count, jobs, seed).await {
+            report.record(stats.winner);
+        }
+        let swapped = [b.clone(), a.clone()];
+        for stats in self.run(&swapped, game_count, game_count, jobs, seed).await {
+            report.record(stats.winner.map(|w| 1 - w));
+        }
+        report
+    }
+
+    /// Plays `game_count` games for every subset of `size` agents.
+    /// The agent positions are rotated between the games.
+    /// The frames of every game are rendered into a separate subdirectory.
+    pub async fn tournament(
+        &self,
+        agents: &[Agent],
+        size: usize,
+        game_count: usize,
+        k_factor: f64,
+        rng: &mut SmallRng,
+    ) -> Tournament {
+        let size = size.clamp(1, agents.len().min(4));
+        let mut result = Tournament::new(agents.len(), k_factor);
+        let mut simulation = self.clone();
+
+        for (n, players) in combinations(agents.len(), size).into_iter().enumerate() {
+            for i in 0..game_count {
+                let mut players = players.clone();
+                players.rotate_left(i % size);
+                // Games do not share the transposition tables of the agents
+                let lineup: Vec<AgentInstance> =
+                    players.iter().map(|&p| agents[p].instance()).collect();
+
+                simulation.render = self
+                    .render
+                    .as_ref()
+                    .map(|dir| dir.join(format!("game_{}", n * game_count + i)));
+                let mut game = simulation.new_game(size, rng);
+                let stats = simulation.play(&lineup, &mut game, rng).await;
+                result.record(&players, stats.winner);
+            }
+        }
+        result
+    }
+}
+
+/// Record of a simulated game.
+///
+/// The `lengths` and `deaths` are ordered like the snakes of the game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GameStats {
+    /// Agent indices of the snakes.
+    pub players: Vec<usize>,
+    /// Index of the winning snake, `None` if all died.
+    pub winner: Option<usize>,
+    pub turns: usize,
+    /// Final lengths, or the lengths before dying.
+    pub lengths: Vec<usize>,
+    pub deaths: Vec<Option<Death>>,
+    /// Training samples, if enabled with [Simulation::samples].
+    #[serde(skip)]
+    pub samples: Vec<Sample>,
+}
+
+impl GameStats {
+    /// Agent index of the winner.
+    pub fn winning_agent(&self) -> Option<usize> {
+        self.winner.map(|w| self.players[w])
+    }
+}
+
+/// Cause of the death of a snake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Death {
+    /// Moved out of the board.
+    Wall,
+    /// Moved into its own body.
+    SelfCollision,
+    /// Moved into the body of another snake.
+    Collision,
+    /// Lost the head to head against a longer snake.
+    HeadToHead,
+    /// Ran out of health.
+    Starvation,
+}
+
+impl Death {
+    /// Determines why the snake `id` died with the `moves` in the game.
+    /// The rules are checked in the same order as in [Game::step].
+    pub fn of(game: &Game, moves: &[Direction], id: usize) -> Self {
+        let snake = &game.snakes[id];
+        let dir = moves[id];
+        let head = game.apply(snake.head(), dir);
+        if !game.grid.has(head) {
+            return Self::Wall;
+        }
+
+        // Tails move away, unless the snake has just eaten
+        let occupied = |s: &Snake| {
+            s.body
+                .iter()
+                .skip(s.tail_moves() as usize)
+                .any(|&p| p == head)
+        };
+        if occupied(snake) {
+            return Self::SelfCollision;
+        }
+        if game
+            .snakes
+            .iter()
+            .any(|s| s.alive() && !s.is_teammate(snake) && occupied(s))
+        {
+            return Self::Collision;
+        }
+
+        let cell = game.grid[head];
+        if cell.t != CellT::Food && snake.health <= game.grid.damage(head) {
+            return Self::Starvation;
+        }
+        Self::HeadToHead
+    }
+}
+
+/// Writes the records as JSON array if the file ends with `.json`,
+/// or as CSV otherwise.
+pub fn write_stats(path: &Path, stats: &[GameStats]) -> io::Result<()> {
+    let out = if path.extension().is_some_and(|e| e == "json") {
+        serde_json::to_string_pretty(stats)?
+    } else {
+        let list = |values: Vec<String>| values.join(";");
+        let mut out = String::from("game,players,winner,turns,lengths,deaths\n");
+        for (i, game) in stats.iter().enumerate() {
+            let deaths = game
+                .deaths
+                .iter()
+                .map(|d| match d {
+                    Some(d) => serde_json::to_value(d)
+                        .ok()
+                        .and_then(|v| v.as_str().map(str::to_string))
+                        .unwrap_or_default(),
+                    None => String::new(),
+                })
+                .collect();
+            let _ = writeln!(
+                out,
+                "{i},{},{},{},{},{}",
+                list(game.players.iter().map(|p| p.to_string()).collect()),
+                game.winning_agent()
+                    .map(|w| w.to_string())
+                    .unwrap_or_default(),
+                game.turns,
+                list(game.lengths.iter().map(|l| l.to_string()).collect()),
+                list(deaths),
+            );
+        }
+        out
+    };
+    fs::write(path, out)
+}
+
+/// How the hazards expand from the edges of the board.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HazardSchedule {
+    /// No hazards.
+    None,
+    /// Like the royale mode of the official game engine, a random side
+    /// shrinks by one row or column every cycle.
+    #[default]
+    Royale,
+    /// All sides shrink by one row or column every cycle.
+    Symmetric,
+}
+
+impl FromStr for HazardSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "royale" => Ok(Self::Royale),
+            "symmetric" => Ok(Self::Symmetric),
+            _ => Err(format!("Unknown hazard schedule {s:?}")),
+        }
+    }
+}
+
+/// Expands the hazards every `shrink_turns` turns.
+#[derive(Debug, Clone)]
+pub struct HazardShrink {
+    schedule: HazardSchedule,
+    shrink_turns: usize,
+    /// Rows and columns covered from the bottom, left, top, and right.
+    insets: [usize; 4],
+}
+
+impl HazardShrink {
+    pub fn new(schedule: HazardSchedule, shrink_turns: usize) -> Self {
+        Self {
+            schedule,
+            shrink_turns: shrink_turns.max(1),
+            insets: [0; 4],
+        }
+    }
+
+    /// Continues the expansion of the hazards in the game, which might have
+    /// been started mid-game.
+    pub fn from_game(schedule: HazardSchedule, shrink_turns: usize, game: &Game) -> Self {
+        let (width, height) = (game.grid.width, game.grid.height);
+        let row = |y: usize| (0..width).all(|x| game.grid[v2(x as _, y as _)].hazard > 0);
+        let col = |x: usize| (0..height).all(|y| game.grid[v2(x as _, y as _)].hazard > 0);
+
+        let bottom = (0..height).take_while(|&y| row(y)).count();
+        let top = (bottom..height).rev().take_while(|&y| row(y)).count();
+        let left = (0..width).take_while(|&x| col(x)).count();
+        let right = (left..width).rev().take_while(|&x| col(x)).count();
+        Self {
+            insets: [bottom, left, top, right],
+            ..Self::new(schedule, shrink_turns)
+        }
+    }
+
+    /// Expands the hazards if a cycle ended with this turn.
+    pub fn step(&mut self, game: &mut Game, turn: usize, rng: &mut SmallRng) {
+        if turn == 0 || !turn.is_multiple_of(self.shrink_turns) {
+            return;
+        }
+        match self.schedule {
+            HazardSchedule::None => {}
+            HazardSchedule::Royale => self.shrink(game, rng.gen_range(0..4)),
+            HazardSchedule::Symmetric => {
+                for side in 0..4 {
+                    self.shrink(game, side);
+                }
+            }
+        }
+    }
+
+    /// Covers the next row or column of the side with hazards.
+    fn shrink(&mut self, game: &mut Game, side: usize) {
+        let [bottom, left, top, right] = self.insets;
+        let (width, height) = (game.grid.width, game.grid.height);
+        if bottom + top >= height || left + right >= width {
+            return;
+        }
+        self.insets[side] += 1;
+        if side.is_multiple_of(2) {
+            let y = if side == 0 { bottom } else { height - top - 1 };
+            for x in 0..width {
+                game.grid[v2(x as _, y as _)].hazard = 1;
+            }
+        } else {
+            let x = if side == 1 { left } else { width - right - 1 };
+            for y in 0..height {
+                game.grid[v2(x as _, y as _)].hazard = 1;
+            }
+        }
+    }
+}
+
+/// Spawns food like the standard rules of the official game engine.
+///
+/// The food is refilled up to `min_food`, or else one food spawns with the
+/// chance of `food_rate`. It is placed on random free cells, which are
+/// not adjacent to a snake head if possible.
+/// Returns the number of spawned food.
+pub fn spawn_food(game: &mut Game, min_food: usize, food_rate: f64, rng: &mut SmallRng) -> usize {
+    let food = game
+        .grid
+        .cells
+        .iter()
+        .filter(|c| c.t == CellT::Food)
+        .count();
+    let count = if food < min_food {
+        min_food - food
+    } else if rng.gen::<f64>() < food_rate {
+        1
+    } else {
+        return 0;
+    };
+
+    let heads: Vec<Vec2D> = game
+        .snakes
+        .iter()
+        .filter(|s| s.alive())
+        .map(|s| s.head())
+        .collect();
+    let mut free = Vec::new();
+    let mut near_heads = Vec::new();
+    for y in 0..game.grid.height {
+        for x in 0..game.grid.width {
+            let p = v2(x as _, y as _);
+            if game.grid[p].t != CellT::Free {
+                continue;
+            }
+            if heads.iter().any(|&h| (h - p).manhattan() <= 1) {
+                near_heads.push(p);
+            } else {
+                free.push(p);
+            }
+        }
+    }
+
+    let mut spawned = 0;
+    for candidates in [free, near_heads] {
+        for p in candidates.choose_multiple(rng, count - spawned) {
+            game.grid[*p].t = CellT::Food;
+            spawned += 1;
+        }
+        if spawned == count {
+            break;
+        }
+    }
+    spawned
+}
+
+/// Results of a [Simulation::tournament].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tournament {
+    /// `wins[i][j]`: Games agent `i` won against agent `j`.
+    pub wins: Vec<Vec<usize>>,
+    /// Games played by each agent.
+    pub games: Vec<usize>,
+    /// Games won by each agent.
+    pub won: Vec<usize>,
+    /// Games without a winner.
+    pub draws: usize,
+    /// Ratings updated after every game.
+    pub elo: Elo,
+}
+
+impl Tournament {
+    fn new(agents: usize, k_factor: f64) -> Self {
+        Self {
+            elo: Elo::new(agents, k_factor),
+            wins: vec![vec![0; agents]; agents],
+            games: vec![0; agents],
+            won: vec![0; agents],
+            draws: 0,
+        }
+    }
+
+    fn record(&mut self, players: &[usize], winner: Option<usize>) {
+        let outcome = winner.map_or(Outcome::Match, |w| Outcome::Winner(w as u8));
+        self.elo.update(players, outcome);
+        for &p in players {
+            self.games[p] += 1;
+        }
+        if let Some(winner) = winner {
+            let winner = players[winner];
+            self.won[winner] += 1;
+            for &p in players {
+                if p != winner {
+                    self.wins[winner][p] += 1;
+                }
+            }
+        } else {
+            self.draws += 1;
+        }
+    }
+
+    /// Share of the played games the agent won.
+    pub fn win_rate(&self, agent: usize) -> f64 {
+        match self.games.get(agent) {
+            Some(&games) if games > 0 => self.won[agent] as f64 / games as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Tournament {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>6}", "")?;
+        for j in 0..self.wins.len() {
+            write!(f, " {j:>5}")?;
+        }
+        writeln!(f, " {:>8} {:>6}", "win rate", "elo")?;
+        for (i, row) in self.wins.iter().enumerate() {
+            write!(f, "{i:>6}")?;
+            for (j, wins) in row.iter().enumerate() {
+                if i == j {
+                    write!(f, " {:>5}", "-")?;
+                } else {
+                    write!(f, " {wins:>5}")?;
+                }
+            }
+            writeln!(
+                f,
+                " {:>7.1}% {:>6.0}",
+                self.win_rate(i) * 100.0,
+                self.elo.ratings[i]
+            )?;
+        }
+        write!(f, "draws: {}", self.draws)
+    }
+}
+
+/// Results of a [Simulation::head_to_head] match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchReport {
+    /// Wins of the first and second agent.
+    pub wins: [usize; 2],
+    pub draws: usize,
+}
+
+impl MatchReport {
+    /// Z-score of the 95% confidence level.
+    pub const Z95: f64 = 1.96;
+
+    fn record(&mut self, winner: Option<usize>) {
+        match winner {
+            Some(winner) => self.wins[winner] += 1,
+            None => self.draws += 1,
+        }
+    }
+
+    pub fn games(&self) -> usize {
+        self.wins[0] + self.wins[1] + self.draws
+    }
+
+    /// Score of the first agent, draws count as half a win.
+    pub fn win_rate(&self) -> f64 {
+        match self.games() {
+            0 => 0.5,
+            games => (self.wins[0] as f64 + 0.5 * self.draws as f64) / games as f64,
+        }
+    }
+
+    /// Wilson score interval of the win rate of the first agent.
+    ///
+    /// If it does not contain 0.5, the first agent is significantly
+    /// stronger or weaker at this confidence level.
+    pub fn interval(&self, z: f64) -> (f64, f64) {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return (0.0, 1.0);
+        }
+        let p = self.win_rate();
+        let z2 = z * z;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        ((center - margin).max(0.0), (center + margin).min(1.0))
+    }
+
+    /// If the difference between the agents is significant.
+    pub fn significant(&self, z: f64) -> bool {
+        let (low, high) = self.interval(z);
+        !(low..=high).contains(&0.5)
+    }
+}
+
+impl fmt::Display for MatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (low, high) = self.interval(Self::Z95);
+        write!(
+            f,
+            "{}-{}-{} ({} games): win rate {:.1}% [{:.1}%, {:.1}%] at 95%{}",
+            self.wins[0],
+            self.draws,
+            self.wins[1],
+            self.games(),
+            self.win_rate() * 100.0,
+            low * 100.0,
+            high * 100.0,
+            if self.significant(Self::Z95) {
+                ", significant"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+/// Elo ratings of the agents.
+///
+/// Games with more than two players are rated as if the winner defeated
+/// every other player, and everyone drew against each other if there is no
+/// winner. The K-factor is split between the opponents, so that a game
+/// counts the same regardless of the number of players.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Elo {
+    pub ratings: Vec<f64>,
+    /// Maximum rating change per game.
+    pub k_factor: f64,
+}
+
+impl Elo {
+    /// Rating of new agents.
+    pub const INITIAL: f64 = 1500.0;
+
+    pub fn new(agents: usize, k_factor: f64) -> Self {
+        Self {
+            ratings: vec![Self::INITIAL; agents],
+            k_factor,
+        }
+    }
+
+    /// Expected score of `a` against `b`.
+    pub fn expected(a: f64, b: f64) -> f64 {
+        1.0 / (1.0 + 10f64.powf((b - a) / 400.0))
+    }
+
+    /// Updates the ratings of the `players` with the outcome of their game.
+    pub fn update(&mut self, players: &[usize], outcome: Outcome) {
+        if players.len() < 2 {
+            return;
+        }
+        let winner = match outcome {
+            Outcome::Winner(winner) => Some(players[winner as usize]),
+            _ => None,
+        };
+        let k = self.k_factor / (players.len() - 1) as f64;
+
+        let mut deltas = vec![0.0; players.len()];
+        for (i, &a) in players.iter().enumerate() {
+            for &b in players {
+                if a == b {
+                    continue;
+                }
+                let score = match winner {
+                    Some(w) if w == a => 1.0,
+                    Some(w) if w == b => 0.0,
+                    // Neither won against the other
+                    _ => 0.5,
+                };
+                deltas[i] += k * (score - Self::expected(self.ratings[a], self.ratings[b]));
+            }
+        }
+        for (&p, delta) in players.iter().zip(deltas) {
+            self.ratings[p] += delta;
+        }
+    }
+}
+
+/// All subsets of `k` elements of `0..n` in lexicographic order.
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    fn rec(start: usize, n: usize, k: usize, current: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        if current.len() == k {
+            out.push(current.clone());
+            return;
+        }
+        for i in start..n {
+            current.push(i);
+            rec(i + 1, n, k, current, out);
+            current.pop();
+        }
+    }
+    let mut out = Vec::new();
+    rec(0, n, k, &mut Vec::with_capacity(k), &mut out);
+    out
+}
+
+/// Returns the official fixed start positions of `n` snakes (up to 8).
+///
+/// The corners are used first, then the middle of the edges.
+/// They are ordered in pairs that are opposite to each other,
+/// so that the starts of two, four, six, and eight snakes are symmetric.
+pub fn fair_start_positions(width: usize, height: usize, n: usize) -> Vec<Vec2D> {
+    assert!(n <= 8, "Only up to 8 start positions");
+    let (corners, edges) = start_points(width, height);
+    corners.into_iter().chain(edges).take(n).collect()
+}
+
+/// The corners and the middle of the edges, one cell inside the board.
+fn start_points(width: usize, height: usize) -> ([Vec2D; 4], [Vec2D; 4]) {
+    let (mx, my) = ((width - 2) as i16, (height - 2) as i16);
+    let (cx, cy) = (((width - 1) / 2) as i16, ((height - 1) / 2) as i16);
+    (
+        [v2(1, 1), v2(mx, my), v2(mx, 1), v2(1, my)],
+        [v2(cx, 1), v2(cx, my), v2(1, cy), v2(mx, cy)],
+    )
+}
+
+/// Creates a new game with the snakes on random fair start positions.
+pub fn init_game(width: usize, height: usize, num_agents: usize, rng: &mut SmallRng) -> Game {
+    if width.is_multiple_of(2) || height.is_multiple_of(2) {
+        warn!("If the dimension are even, the initial board configuration is unfair!");
+    }
+    if width != height {
+        warn!("If width != height, the initial board configuration is unfair!");
+    }
+
+    // Like the official rules, either start in the corners or in the middle of the edges
+    let mut start_positions = if num_agents <= 4 && rng.gen() {
+        start_points(width, height).1[..num_agents].to_vec()
+    } else {
+        fair_start_positions(width, height, num_agents)
+    };
+    start_positions.shuffle(rng);
+
+    let snakes = start_positions
+        .into_iter()
+        .map(|p| Snake::new(vec![p; 3].into(), 100))
+        .collect();
+
+    let mut game = Game::new(0, width, height, snakes, &[], &[]);
+
+    // Food at center
+    game.grid[(width / 2, height / 2).into()].t = CellT::Food;
+
+    // Spawn 1 food 2 steps away from each snake
+    let (width, height) = (width as i16, height as i16);
+    for snake in game.snakes.clone() {
+        let diagonals = [v2(-1, -1), v2(-1, 1), v2(1, 1), v2(1, -1)].map(|p| snake.head() + p);
+        let straight = [v2(0, 2), v2(2, 0), v2(0, -2), v2(-2, 0)].map(|p| snake.head() + p);
+        // Only free cells on the board
+        let free = |p: &Vec2D| game.grid.has(*p) && game.grid[*p].t == CellT::Free;
+        // Limit to a border cells (excluding the corners)
+        let border = |p: &Vec2D| (p.x == 0 || p.x == width - 1) ^ (p.y == 0 || p.y == height - 1);
+
+        // Retry with the other offsets if crowded
+        let p = diagonals
+            .into_iter()
+            .filter(free)
+            .filter(border)
+            .choose(rng)
+            .or_else(|| diagonals.into_iter().filter(free).choose(rng))
+            .or_else(|| straight.into_iter().filter(free).choose(rng));
+        if let Some(p) = p {
+            game.grid[p].t = CellT::Food;
+        } else {
+            warn!("No space for the initial food of {:?}", snake.head());
+        }
+    }
+
+    game
+}
+
+#[cfg(test)]
+mod test {
+    use log::info;
+
+    use crate::logging;
+
+    #[tokio::test]
+    async fn tournament() {
+        use super::*;
+        use crate::agents::RandomAgent;
+        logging();
+
+        assert_eq!(combinations(3, 2), [[0, 1], [0, 2], [1, 2]]);
+        assert_eq!(combinations(4, 4).len(), 1);
+
+        let agents = [
+            Agent::Random(RandomAgent { seed: Some(1) }),
+            Agent::Random(RandomAgent { seed: Some(2) }),
+        ];
+        let simulation = Simulation {
+            width: 7,
+            height: 7,
+            ..Default::default()
+        };
+        let run = || async {
+            let mut rng = SmallRng::seed_from_u64(42);
+            simulation.tournament(&agents, 2, 6, 32.0, &mut rng).await
+        };
+        let result = run().await;
+        info!("\n{result}");
+
+        assert_eq!(result.games, [6, 6]);
+        assert_eq!(result.won.iter().sum::<usize>() + result.draws, 6);
+        assert_eq!(result.wins[0][1], result.won[0]);
+        assert_eq!(result.wins[1][0], result.won[1]);
+        assert_eq!(result.wins[0][0], 0);
+
+        // Seeded agents and rules are deterministic
+        assert_eq!(result, run().await);
+    }
+
+    #[test]
+    fn food_spawn() {
+        use super::*;
+        logging();
+
+        let start = Game::parse(
+            r#"
+            . . . . .
+            . 0 . . .
+            . ^ . 1 .
+            . ^ . ^ .
+            . . . ^ ."#,
+        )
+        .unwrap();
+        let heads = [v2(1, 3), v2(3, 2)];
+        let owned: Vec<bool> = start
+            .grid
+            .cells
+            .iter()
+            .map(|c| c.t == CellT::Owned)
+            .collect();
+        let occupied = owned.iter().filter(|&&o| o).count();
+        let food = |game: &Game| {
+            game.grid
+                .cells
+                .iter()
+                .filter(|c| c.t == CellT::Food)
+                .count()
+        };
+
+        for seed in 0..100 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut game = start.clone();
+
+            // Refilled to the minimum
+            assert_eq!(spawn_food(&mut game, 3, 0.0, &mut rng), 3);
+            assert_eq!(food(&game), 3);
+            // There are enough cells away from the heads
+            for &h in &heads {
+                for d in Direction::all() {
+                    let p = h.apply(d);
+                    assert!(!game.grid.has(p) || game.grid[p].t != CellT::Food);
+                }
+            }
+            // Only the chance if the minimum is reached
+            assert_eq!(spawn_food(&mut game, 3, 0.0, &mut rng), 0);
+            assert_eq!(spawn_food(&mut game, 3, 1.0, &mut rng), 1);
+
+            // Never on the snakes, even if the board is full
+            assert_eq!(spawn_food(&mut game, 25, 0.0, &mut rng), 25 - occupied - 4);
+            for (cell, owned) in game.grid.cells.iter().zip(&owned) {
+                assert_eq!(cell.t == CellT::Owned, *owned);
+            }
+            assert_eq!(food(&game), 25 - occupied);
+        }
+        info!("{start:?}");
+    }
+
+    #[test]
+    fn hazard_shrink() {
+        use super::*;
+        logging();
+
+        let hazards = |game: &Game| game.grid.cells.iter().filter(|c| c.hazard > 0).count();
+        let (w, h) = (11, 9);
+
+        // All sides shrink every cycle
+        let mut game = Game::new(0, w, h, Vec::new(), &[], &[]);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut shrink = HazardShrink::new(HazardSchedule::Symmetric, 5);
+        for k in 1..=3 {
+            for turn in (k - 1) * 5 + 1..=k * 5 {
+                shrink.step(&mut game, turn, &mut rng);
+            }
+            assert_eq!(hazards(&game), w * h - (w - 2 * k) * (h - 2 * k));
+        }
+        info!("{:?}", game.grid);
+
+        // One random side every cycle
+        for seed in 0..10 {
+            let mut game = Game::new(0, w, h, Vec::new(), &[], &[]);
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut shrink = HazardShrink::new(HazardSchedule::Royale, 5);
+            for turn in 0..=20 {
+                shrink.step(&mut game, turn, &mut rng);
+            }
+            let [bottom, left, top, right] = shrink.insets;
+            assert_eq!(bottom + left + top + right, 4);
+            let free = (w - left - right) * (h - bottom - top);
+            assert_eq!(hazards(&game), w * h - free);
+        }
+
+        // Stops if the board is covered
+        let mut game = Game::new(0, 3, 3, Vec::new(), &[], &[]);
+        let mut shrink = HazardShrink::new(HazardSchedule::Symmetric, 1);
+        for turn in 1..10 {
+            shrink.step(&mut game, turn, &mut rng);
+        }
+        assert_eq!(hazards(&game), 9);
+
+        assert_eq!("symmetric".parse(), Ok(HazardSchedule::Symmetric));
+        assert!("spiral".parse::<HazardSchedule>().is_err());
+    }
+
+    #[tokio::test]
+    async fn game_stats() {
+        use super::*;
+        use crate::agents::MobilityAgent;
+        logging();
+
+        // Snake 1 is trapped by snake 0
+        let game = Game::parse(
+            r#"
+            1 0 . . .
+            > ^ . . .
+            ^ . . . .
+            . . . . .
+            . . . . ."#,
+        )
+        .unwrap();
+        let agents = [
+            Agent::Mobility(MobilityAgent::default()),
+            Agent::Mobility(MobilityAgent::default()),
+        ]
+        .map(|a| a.instance());
+        let simulation = Simulation {
+            width: 5,
+            height: 5,
+            min_food: 0,
+            food_rate: 0.0,
+            ..Default::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(0);
+
+        let mut stats = Vec::new();
+        stats.push(simulation.play(&agents, &mut game.clone(), &mut rng).await);
+        // Swap the positions of the agents
+        let mut swapped = game.clone();
+        swapped.snakes.reverse();
+        let mut record = simulation.play(&agents, &mut swapped, &mut rng).await;
+        record.players = vec![1, 0];
+        stats.push(record);
+        info!("{stats:?}");
+
+        assert_eq!(stats[0].winner, Some(0));
+        assert_eq!(stats[0].turns, 1);
+        assert_eq!(stats[0].lengths, [4, 3]);
+        assert!(matches!(
+            stats[0].deaths[..],
+            [None, Some(Death::Wall | Death::Collision)]
+        ));
+        assert_eq!(stats[1].winner, Some(1));
+        assert_eq!(stats[1].winning_agent(), Some(0));
+
+        let dir = crate::unique_temp_dir("snork_stats");
+        fs::create_dir_all(&dir).unwrap();
+        write_stats(&dir.join("stats.csv"), &stats).unwrap();
+        write_stats(&dir.join("stats.json"), &stats).unwrap();
+        let csv = fs::read_to_string(dir.join("stats.csv")).unwrap();
+        let json = fs::read_to_string(dir.join("stats.json")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        info!("{csv}");
+        let rows: Vec<Vec<&str>> = csv
+            .lines()
+            .skip(1)
+            .map(|l| l.split(',').collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][..4], ["0", "0;1", "0", "1"]);
+        assert_eq!(rows[1][..4], ["1", "1;0", "0", "1"]);
+
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[0]["winner"], 0);
+        assert_eq!(json[1]["winner"], 1);
+    }
+
+    #[test]
+    fn death_causes() {
+        use super::*;
+        use Direction::*;
+        logging();
+
+        let game = Game::parse(
+            r#"
+            . . . . .
+            . . 0 < <
+            . . . 1 .
+            . . . ^ .
+            . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(Death::of(&game, &[Down, Left], 0), Death::HeadToHead);
+        assert_eq!(Death::of(&game, &[Right, Left], 0), Death::SelfCollision);
+        assert_eq!(Death::of(&game, &[Left, Up], 1), Death::Collision);
+        let mut starving = game.clone();
+        starving.snakes[0].health = 1;
+        assert_eq!(Death::of(&starving, &[Left, Up], 0), Death::Starvation);
+
+        let corner = Game::parse(
+            r#"
+            0 . .
+            ^ . .
+            ^ . ."#,
+        )
+        .unwrap();
+        assert_eq!(Death::of(&corner, &[Up], 0), Death::Wall);
+    }
+
+    #[tokio::test]
+    async fn simulate_mid_game() {
+        use super::*;
+        use crate::agents::MobilityAgent;
+        use crate::savegame;
+        logging();
+
+        let request = |turn: usize| -> GameRequest {
+            serde_json::from_str(&format!(
+                r#"{{"game":{{"id":"mid-game","ruleset":{{"name":"royale"}},"timeout":200}},"turn":{turn},"board":{{"height":7,"width":7,"food":[{{"x":3,"y":3}}],"hazards":[{{"x":0,"y":0}},{{"x":1,"y":0}},{{"x":2,"y":0}},{{"x":3,"y":0}},{{"x":4,"y":0}},{{"x":5,"y":0}},{{"x":6,"y":0}},{{"x":0,"y":1}},{{"x":0,"y":2}},{{"x":0,"y":3}},{{"x":0,"y":4}},{{"x":0,"y":5}},{{"x":0,"y":6}}],"snakes":[{{"id":"a","name":"a","health":90,"body":[{{"x":2,"y":5}},{{"x":2,"y":4}},{{"x":2,"y":3}}]}},{{"id":"b","name":"b","health":90,"body":[{{"x":5,"y":2}},{{"x":5,"y":3}},{{"x":5,"y":4}}]}}]}},"you":{{"id":"a","name":"a","health":90,"body":[{{"x":2,"y":5}},{{"x":2,"y":4}},{{"x":2,"y":3}}]}}}}"#
+            ))
+            .unwrap()
+        };
+
+        // Snapshot from a saved game
+        let dir = crate::unique_temp_dir("snork_mid_game");
+        fs::create_dir_all(&dir).unwrap();
+        for turn in [48, 49, 50, 51] {
+            savegame::save(&dir, &request(turn)).unwrap();
+        }
+        let snapshot = savegame::load_turn(&savegame::path(&dir, "mid-game"), 50);
+        let missing = savegame::load_turn(&savegame::path(&dir, "mid-game"), 60);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(missing.is_err());
+
+        let simulation = Simulation {
+            width: 7,
+            height: 7,
+            shrink_turns: 25,
+            hazards: HazardSchedule::Symmetric,
+            init: Some(snapshot.unwrap()),
+            ..Default::default()
+        };
+        let mut rng = SmallRng::seed_from_u64(0);
+        let game = simulation.new_game(2, &mut rng);
+        assert_eq!(game.turn, 50);
+
+        // The hazards continue from the bottom and left side
+        let mut shrink = HazardShrink::from_game(simulation.hazards, 25, &game);
+        assert_eq!(shrink.insets, [1, 1, 0, 0]);
+        let mut next = game.clone();
+        for turn in 50..75 {
+            shrink.step(&mut next, turn, &mut rng);
+        }
+        assert_eq!(shrink.insets, [2, 2, 1, 1]);
+        let hazards = next.grid.cells.iter().filter(|c| c.hazard > 0).count();
+        assert_eq!(hazards, 7 * 7 - 4 * 4);
+
+        // The turns are counted from the snapshot
+        let agents = [
+            Agent::Mobility(MobilityAgent::default()),
+            Agent::Mobility(MobilityAgent::default()),
+        ]
+        .map(|a| a.instance());
+        let mut game = game;
+        let stats = simulation.play(&agents, &mut game, &mut rng).await;
+        info!("{stats:?} {game:?}");
+        assert!(stats.turns > 50);
+        assert_eq!(stats.turns, game.turn);
+    }
+
+    #[tokio::test]
+    async fn repetition_draw() {
+        use super::*;
+        logging();
+
+        // Both snakes chase their tails without food or hazards
+        let agent: Agent = r#"{"Survival":{"hunger":0}}"#.parse().unwrap();
+        let agents = [agent.instance(), agent.instance()];
+        let simulation = Simulation {
+            width: 7,
+            height: 7,
+            food_rate: 0.0,
+            min_food: 0,
+            hazards: HazardSchedule::None,
+            ..Default::default()
+        };
+        let init = Game::parse(
+            r#"
+            . . . . . . .
+            . > v . . . .
+            . 0 < . . . .
+            . . . . . . .
+            . . . . > v .
+            . . . . 1 < .
+            . . . . . . ."#,
+        )
+        .unwrap();
+        assert_eq!(init.snakes[0].body.len(), 4);
+        assert!(init.can_tail_chase(0).is_some() && init.can_tail_chase(1).is_some());
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut game = init.clone();
+        let stats = simulation.play(&agents, &mut game, &mut rng).await;
+        info!("{stats:?} {game:?}");
+        // The start position repeats every 4 turns
+        assert_eq!(stats.winner, None);
+        assert_eq!(stats.turns, 2 * 4);
+        assert_eq!(stats.deaths, [None, None]);
+        assert_eq!(game.position_hash(), init.position_hash());
+        assert_ne!(game.zobrist_hash(), init.zobrist_hash());
+
+        // Without adjudication the snakes stall until they starve
+        let simulation = Simulation {
+            repetitions: 0,
+            ..simulation
+        };
+        let mut game = init.clone();
+        let stats = simulation.play(&agents, &mut game, &mut rng).await;
+        info!("{stats:?}");
+        assert_eq!(stats.turns, 100);
+
+        // Snake 1 starves first, which is not a draw
+        let simulation = Simulation {
+            repetitions: 3,
+            ..simulation
+        };
+        let mut game = init.clone();
+        game.snakes[1].health = 50;
+        let stats = simulation.play(&agents, &mut game, &mut rng).await;
+        info!("{stats:?}");
+        assert_eq!(stats.winner, Some(0));
+        assert_eq!(stats.turns, 50);
+        assert_eq!(stats.deaths[1], Some(Death::Starvation));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn parallel_games() {
+        use super::*;
+        use crate::agents::{MobilityAgent, RandomAgent};
+        logging();
+
+        let agents = [
+            Agent::Mobility(MobilityAgent::default()),
+            Agent::Random(RandomAgent { seed: Some(3) }),
+        ];
+        let simulation = Simulation {
+            width: 7,
+            height: 7,
+            ..Default::default()
+        };
+
+        let sequential = simulation.run(&agents, 0, 8, 1, 42).await;
+        let parallel = simulation.run(&agents, 0, 8, 4, 42).await;
+        assert_eq!(sequential.len(), 8);
+
+        let wins = |records: &[GameStats]| {
+            let mut wins = [0; 2];
+            for winner in records.iter().filter_map(|r| r.winner) {
+                wins[winner] += 1;
+            }
+            wins
+        };
+        info!("{:?} {:?}", wins(&sequential), wins(&parallel));
+        assert_eq!(wins(&sequential), wins(&parallel));
+        assert_eq!(sequential, parallel);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn many_snakes() {
+        use super::*;
+        use crate::agents::RandomAgent;
+        logging();
+
+        let agents: Vec<Agent> = (0..6)
+            .map(|i| Agent::Random(RandomAgent { seed: Some(i) }))
+            .collect();
+        let simulation = Simulation {
+            width: 11,
+            height: 11,
+            ..Default::default()
+        };
+        let records = simulation.run(&agents, 0, 2, 1, 42).await;
+        assert_eq!(records.len(), 2);
+        for record in &records {
+            info!("{record:?}");
+            assert_eq!(record.players.len(), 6);
+            assert!(record.turns > 0);
+        }
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Every snake requires an agent")]
+    async fn missing_agent() {
+        use super::*;
+        logging();
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut game = init_game(11, 11, 3, &mut rng);
+        let agents = [Agent::default().instance(), Agent::default().instance()];
+        Simulation::default()
+            .play(&agents, &mut game, &mut rng)
+            .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn head_to_head() {
+        use super::*;
+        use crate::agents::{MobilityAgent, RandomAgent};
+        logging();
+
+        // Known values
+        let report = MatchReport {
+            wins: [8, 2],
+            draws: 0,
+        };
+        let (low, high) = report.interval(MatchReport::Z95);
+        assert!((low - 0.4902).abs() < 1e-3 && (high - 0.9433).abs() < 1e-3);
+        assert!(!report.significant(MatchReport::Z95));
+        assert_eq!(
+            MatchReport::default().interval(MatchReport::Z95),
+            (0.0, 1.0)
+        );
+
+        let a = Agent::Mobility(MobilityAgent::default());
+        let b = Agent::Random(RandomAgent { seed: Some(3) });
+        let simulation = Simulation {
+            width: 7,
+            height: 7,
+            ..Default::default()
+        };
+
+        let mut last_width = 1.0;
+        for game_count in [2, 8, 32] {
+            let report = simulation.head_to_head(&a, &b, game_count, 4, 42).await;
+            info!("{report}");
+            assert_eq!(report.games(), 2 * game_count);
+
+            let (low, high) = report.interval(MatchReport::Z95);
+            assert!(low <= report.win_rate() && report.win_rate() <= high);
+            assert!(high - low < last_width);
+            last_width = high - low;
+        }
+    }
+
+    #[test]
+    fn elo() {
+        use super::*;
+        logging();
+
+        let mut elo = Elo::new(4, 32.0);
+        assert_eq!(Elo::expected(1500.0, 1500.0), 0.5);
+
+        // Ratings are zero sum
+        elo.update(&[0, 1], Outcome::Winner(0));
+        assert_eq!(elo.ratings[..2], [1516.0, 1484.0]);
+
+        // 0 > 1 > 2, and 3 only draws
+        let games = [
+            (vec![0, 1], Outcome::Winner(0)),
+            (vec![1, 2], Outcome::Winner(0)),
+            (vec![2, 0], Outcome::Winner(1)),
+            (vec![1, 2], Outcome::Winner(1)),
+            (vec![0, 1, 2, 3], Outcome::Match),
+            (vec![2, 1], Outcome::Winner(1)),
+            (vec![1, 0], Outcome::Winner(1)),
+            (vec![3, 1], Outcome::Match),
+            (vec![1, 2], Outcome::Winner(0)),
+            (vec![2, 1], Outcome::Winner(1)),
+        ];
+        for (players, outcome) in games {
+            elo.update(&players, outcome);
+        }
+        info!("{:?}", elo.ratings);
+        assert!((elo.ratings.iter().sum::<f64>() - 4.0 * Elo::INITIAL).abs() < 1e-6);
+
+        let mut order: Vec<usize> = (0..4).collect();
+        order.sort_by(|&a, &b| elo.ratings[b].total_cmp(&elo.ratings[a]));
+        assert_eq!(order, [0, 1, 3, 2]);
+    }
+
+    #[test]
+    fn fair_start() {
+        use super::*;
+        logging();
+
+        let positions = fair_start_positions(11, 11, 4);
+        info!("{positions:?}");
+        assert_eq!(positions.len(), 4);
+        for n in [2, 4, 6, 8] {
+            let positions = fair_start_positions(11, 11, n);
+            // Symmetric about the center
+            for p in &positions {
+                assert!(positions.contains(&v2(10 - p.x, 10 - p.y)));
+            }
+            assert!(positions.iter().all(|p| p.within(11, 11)));
+        }
+        assert_eq!(fair_start_positions(11, 11, 8).len(), 8);
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let game = init_game(11, 11, 4, &mut rng);
+            let heads: Vec<_> = game.snakes.iter().map(|s| s.head()).collect();
+            for h in &heads {
+                assert!(heads.contains(&v2(10 - h.x, 10 - h.y)));
+            }
+        }
+    }
+
+    #[test]
+    fn initial_food() {
+        use super::*;
+        logging();
+
+        for seed in 0..8 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            for (size, n) in [(11, 4), (11, 8), (7, 8), (19, 6), (7, 2)] {
+                let game = init_game(size, size, n, &mut rng);
+                info!("{seed} {n} {game:?}");
+                let food: Vec<_> = (0..size as i16)
+                    .flat_map(|y| (0..size as i16).map(move |x| v2(x, y)))
+                    .filter(|&p| game.grid[p].t == CellT::Food)
+                    .collect();
+                // One for each snake and the center
+                assert_eq!(food.len(), n + 1);
+                for snake in &game.snakes {
+                    let head = snake.head();
+                    assert!(
+                        food.iter().any(|&p| (p - head).manhattan() <= 2),
+                        "no food next to {head:?}"
+                    );
+                }
+            }
+        }
+    }
+}
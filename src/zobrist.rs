@@ -0,0 +1,101 @@
+//! # Zobrist Hashing
+//!
+//! Random keys for every feature of a game state, that are xor-ed together
+//! to create the hash of a position.
+//!
+//! @see https://en.wikipedia.org/wiki/Zobrist_hashing
+
+use std::sync::OnceLock;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+/// Number of cells that are covered by the precomputed keys.
+const MAX_CELLS: usize = 25 * 25;
+/// Number of snakes that are covered by the precomputed keys.
+const MAX_SNAKES: usize = 8;
+/// Body parts: the direction to the next segment, the head, and stacked segments.
+pub const PARTS: usize = 6;
+pub const PART_HEAD: usize = 4;
+pub const PART_STACKED: usize = 5;
+const HEALTHS: usize = u8::MAX as usize + 1;
+
+struct Keys {
+    food: Vec<u64>,
+    hazard: Vec<u64>,
+    body: Vec<u64>,
+    health: Vec<u64>,
+    side: Vec<u64>,
+}
+
+fn keys() -> &'static Keys {
+    static KEYS: OnceLock<Keys> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        let mut rng = SmallRng::seed_from_u64(0x5eed);
+        let mut gen = |n: usize| (0..n).map(|_| rng.gen()).collect::<Vec<u64>>();
+        Keys {
+            food: gen(MAX_CELLS),
+            hazard: gen(MAX_CELLS),
+            body: gen(MAX_SNAKES * PARTS * MAX_CELLS),
+            health: gen(MAX_SNAKES * HEALTHS),
+            side: gen(MAX_SNAKES),
+        }
+    })
+}
+
+/// Fallback for features that are not covered by the precomputed keys.
+/// (splitmix64 of the feature index)
+fn mix(domain: u64, i: usize) -> u64 {
+    let mut z = (domain << 56 ^ i as u64).wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Key for food on the cell with the given index.
+pub fn food(cell: usize) -> u64 {
+    keys()
+        .food
+        .get(cell)
+        .copied()
+        .unwrap_or_else(|| mix(1, cell))
+}
+
+/// Key for a hazard on the cell with the given index.
+pub fn hazard(cell: usize) -> u64 {
+    keys()
+        .hazard
+        .get(cell)
+        .copied()
+        .unwrap_or_else(|| mix(2, cell))
+}
+
+/// Key for a body `part` of a snake on the cell with the given index.
+pub fn body(snake: usize, part: usize, cell: usize) -> u64 {
+    debug_assert!(part < PARTS);
+    if snake < MAX_SNAKES && cell < MAX_CELLS {
+        keys().body[(snake * PARTS + part) * MAX_CELLS + cell]
+    } else {
+        // Snake ids are bytes
+        mix(8 + part as u64, cell << 8 | snake)
+    }
+}
+
+/// Key for the health of a snake.
+pub fn health(snake: usize, health: u8) -> u64 {
+    let i = snake * HEALTHS + health as usize;
+    keys().health.get(i).copied().unwrap_or_else(|| mix(4, i))
+}
+
+/// Key for the turn, which is not part of the position itself.
+pub fn turn(turn: usize) -> u64 {
+    mix(6, turn)
+}
+
+/// Key for the snake that moves next, used by the tree searches.
+pub fn side(snake: usize) -> u64 {
+    keys()
+        .side
+        .get(snake)
+        .copied()
+        .unwrap_or_else(|| mix(5, snake))
+}